@@ -0,0 +1,173 @@
+use std::str::FromStr;
+
+use anyhow::{anyhow, Result};
+use cgmath::Point2;
+use dashu_float::{ops::EstimatedLog2, round::mode::HalfAway, DBig, FBig};
+
+/// The number of significant decimal digits needed to address a pixel at the given zoom, with
+/// some headroom so that panning doesn't accumulate visible error.
+pub fn digits_for_zoom(zoom: f32) -> usize {
+    (zoom.max(0.0) / std::f32::consts::LN_10) as usize + 20
+}
+
+/// Raises the precision of `value` to at least `digits`. Precision is never lowered, so exact
+/// values such as a parsed location are kept exact.
+pub fn with_digits(value: DBig, digits: usize) -> DBig {
+    if value.precision() < digits {
+        value.with_precision(digits).value()
+    } else {
+        value
+    }
+}
+
+/// Rounds to the nearest decimal of `digits`, which gives back the same f64 from 17 on.
+pub fn from_f64(value: f64, digits: usize) -> DBig {
+    let value: FBig = FBig::try_from(value).unwrap_or(FBig::ZERO);
+    from_binary(&value, digits)
+}
+
+/// Converts to binary with `bits` of precision, which is much faster to calculate with.
+pub fn to_binary(value: &DBig, bits: usize) -> FBig {
+    value
+        .clone()
+        .with_base_and_precision::<2>(bits)
+        .value()
+        .with_rounding()
+}
+
+pub fn binary_from_f64(value: f64, bits: usize) -> FBig {
+    FBig::try_from(value)
+        .unwrap_or(FBig::ZERO)
+        .with_precision(bits)
+        .value()
+}
+
+/// The rounding mode is switched first, since the conversion rounds with it.
+pub fn from_binary(value: &FBig, digits: usize) -> DBig {
+    value
+        .clone()
+        .with_rounding::<HalfAway>()
+        .with_base_and_precision::<10>(digits)
+        .value()
+}
+
+/// Formats a positive value like `{:e}`, including exponents beyond the range of f64.
+pub fn format_scientific(value: &FBig) -> String {
+    let log10 = value.log2_est() as f64 * std::f64::consts::LOG10_2;
+    let exponent = log10.floor();
+    format!("{:.3}e{}", 10f64.powf(log10 - exponent), exponent)
+}
+
+pub fn parse(value: &str) -> Result<DBig> {
+    DBig::from_str(value).map_err(|e| anyhow!("invalid number {:?}: {}", value, e))
+}
+
+/// A point on the complex plane stored as exact decimals.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BigPoint {
+    pub x: DBig,
+    pub y: DBig,
+}
+
+impl BigPoint {
+    pub fn from_f64(point: Point2<f64>, digits: usize) -> Self {
+        Self {
+            x: from_f64(point.x, digits),
+            y: from_f64(point.y, digits),
+        }
+    }
+
+    pub fn to_f64(&self) -> Point2<f64> {
+        Point2::new(self.x.to_f64().value(), self.y.to_f64().value())
+    }
+
+    /// `self - origin`, which is small enough near the origin to be represented in f64.
+    pub fn offset_from(&self, origin: &BigPoint) -> Point2<f64> {
+        Point2::new(
+            (&self.x - &origin.x).to_f64().value(),
+            (&self.y - &origin.y).to_f64().value(),
+        )
+    }
+
+    pub fn translate(&mut self, offset: Point2<f64>, digits: usize) {
+        let x = with_digits(self.x.clone(), digits);
+        let y = with_digits(self.y.clone(), digits);
+        self.x = x + from_f64(offset.x, digits);
+        self.y = y + from_f64(offset.y, digits);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// More significant digits than an f64 holds, near a well known deep zoom.
+    const DEEP: &str = "-0.743643887037158704752191506114774";
+
+    #[test]
+    fn digits_grow_with_the_zoom() {
+        assert_eq!(digits_for_zoom(0.0), 20);
+        assert_eq!(digits_for_zoom(-5.0), 20);
+        // Each factor of 10 in magnification needs another digit
+        assert_eq!(digits_for_zoom(23.0), 29);
+        assert_eq!(digits_for_zoom(100.0 * std::f32::consts::LN_10 + 0.1), 120);
+    }
+
+    #[test]
+    fn binary_round_trips_keep_every_digit() {
+        let value = parse(DEEP).unwrap();
+        // 34 digits need 113 bits, so this leaves some to spare
+        let binary = to_binary(&value, 128);
+        assert_eq!(binary.precision(), 128);
+        assert_eq!(from_binary(&binary, 34), value);
+        assert_eq!(from_binary(&binary, 34).to_string(), DEEP);
+        // Too few bits lose the digits beyond an f64
+        assert_ne!(from_binary(&to_binary(&value, 53), 34), value);
+    }
+
+    #[test]
+    fn f64s_convert_exactly() {
+        // 0.1 is 0.1000000000000000055511151231257827... as an f64
+        assert_eq!(
+            from_f64(0.1, 30),
+            parse("0.100000000000000005551115123126").unwrap()
+        );
+        assert_eq!(
+            from_binary(&binary_from_f64(0.1, 64), 20),
+            parse("0.10000000000000000555").unwrap()
+        );
+        assert_eq!(from_f64(f64::NAN, 20), DBig::ZERO);
+    }
+
+    #[test]
+    fn precision_is_only_raised() {
+        let value = parse(DEEP).unwrap();
+        assert_eq!(
+            with_digits(value.clone(), 20).precision(),
+            value.precision()
+        );
+        let raised = with_digits(value.clone(), 50);
+        assert_eq!(raised.precision(), 50);
+        assert_eq!(raised, value);
+    }
+
+    #[test]
+    fn scientific_notation_beyond_f64() {
+        let value = to_binary(&parse("2.5e-400").unwrap(), 64);
+        assert_eq!(format_scientific(&value), "2.500e-400");
+        assert_eq!(format_scientific(&binary_from_f64(1234.0, 64)), "1.234e3");
+    }
+
+    #[test]
+    fn offsets_are_taken_in_full_precision() {
+        let mut point = BigPoint {
+            x: parse(DEEP).unwrap(),
+            y: parse("0.131825904205311970493132056385139").unwrap(),
+        };
+        let origin = point.clone();
+        point.translate(Point2::new(1e-30, -2e-30), 40);
+        let offset = point.offset_from(&origin);
+        assert!((offset.x - 1e-30).abs() < 1e-45);
+        assert!((offset.y + 2e-30).abs() < 1e-45);
+    }
+}
@@ -0,0 +1,82 @@
+use anyhow::{anyhow, Result};
+use winit::dpi::PhysicalSize;
+
+use crate::histogram::{self, HistogramPass};
+use crate::precision::Precision;
+
+/// Settings for the Buddhabrot, which plots the density of orbits rather than colouring the
+/// points they start from. Each colour channel has its own iteration limit, giving the
+/// "Nebulabrot" when they differ.
+#[derive(Debug)]
+pub struct Buddhabrot {
+    pub limits: [u32; 3],
+    /// Plots the orbits that stay bounded instead of the ones that escape.
+    pub anti: bool,
+}
+
+impl Buddhabrot {
+    pub fn new() -> Self {
+        Self {
+            limits: [2000, 200, 20],
+            anti: false,
+        }
+    }
+
+    pub fn set_limits(&mut self, arguments: &str) -> Result<()> {
+        let limits = arguments
+            .split_whitespace()
+            .map(str::parse)
+            .collect::<Result<Vec<u32>, _>>()
+            .map_err(|_| anyhow!("invalid iteration limits {:?}", arguments))?;
+        self.limits = match limits[..] {
+            [limit] => [limit; 3],
+            [red, green, blue] => [red, green, blue],
+            _ => {
+                return Err(anyhow!(
+                    "expected one limit, or one for each of red, green and blue"
+                ))
+            }
+        };
+        Ok(())
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct BuddhabrotUniform {
+    pub limits: [u32; 3],
+    pub anti: u32,
+    pub width: u32,
+    pub height: u32,
+    pub _padding: [u32; 2],
+}
+
+impl BuddhabrotUniform {
+    pub fn update(&mut self, buddhabrot: &Buddhabrot, size: PhysicalSize<u32>) {
+        self.limits = buddhabrot.limits;
+        self.anti = buddhabrot.anti as u32;
+        self.width = size.width;
+        self.height = size.height;
+    }
+}
+
+pub type BuddhabrotPass = HistogramPass<BuddhabrotUniform>;
+
+/// The compute pass that accumulates orbits into a histogram, which `fs_main` then tone maps.
+pub fn create_pass(
+    device: &wgpu::Device,
+    camera_bind_group_layout: &wgpu::BindGroupLayout,
+    size: PhysicalSize<u32>,
+    precision: Precision,
+    compute_shaders: bool,
+) -> BuddhabrotPass {
+    HistogramPass::new(
+        device,
+        "buddhabrot",
+        precision.shader_source(&histogram::shader_source(include_str!("buddhabrot.wgsl"))),
+        3,
+        &[camera_bind_group_layout],
+        size,
+        compute_shaders,
+    )
+}
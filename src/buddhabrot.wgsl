@@ -0,0 +1,80 @@
+struct BuddhabrotUniform {
+    limits: vec3<u32>,
+    anti: u32,
+    width: u32,
+    height: u32,
+};
+@group(0)
+@binding(0)
+var<uniform> buddhabrot: BuddhabrotUniform;
+// The largest count of each channel, a padding word, then the red, green and blue counts of
+// every pixel
+@group(0)
+@binding(1)
+var<storage, read_write> histogram: array<atomic<u32>>;
+
+// Counts stop at half of what fits in a u32, far more than one frame can add, so long runs
+// never wrap around.
+const MAX_PIXEL_COUNT: u32 = 2147483648u;
+
+fn plot(z: vec2<f32>, channels: vec3<bool>) {
+    let view = vec2<f32>((vec2<real>(z) - unpack2(camera.pos)) / unpack(camera.scale));
+    let position = (vec2<f32>(view.x / camera.aspect, view.y) + 1.0) * 0.5
+        * vec2<f32>(f32(buddhabrot.width), f32(buddhabrot.height));
+    if any(position < vec2<f32>(0.0)) || position.x >= f32(buddhabrot.width) || position.y >= f32(buddhabrot.height) {
+        return;
+    }
+
+    let index = HISTOGRAM_HEADER + (u32(position.y) * buddhabrot.width + u32(position.x)) * 3u;
+    for (var channel = 0u; channel < 3u; channel++) {
+        if channels[channel] && atomicLoad(&histogram[index + channel]) < MAX_PIXEL_COUNT {
+            let count = atomicAdd(&histogram[index + channel], 1u) + 1u;
+            atomicMax(&histogram[channel], count);
+        }
+    }
+}
+
+fn compute_next(z: vec2<f32>, c: vec2<f32>) -> vec2<f32> {
+    return vec2<f32>(z.x * z.x - z.y * z.y, 2.0 * z.x * z.y) + c;
+}
+
+@compute
+@workgroup_size(64)
+fn cs_main(@builtin(global_invocation_id) id: vec3<u32>) {
+    var seed = hash(id.x ^ hash(batch));
+    let c = vec2<f32>(random(&seed), random(&seed)) * 4.0 - 2.0;
+    let limits = buddhabrot.limits;
+    let max_limit = max(limits.x, max(limits.y, limits.z));
+
+    // Find out when, if ever, the orbit escapes before plotting it
+    var z = vec2<f32>(0.0, 0.0);
+    var escape = 0u;
+    while escape < max_limit && dot(z, z) <= 4.0 {
+        z = compute_next(z, c);
+        escape += 1u;
+    }
+    if dot(z, z) <= 4.0 {
+        escape = max_limit + 1u;
+    }
+
+    // A channel plots the orbit if it escaped within that channel's limit, or for the
+    // Anti-Buddhabrot, if it didn't. Bounded orbits are plotted up to the channel's limit.
+    let escaped = vec3<u32>(escape) <= limits;
+    var channels = escaped;
+    var lengths = vec3<u32>(escape);
+    if buddhabrot.anti != 0u {
+        channels = !escaped;
+        lengths = limits;
+    }
+    if !any(channels) {
+        return;
+    }
+
+    z = vec2<f32>(0.0, 0.0);
+    let plotted = select(vec3<u32>(0u), lengths, channels);
+    let orbit_length = max(plotted.x, max(plotted.y, plotted.z));
+    for (var i = 0u; i < orbit_length; i++) {
+        z = compute_next(z, c);
+        plot(z, channels && vec3<u32>(i) < lengths);
+    }
+}
@@ -1,31 +1,185 @@
 use std::time::Duration;
 
-use cgmath::Point2;
+use anyhow::{anyhow, Result};
+use cgmath::{InnerSpace, Point2, Point3, Vector3};
 use std::ops::*;
 use winit::{
+    dpi::{PhysicalPosition, PhysicalSize},
     event::{ElementState, MouseScrollDelta},
     keyboard::KeyCode,
 };
 
+use crate::big::{self, BigPoint};
+use crate::fractal::Fractal;
+
 #[derive(Debug)]
 pub struct Camera {
-    position: Point2<f64>,
-    position_target: Point2<f64>,
+    position: BigPoint,
+    position_target: BigPoint,
     zoom: f32,
     zoom_target: f32,
     pub aspect: f32,
 }
 
 impl Camera {
-    pub fn new(position: impl Into<Point2<f64>> + Clone, zoom: f32, aspect: f32) -> Self {
+    pub fn new(position: impl Into<Point2<f64>>, zoom: f32, aspect: f32) -> Self {
+        let position = BigPoint::from_f64(position.into(), big::digits_for_zoom(zoom));
         Self {
-            position: position.clone().into(),
-            position_target: position.into(),
+            position: position.clone(),
+            position_target: position,
             zoom,
             zoom_target: zoom,
             aspect,
         }
     }
+
+    pub fn position(&self) -> &BigPoint {
+        &self.position
+    }
+
+    /// The location the camera is moving towards as `"<re> <im> <zoom>"`, with the centre
+    /// written out as exact decimals.
+    pub fn location(&self) -> String {
+        format!(
+            "{} {} {}",
+            self.position_target.x, self.position_target.y, self.zoom_target
+        )
+    }
+
+    /// Jumps to a location in the format written by [`Camera::location`]. The zoom may be left
+    /// out to keep the current one.
+    pub fn set_location(&mut self, location: &str) -> Result<()> {
+        let parts = location.split_whitespace().collect::<Vec<_>>();
+        let (x, y, zoom) = match parts[..] {
+            [x, y] => (x, y, None),
+            [x, y, zoom] => (x, y, Some(zoom)),
+            _ => return Err(anyhow!("expected \"<re> <im> [zoom]\", got {:?}", location)),
+        };
+        let zoom = match zoom {
+            Some(zoom) => zoom
+                .parse::<f32>()
+                .map_err(|e| anyhow!("invalid zoom {:?}: {}", zoom, e))?,
+            None => self.zoom_target,
+        };
+
+        self.position_target = BigPoint {
+            x: big::parse(x)?,
+            y: big::parse(y)?,
+        };
+        self.position = self.position_target.clone();
+        self.zoom_target = zoom;
+        self.zoom = zoom;
+        Ok(())
+    }
+
+    /// Jumps straight to a position and zoom without moving there smoothly.
+    pub fn jump(&mut self, position: impl Into<Point2<f64>>, zoom: f32) {
+        self.position_target = BigPoint::from_f64(position.into(), big::digits_for_zoom(zoom));
+        self.position = self.position_target.clone();
+        self.zoom_target = zoom;
+        self.zoom = zoom;
+    }
+
+    /// Moves smoothly to a position and zoom, by way of the targets that
+    /// [`CameraController::update_camera`] follows.
+    pub fn fly_to(&mut self, position: BigPoint, zoom: f32) {
+        self.position_target = position;
+        self.zoom_target = zoom;
+    }
+
+    /// Pulls the zoom back to `max_zoom`, returning whether it was beyond it.
+    pub fn limit_zoom(&mut self, max_zoom: f32) -> bool {
+        let limited = self.zoom_target > max_zoom;
+        self.zoom_target = self.zoom_target.min(max_zoom);
+        self.zoom = self.zoom.min(max_zoom);
+        limited
+    }
+
+    pub fn zoom(&self) -> f32 {
+        self.zoom
+    }
+
+    /// Maps a position in window pixels onto the complex plane, matching `vs_main`.
+    pub fn screen_to_world(
+        &self,
+        position: PhysicalPosition<f64>,
+        size: PhysicalSize<u32>,
+    ) -> Point2<f64> {
+        let offset = self.screen_offset(position, size);
+        let position = self.position.to_f64();
+        Point2::new(offset.x + position.x, offset.y + position.y)
+    }
+
+    /// [`Camera::screen_to_world`] in full precision, for deep zooms.
+    pub fn screen_to_world_big(
+        &self,
+        position: PhysicalPosition<f64>,
+        size: PhysicalSize<u32>,
+    ) -> BigPoint {
+        let mut world = self.position.clone();
+        world.translate(
+            self.screen_offset(position, size),
+            big::digits_for_zoom(self.zoom),
+        );
+        world
+    }
+
+    /// The offset of a position in window pixels from the centre on the complex plane.
+    fn screen_offset(
+        &self,
+        position: PhysicalPosition<f64>,
+        size: PhysicalSize<u32>,
+    ) -> Point2<f64> {
+        let x = (2.0 * position.x / size.width as f64 - 1.0) * self.aspect as f64;
+        let y = 2.0 * position.y / size.height as f64 - 1.0;
+        let scale = (-self.zoom as f64).exp();
+        Point2::new(x * scale, y * scale)
+    }
+}
+
+/// A free flying camera for the ray marched modes, looking along the direction given by its yaw
+/// and pitch.
+#[derive(Debug)]
+pub struct Camera3d {
+    position: Point3<f32>,
+    position_target: Point3<f32>,
+    yaw: f32,
+    yaw_target: f32,
+    pitch: f32,
+    pitch_target: f32,
+    /// Distance from the eye to a screen of height 2, which sets the field of view.
+    pub focal_length: f32,
+}
+
+impl Camera3d {
+    pub fn new(position: impl Into<Point3<f32>>, yaw: f32, pitch: f32) -> Self {
+        let position = position.into();
+        Self {
+            position,
+            position_target: position,
+            yaw,
+            yaw_target: yaw,
+            pitch,
+            pitch_target: pitch,
+            focal_length: 1.5,
+        }
+    }
+
+    pub fn forward(&self) -> Vector3<f32> {
+        Vector3::new(
+            self.pitch.cos() * self.yaw.sin(),
+            self.pitch.sin(),
+            self.pitch.cos() * self.yaw.cos(),
+        )
+    }
+
+    fn forward_target(&self) -> Vector3<f32> {
+        Vector3::new(
+            self.pitch_target.cos() * self.yaw_target.sin(),
+            self.pitch_target.sin(),
+            self.pitch_target.cos() * self.yaw_target.cos(),
+        )
+    }
 }
 
 fn lerp<T, F>(start: T, end: T, percent: F) -> T
@@ -43,6 +197,10 @@ pub struct CameraController {
     amount_down: f32,
     amount_in: f32,
     amount_out: f32,
+    amount_real_up: f32,
+    amount_real_down: f32,
+    amount_imag_up: f32,
+    amount_imag_down: f32,
     speed: f32,
 }
 
@@ -55,6 +213,10 @@ impl CameraController {
             amount_down: 0.0,
             amount_in: 0.0,
             amount_out: 0.0,
+            amount_real_up: 0.0,
+            amount_real_down: 0.0,
+            amount_imag_up: 0.0,
+            amount_imag_down: 0.0,
             speed,
         }
     }
@@ -91,6 +253,22 @@ impl CameraController {
                 self.amount_in = amount;
                 true
             }
+            KeyCode::KeyO => {
+                self.amount_real_up = amount;
+                true
+            }
+            KeyCode::KeyU => {
+                self.amount_real_down = amount;
+                true
+            }
+            KeyCode::KeyI => {
+                self.amount_imag_up = amount;
+                true
+            }
+            KeyCode::KeyK => {
+                self.amount_imag_down = amount;
+                true
+            }
             KeyCode::Equal => {
                 self.speed *= 1.2;
                 true
@@ -107,26 +285,69 @@ impl CameraController {
 
     pub fn update_camera(&mut self, camera: &mut Camera, dt: Duration) {
         let dt = dt.as_secs_f32();
-        camera.position_target.x +=
-            ((self.amount_right - self.amount_left) * self.speed * (-camera.zoom).exp() * dt)
-                as f64;
-        camera.position_target.y +=
-            ((self.amount_down - self.amount_up) * self.speed * (-camera.zoom).exp() * dt) as f64;
-        camera.position.x = lerp(camera.position.x, camera.position_target.x, 5.0 * dt as f64);
-        camera.position.y = lerp(camera.position.y, camera.position_target.y, 5.0 * dt as f64);
+        let digits = big::digits_for_zoom(camera.zoom);
+        let step = (self.speed * dt) as f64 * (-camera.zoom as f64).exp();
+        let movement = Point2::new(
+            (self.amount_right - self.amount_left) as f64 * step,
+            (self.amount_down - self.amount_up) as f64 * step,
+        );
+        if movement != Point2::new(0.0, 0.0) {
+            camera.position_target.translate(movement, digits);
+        }
+        if camera.position != camera.position_target {
+            let percent = big::from_f64(5.0 * dt as f64, digits);
+            let position = &mut camera.position;
+            position.x = lerp(
+                big::with_digits(position.x.clone(), digits),
+                camera.position_target.x.clone(),
+                percent.clone(),
+            );
+            position.y = lerp(
+                big::with_digits(position.y.clone(), digits),
+                camera.position_target.y.clone(),
+                percent,
+            );
+        }
 
         camera.zoom_target += (self.amount_in - self.amount_out) * self.speed * 0.5 * dt;
         camera.zoom = lerp(camera.zoom, camera.zoom_target, 5.0 * dt);
     }
+
+    /// Turns with the arrow keys and flies forwards and backwards with the zoom keys.
+    pub fn update_camera_3d(&mut self, camera: &mut Camera3d, dt: Duration) {
+        let dt = dt.as_secs_f32();
+        let max_pitch = std::f32::consts::FRAC_PI_2 * 0.99;
+        camera.yaw_target += (self.amount_right - self.amount_left) * dt;
+        camera.pitch_target += (self.amount_up - self.amount_down) * dt;
+        camera.pitch_target = camera.pitch_target.clamp(-max_pitch, max_pitch);
+        camera.position_target +=
+            camera.forward_target() * (self.amount_in - self.amount_out) * self.speed * 0.5 * dt;
+
+        camera.yaw = lerp(camera.yaw, camera.yaw_target, 5.0 * dt);
+        camera.pitch = lerp(camera.pitch, camera.pitch_target, 5.0 * dt);
+        camera.position = camera.position + (camera.position_target - camera.position) * 5.0 * dt;
+    }
+
+    /// Moves the Julia constant, slowing down as the camera zooms in so that small changes can
+    /// be made at depth.
+    pub fn update_fractal(&mut self, fractal: &mut Fractal, camera: &Camera, dt: Duration) {
+        let dt = dt.as_secs_f32();
+        let step = (self.speed * 0.1 * dt) as f64 * (-camera.zoom as f64).exp();
+        fractal.julia_constant.x += (self.amount_real_up - self.amount_real_down) as f64 * step;
+        fractal.julia_constant.y += (self.amount_imag_up - self.amount_imag_down) as f64 * step;
+    }
 }
 
 #[repr(C)]
-#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+#[derive(Debug, Copy, Clone, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
 pub struct CameraUniform {
     pub pos: [f64; 2],
     pub zoom: f32,
     pub aspect: f32,
-    pub _padding: f64,
+    /// `exp(-zoom)` computed in f64, since the shader's f32 `exp` underflows at deep zooms.
+    pub scale: f64,
+    /// The centre relative to the reference orbit when rendering by perturbation.
+    pub offset: [f64; 2],
 }
 
 impl CameraUniform {
@@ -135,13 +356,96 @@ impl CameraUniform {
             pos: [0.0; 2],
             zoom: 0.0,
             aspect: 1.0,
-            _padding: 0.0,
+            scale: 1.0,
+            offset: [0.0; 2],
         }
     }
 
-    pub fn update(&mut self, camera: &Camera) {
-        self.pos = camera.position.into();
+    pub fn update(&mut self, camera: &Camera, reference: Option<&BigPoint>) {
+        self.pos = camera.position.to_f64().into();
+        self.offset = reference.map_or([0.0; 2], |reference| {
+            camera.position.offset_from(reference).into()
+        });
         self.zoom = camera.zoom;
         self.aspect = camera.aspect;
+        self.scale = (-camera.zoom as f64).exp();
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct Camera3dUniform {
+    pub position: [f32; 3],
+    pub focal_length: f32,
+    pub forward: [f32; 3],
+    pub _padding0: f32,
+    pub right: [f32; 3],
+    pub _padding1: f32,
+    pub up: [f32; 3],
+    pub _padding2: f32,
+}
+
+impl Camera3dUniform {
+    pub fn new() -> Self {
+        Self {
+            position: [0.0; 3],
+            focal_length: 1.0,
+            forward: [0.0, 0.0, 1.0],
+            _padding0: 0.0,
+            right: [1.0, 0.0, 0.0],
+            _padding1: 0.0,
+            up: [0.0, 1.0, 0.0],
+            _padding2: 0.0,
+        }
+    }
+
+    pub fn update(&mut self, camera: &Camera3d) {
+        let forward = camera.forward();
+        let right = Vector3::unit_y().cross(forward).normalize();
+        let up = forward.cross(right);
+        self.position = camera.position.into();
+        self.focal_length = camera.focal_length;
+        self.forward = forward.into();
+        self.right = right.into();
+        self.up = up.into();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn locations_round_trip_exactly() {
+        let location =
+            "-1.749999999999999999999999999987654321 0.00000000000000000000000000123456789 62.5";
+        let mut camera = Camera::new((0.0, 0.0), 0.0, 1.0);
+        camera.set_location(location).unwrap();
+        assert_eq!(camera.location(), location);
+        assert_eq!(camera.zoom(), 62.5);
+
+        let mut copy = Camera::new((1.0, 1.0), 3.0, 1.0);
+        copy.set_location(&camera.location()).unwrap();
+        assert_eq!(copy.position(), camera.position());
+    }
+
+    #[test]
+    fn the_zoom_can_be_left_out() {
+        let mut camera = Camera::new((0.0, 0.0), 7.0, 1.0);
+        camera.set_location("0.25 -0.5").unwrap();
+        assert_eq!(camera.location(), "0.25 -0.5 7");
+        assert!(camera.set_location("0.25").is_err());
+        assert!(camera.set_location("0.25 x").is_err());
+        assert!(camera.set_location("0.25 0 deep").is_err());
+        assert!(camera.set_location("1 2 3 4").is_err());
+    }
+
+    #[test]
+    fn new_cameras_hold_their_f64_position_exactly() {
+        let camera = Camera::new((0.1, -0.3), 0.0, 1.0);
+        assert_eq!(camera.position().to_f64(), Point2::new(0.1, -0.3));
+        let mut copy = Camera::new((0.0, 0.0), 0.0, 1.0);
+        copy.set_location(&camera.location()).unwrap();
+        assert_eq!(copy.position().to_f64(), Point2::new(0.1, -0.3));
     }
 }
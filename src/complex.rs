@@ -0,0 +1,151 @@
+use std::fmt;
+use std::ops::*;
+use std::str::FromStr;
+
+use anyhow::{anyhow, Error};
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Complex {
+    pub re: f64,
+    pub im: f64,
+}
+
+impl Complex {
+    pub const ZERO: Complex = Complex::new(0.0, 0.0);
+    pub const ONE: Complex = Complex::new(1.0, 0.0);
+
+    pub const fn new(re: f64, im: f64) -> Self {
+        Self { re, im }
+    }
+
+    pub fn norm_sqr(self) -> f64 {
+        self.re * self.re + self.im * self.im
+    }
+}
+
+impl From<Complex> for [f64; 2] {
+    fn from(value: Complex) -> Self {
+        [value.re, value.im]
+    }
+}
+
+impl Add for Complex {
+    type Output = Complex;
+
+    fn add(self, rhs: Complex) -> Complex {
+        Complex::new(self.re + rhs.re, self.im + rhs.im)
+    }
+}
+
+impl Sub for Complex {
+    type Output = Complex;
+
+    fn sub(self, rhs: Complex) -> Complex {
+        Complex::new(self.re - rhs.re, self.im - rhs.im)
+    }
+}
+
+impl Mul for Complex {
+    type Output = Complex;
+
+    fn mul(self, rhs: Complex) -> Complex {
+        Complex::new(
+            self.re * rhs.re - self.im * rhs.im,
+            self.re * rhs.im + self.im * rhs.re,
+        )
+    }
+}
+
+impl Mul<f64> for Complex {
+    type Output = Complex;
+
+    fn mul(self, rhs: f64) -> Complex {
+        Complex::new(self.re * rhs, self.im * rhs)
+    }
+}
+
+impl Div for Complex {
+    type Output = Complex;
+
+    fn div(self, rhs: Complex) -> Complex {
+        let denominator = rhs.norm_sqr();
+        Complex::new(
+            (self.re * rhs.re + self.im * rhs.im) / denominator,
+            (self.im * rhs.re - self.re * rhs.im) / denominator,
+        )
+    }
+}
+
+impl fmt::Display for Complex {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{:+}i", self.re, self.im)
+    }
+}
+
+/// Parses numbers written as `1`, `-2.5i`, `i`, `0.5-0.866i` or `1e-3+2e-4i`.
+impl FromStr for Complex {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || anyhow!("invalid complex number {:?}", s);
+        let parse = |part: &str| part.parse::<f64>().map_err(|_| invalid());
+        let text = s.replace(' ', "");
+
+        let Some(text) = text.strip_suffix('i') else {
+            return Ok(Complex::new(parse(&text)?, 0.0));
+        };
+        // The sign separating the parts, skipping a leading sign and exponent signs
+        let split = text
+            .char_indices()
+            .skip(1)
+            .filter(|&(i, c)| (c == '+' || c == '-') && !text[..i].ends_with(['e', 'E']))
+            .last()
+            .map(|(i, _)| i);
+        let (re, im) = match split {
+            Some(i) => (parse(&text[..i])?, &text[i..]),
+            None => (0.0, text),
+        };
+        let im = match im {
+            "" | "+" => 1.0,
+            "-" => -1.0,
+            im => parse(im)?,
+        };
+        Ok(Complex::new(re, im))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(text: &str) -> Complex {
+        text.parse().unwrap()
+    }
+
+    #[test]
+    fn parses_every_form() {
+        assert_eq!(parse("1"), Complex::new(1.0, 0.0));
+        assert_eq!(parse("-2.5i"), Complex::new(0.0, -2.5));
+        assert_eq!(parse("i"), Complex::new(0.0, 1.0));
+        assert_eq!(parse("-i"), Complex::new(0.0, -1.0));
+        assert_eq!(parse("2+i"), Complex::new(2.0, 1.0));
+        assert_eq!(parse("0.5-0.866i"), Complex::new(0.5, -0.866));
+        assert_eq!(parse("-0.5 - 0.866i"), Complex::new(-0.5, -0.866));
+        assert_eq!(parse("1e-3+2e-4i"), Complex::new(1e-3, 2e-4));
+        assert_eq!(parse("-1E+2-3e-1i"), Complex::new(-100.0, -0.3));
+    }
+
+    #[test]
+    fn rejects_malformed_numbers() {
+        for text in ["", "x", "1+", "1+2j", "ii", "1+2i+3i"] {
+            assert!(text.parse::<Complex>().is_err(), "{:?}", text);
+        }
+    }
+
+    #[test]
+    fn displays_what_it_parses() {
+        let value = Complex::new(0.25, -3.0);
+        assert_eq!(value.to_string(), "0.25-3i");
+        assert_eq!(parse(&value.to_string()), value);
+    }
+}
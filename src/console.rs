@@ -0,0 +1,52 @@
+use winit::{
+    event::{ElementState, KeyEvent},
+    keyboard::{Key, NamedKey},
+};
+
+/// A single line of command input. There is no text rendering, so the line being typed is shown
+/// in the window title.
+#[derive(Debug)]
+pub struct Console {
+    input: Option<String>,
+}
+
+impl Console {
+    pub fn new() -> Self {
+        Self { input: None }
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.input.is_some()
+    }
+
+    pub fn open(&mut self) {
+        self.input = Some(String::new());
+    }
+
+    pub fn input(&self) -> Option<&str> {
+        self.input.as_deref()
+    }
+
+    /// Edits the line while the console is open. Enter closes the console and returns the
+    /// command, Escape throws it away.
+    pub fn process_key(&mut self, event: &KeyEvent) -> Option<String> {
+        let input = self.input.as_mut()?;
+        if event.state != ElementState::Pressed {
+            return None;
+        }
+
+        match &event.logical_key {
+            Key::Named(NamedKey::Enter) => return self.input.take(),
+            Key::Named(NamedKey::Escape) => self.input = None,
+            Key::Named(NamedKey::Backspace) => {
+                input.pop();
+            }
+            _ => {
+                if let Some(text) = &event.text {
+                    input.extend(text.chars().filter(|c| !c.is_control()));
+                }
+            }
+        }
+        None
+    }
+}
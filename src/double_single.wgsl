@@ -0,0 +1,145 @@
+// Double-single arithmetic, which keeps about 48 bits of precision by storing each number as
+// the unevaluated sum of two f32s. Used for the escape-time iteration when f64 isn't available.
+// Complex numbers are stored as (re.high, re.low, im.high, im.low).
+
+fn ds_add(a: vec2<f32>, b: vec2<f32>) -> vec2<f32> {
+    // Knuth's two-sum, which finds the rounding error of the high parts exactly
+    let sum = a.x + b.x;
+    let v = sum - a.x;
+    let error = (a.x - (sum - v)) + (b.x - v) + a.y + b.y;
+    let high = sum + error;
+    return vec2<f32>(high, error - (high - sum));
+}
+
+// Dekker's split of an f32 into two halves of 12 bits, whose products with each other are exact.
+fn ds_split(a: f32) -> vec2<f32> {
+    let scaled = 4097.0 * a;
+    let high = scaled - (scaled - a);
+    return vec2<f32>(high, a - high);
+}
+
+fn ds_mul(a: vec2<f32>, b: vec2<f32>) -> vec2<f32> {
+    // Dekker's two-product finds the rounding error of the high parts exactly. WGSL doesn't
+    // promise that fma is fused, so it can't be used for this.
+    let product = a.x * b.x;
+    let x = ds_split(a.x);
+    let y = ds_split(b.x);
+    let product_error = ((x.x * y.x - product) + x.x * y.y + x.y * y.x) + x.y * y.y;
+    let error = product_error + (a.x * b.y + a.y * b.x);
+    let high = product + error;
+    return vec2<f32>(high, error - (high - product));
+}
+
+fn ds_abs(a: vec2<f32>) -> vec2<f32> {
+    return select(a, -a, a.x < 0.0);
+}
+
+fn ds_complex_add(a: vec4<f32>, b: vec4<f32>) -> vec4<f32> {
+    return vec4<f32>(ds_add(a.xy, b.xy), ds_add(a.zw, b.zw));
+}
+
+// `compute_next` for exponent 2, the only one with a double-single version.
+fn ds_compute_next(current: vec4<f32>, constant: vec4<f32>) -> vec4<f32> {
+    var z = current;
+    switch fractal.formula {
+        // Burning Ship
+        case 1u: {
+            z = vec4<f32>(ds_abs(z.xy), ds_abs(z.zw));
+        }
+        // Tricorn
+        case 2u: {
+            z = vec4<f32>(z.xy, -z.zw);
+        }
+        // Perpendicular
+        case 5u: {
+            z = vec4<f32>(ds_abs(z.xy), -z.zw);
+        }
+        default: {}
+    }
+
+    let re = ds_add(ds_mul(z.xy, z.xy), -ds_mul(z.zw, z.zw));
+    let im = ds_mul(ds_mul(z.xy, z.zw), vec2<f32>(2.0, 0.0));
+    z = vec4<f32>(re, im);
+
+    switch fractal.formula {
+        // Celtic
+        case 3u: {
+            z = vec4<f32>(ds_abs(z.xy), z.zw);
+        }
+        // Buffalo
+        case 4u: {
+            z = vec4<f32>(ds_abs(z.xy), ds_abs(z.zw));
+        }
+        default: {}
+    }
+    return ds_complex_add(z, constant);
+}
+
+// `compute_iterations` for the pixel at tex_coords, with the camera position unpacked to
+// double-single so that the pixels stay distinct at zooms beyond the reach of f32.
+fn ds_compute_iterations(tex_coords: vec2<f32>, max_iteration: i32) -> Orbit {
+    let offset = tex_coords * f32(unpack(camera.scale));
+    let pixel = vec4<f32>(
+        ds_add(unpack_double_single(wide_x(camera.pos)), vec2<f32>(offset.x, 0.0)),
+        ds_add(unpack_double_single(wide_y(camera.pos)), vec2<f32>(offset.y, 0.0)),
+    );
+    var zn = vec4<f32>(0.0);
+    var constant = pixel;
+    if fractal.julia != 0u {
+        zn = pixel;
+        constant = vec4<f32>(
+            unpack_double_single(wide_x(fractal.julia_constant)),
+            unpack_double_single(wide_y(fractal.julia_constant)),
+        );
+    }
+
+    // The interior tests are only evaluated on the high parts, so they leave a margin for
+    // their rounding error
+    let c = vec2<real>(real(constant.x), real(constant.z));
+    let component = known_component(c, 1e-6);
+    if component != 0u {
+        let cycle_point = component_cycle_point(c, component);
+        return interior_orbit(cycle_point, c, component, cycle_point, 1, no_trap_hit(), false);
+    }
+
+    // The escape test, the derivative and the interior colourings only need the high parts
+    let epsilon = f32(periodicity_epsilon());
+    var derivative = vec2<real>(real(f32(fractal.julia != 0u)), real(0.0));
+    var sum = vec2<real>(real(0.0), real(0.0));
+    var trap_hit = no_trap_hit();
+    var statistic = new_statistic(vec2<real>(real(zn.x), real(zn.z)));
+    var check = zn;
+    var check_iteration = 0;
+    var iteration = 0;
+    var norm = escape_norm(vec2<real>(real(zn.x), real(zn.z)));
+    while iteration < max_iteration {
+        let z = vec2<real>(real(zn.x), real(zn.z));
+        if norm > fractal.bailout {
+            return escaped_orbit(iteration, norm, max_iteration, z, derivative, trap_hit, statistic);
+        }
+        if fractal.colouring == 1u {
+            derivative = next_derivative(z, derivative);
+        }
+        zn = ds_compute_next(zn, constant);
+        norm = escape_norm(vec2<real>(real(zn.x), real(zn.z)));
+        iteration += 1;
+        if fractal.interior == 4u {
+            sum += vec2<real>(real(zn.x), real(zn.z));
+        }
+        trap_hit = next_trap_hit(trap_hit, vec2<real>(real(zn.x), real(zn.z)));
+        statistic = next_statistic(statistic, vec2<real>(real(zn.x), real(zn.z)));
+
+        if checks_periodicity() {
+            let difference = vec2<f32>(ds_add(zn.xy, -check.xy).x, ds_add(zn.zw, -check.zw).x);
+            if dot(difference, difference) < epsilon * epsilon {
+                let z = vec2<real>(real(zn.x), real(zn.z));
+                return interior_orbit(z, c, u32(iteration - check_iteration), sum, iteration, trap_hit, false);
+            }
+            if (iteration & (iteration - 1)) == 0 {
+                check = zn;
+                check_iteration = iteration;
+            }
+        }
+    }
+    return interior_orbit(vec2<real>(real(zn.x), real(zn.z)), c, 0u, sum, iteration, trap_hit, false);
+}
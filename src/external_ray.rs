@@ -0,0 +1,651 @@
+use std::fmt;
+
+use anyhow::{anyhow, Result};
+use cgmath::Point2;
+
+use crate::big::{self, BigPoint};
+use crate::camera::Camera;
+use crate::complex::Complex;
+use crate::nucleus::{self, BigComplex};
+use crate::palette;
+use crate::perturbation::precision_for_zoom;
+
+/// Rays are traced in from this radius, where they are nearly straight.
+const ESCAPE_RADIUS: f64 = 65536.0;
+/// Points traced for each halving of the potential, one per doubling of the angle.
+const SHARPNESS: usize = 8;
+const MAX_LEVELS: usize = 256;
+pub const MAX_RAY_POINTS: usize = MAX_LEVELS * SHARPNESS + 1;
+/// Levels traced beyond those it takes the angle to start repeating, for the ray to get close
+/// to where it lands.
+const EXTRA_LEVELS: usize = 16;
+/// Tracing stops once the points are closer together than f64 can resolve.
+const TRACE_TOLERANCE: f64 = 1e-14;
+/// The view fits this many of the last levels of the ray.
+const SHOWN_LEVELS: usize = 4;
+/// Landing points are refined this much deeper than the view, so that they stay exact when
+/// zooming into them.
+const REFINE_ZOOM: f32 = 100.0;
+/// Steps the multiplier takes from the nucleus of a component to its root.
+const ROOT_STEPS: usize = 8;
+/// A satellite's root is on the boundary of its parent component, where the multiplier m of
+/// the parent's cycle has m^(p / p') = 1 for periods p and p'. This is how close it has to be.
+const SATELLITE_TOLERANCE: f64 = 1e-6;
+/// Denominators are limited so that the numerator can be doubled in a u128.
+const MAX_DENOMINATOR_BITS: u32 = 126;
+const MAX_PERIOD: usize = 64;
+/// Clip space coordinates are clamped to this, well outside the window.
+const CLIP_LIMIT: f64 = 1e6;
+
+/// An external angle in turns, kept as an exact fraction so that it can be doubled without
+/// losing precision.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExternalAngle {
+    numerator: u128,
+    denominator: u128,
+    /// The number of doublings before the angle starts repeating.
+    pub preperiod: usize,
+    /// The number of doublings that bring it back round once it is repeating.
+    pub period: usize,
+}
+
+impl ExternalAngle {
+    /// Parses a fraction such as `1/3`, or a binary expansion such as `.0(01)` or `0.011`, with
+    /// the repeating part in brackets.
+    pub fn parse(text: &str) -> Result<Self> {
+        let text = text.trim();
+        if let Some((numerator, denominator)) = text.split_once('/') {
+            let parse = |part: &str| {
+                part.trim()
+                    .parse::<u128>()
+                    .map_err(|e| anyhow!("invalid angle {:?}: {}", text, e))
+            };
+            return Self::new(parse(numerator)?, parse(denominator)?);
+        }
+
+        let Some(digits) = text.strip_prefix("0.").or_else(|| text.strip_prefix('.')) else {
+            return Err(anyhow!(
+                "expected an angle like 1/3 or .0(01), got {:?}",
+                text
+            ));
+        };
+        let (preperiodic, periodic) = match digits.split_once('(') {
+            Some((preperiodic, periodic)) => {
+                let periodic = periodic
+                    .strip_suffix(')')
+                    .filter(|periodic| !periodic.is_empty())
+                    .ok_or_else(|| anyhow!("invalid repeating part in {:?}", text))?;
+                (preperiodic, Some(periodic))
+            }
+            None => (digits, None),
+        };
+        let length = preperiodic.len() + periodic.map_or(0, str::len);
+        if length > MAX_DENOMINATOR_BITS as usize {
+            return Err(anyhow!(
+                "angles are limited to {} binary digits",
+                MAX_DENOMINATOR_BITS
+            ));
+        }
+        let parse = |part: &str| {
+            if part.is_empty() {
+                return Ok(0);
+            }
+            u128::from_str_radix(part, 2)
+                .map_err(|_| anyhow!("invalid binary digits {:?} in {:?}", part, text))
+        };
+
+        let preperiodic_value = parse(preperiodic)?;
+        let scale = 1u128 << preperiodic.len();
+        match periodic {
+            Some(periodic) => {
+                // 0.p(q) = (p + q / (2^n - 1)) / 2^k
+                let repeat = (1u128 << periodic.len()) - 1;
+                Self::new(
+                    preperiodic_value * repeat + parse(periodic)?,
+                    scale * repeat,
+                )
+            }
+            None => Self::new(preperiodic_value, scale),
+        }
+    }
+
+    fn new(numerator: u128, denominator: u128) -> Result<Self> {
+        if denominator == 0 || denominator > 1 << MAX_DENOMINATOR_BITS {
+            return Err(anyhow!(
+                "the denominator must be between 1 and 2^{}",
+                MAX_DENOMINATOR_BITS
+            ));
+        }
+        let numerator = numerator % denominator;
+        let divisor = gcd(numerator, denominator);
+        let (numerator, denominator) = (numerator / divisor, denominator / divisor);
+
+        // Doubling removes a factor of 2 from the denominator each time, then cycles through
+        // the powers of 2 modulo the odd part.
+        let preperiod = denominator.trailing_zeros() as usize;
+        let odd = denominator >> preperiod;
+        let mut period = 1;
+        let mut power = 2 % odd;
+        while odd > 1 && power != 1 {
+            power = (power << 1) % odd;
+            period += 1;
+            if period > MAX_PERIOD {
+                return Err(anyhow!(
+                    "the period of {}/{} is longer than {}",
+                    numerator,
+                    denominator,
+                    MAX_PERIOD
+                ));
+            }
+        }
+        Ok(Self {
+            numerator,
+            denominator,
+            preperiod,
+            period,
+        })
+    }
+}
+
+impl fmt::Display for ExternalAngle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.numerator, self.denominator)
+    }
+}
+
+fn gcd(mut a: u128, mut b: u128) -> u128 {
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    a
+}
+
+/// An external ray of the Mandelbrot set, traced in from outside to the point it lands on.
+#[derive(Debug)]
+pub struct ExternalRay {
+    pub angle: ExternalAngle,
+    /// A Misiurewicz point for preperiodic angles, or the root of a component for periodic
+    /// ones, refined in full precision.
+    pub landing: BigPoint,
+    /// Offsets of the traced points from the landing point, so that they keep their precision
+    /// near it.
+    pub points: Vec<Point2<f64>>,
+    /// The zoom that fits the last few levels of the ray in view, or the component a periodic
+    /// ray lands on.
+    pub zoom: f32,
+}
+
+impl ExternalRay {
+    pub fn trace(angle: ExternalAngle) -> Result<Self> {
+        let points = trace(angle);
+        let end = points[points.len() - 1];
+        let shown = points[points.len().saturating_sub(SHOWN_LEVELS * SHARPNESS + 1)];
+        let zoom = nucleus::view_zoom((shown - end).norm_sqr().sqrt());
+
+        let (landing, zoom) = if angle.preperiod > 0 {
+            // z_1 = c, so the critical orbit takes one more step than the angle to repeat
+            let c = misiurewicz(
+                end,
+                angle.preperiod + 1,
+                angle.period,
+                precision_for_zoom(zoom + REFINE_ZOOM),
+            )?;
+            let digits = big::digits_for_zoom(zoom + REFINE_ZOOM);
+            let landing = BigPoint {
+                x: big::from_binary(&c.0, digits),
+                y: big::from_binary(&c.1, digits),
+            };
+            (landing, zoom)
+        } else {
+            // Periodic rays approach their root too slowly for the trace to reach it, so it is
+            // found from the nucleus of the component instead
+            let bits = precision_for_zoom(zoom);
+            let c = (
+                big::binary_from_f64(end.re, bits),
+                big::binary_from_f64(end.im, bits),
+            );
+            let centre = nucleus::to_complex(&nucleus::newton(c, angle.period, bits)?);
+            let (z, root) = root(centre, angle.period)?;
+            let zoom = nucleus::view_zoom((root - centre).norm_sqr().sqrt());
+            let c = refine_root(
+                z,
+                root,
+                angle.period,
+                precision_for_zoom(zoom + REFINE_ZOOM),
+            )?;
+            let digits = big::digits_for_zoom(zoom + REFINE_ZOOM);
+            let landing = BigPoint {
+                x: big::from_binary(&c.0, digits),
+                y: big::from_binary(&c.1, digits),
+            };
+            (landing, zoom)
+        };
+        let origin = landing.to_f64();
+        let points = points
+            .into_iter()
+            .map(|point| Point2::new(point.re - origin.x, point.im - origin.y))
+            .collect();
+        Ok(Self {
+            angle,
+            landing,
+            points,
+            zoom,
+        })
+    }
+}
+
+fn polar(radius: f64, turns: f64) -> Complex {
+    let (sin, cos) = (turns * std::f64::consts::TAU).sin_cos();
+    Complex::new(radius * cos, radius * sin)
+}
+
+/// Traces the ray inward from the escape radius. Level `n` of the ray is where z_{n+1} has the
+/// angle doubled `n` times, and its potential is halved over [`SHARPNESS`] points, each found
+/// by Newton's method from the one before.
+fn trace(angle: ExternalAngle) -> Vec<Complex> {
+    let levels = ((angle.preperiod + angle.period) * 4 + EXTRA_LEVELS).min(MAX_LEVELS);
+    let mut numerator = angle.numerator;
+    let mut c = polar(ESCAPE_RADIUS, numerator as f64 / angle.denominator as f64);
+    let mut points = vec![c];
+    for level in 0..levels {
+        let turns = numerator as f64 / angle.denominator as f64;
+        for step in 0..SHARPNESS {
+            let potential = 0.5f64.powf((step as f64 + 0.5) / SHARPNESS as f64);
+            let target = polar(ESCAPE_RADIUS.powf(potential), turns);
+            let next = ray_point(c, target, level + 1);
+            if (next - c).norm_sqr() < TRACE_TOLERANCE * TRACE_TOLERANCE {
+                return points;
+            }
+            c = next;
+            points.push(c);
+        }
+        numerator = (numerator << 1) % angle.denominator;
+    }
+    points
+}
+
+/// Newton's method on z_n(c) = target.
+fn ray_point(mut c: Complex, target: Complex, iterations: usize) -> Complex {
+    for _ in 0..nucleus::MAX_NEWTON_STEPS {
+        let mut z = Complex::ZERO;
+        let mut derivative = Complex::ZERO;
+        for _ in 0..iterations {
+            derivative = z * derivative * 2.0 + Complex::ONE;
+            z = z * z + c;
+        }
+        let delta = (z - target) / derivative;
+        if !(delta.re.is_finite() && delta.im.is_finite()) {
+            break;
+        }
+        c = c - delta;
+        if delta.norm_sqr() <= f64::EPSILON * f64::EPSILON * c.norm_sqr() {
+            break;
+        }
+    }
+    c
+}
+
+/// The root of the component with the given nucleus, where the multiplier of its cycle is 1,
+/// and a point z of the cycle there. Newton's method is run on f^p(z) = z and (f^p)'(z) = m for
+/// z and c together, with m raised in steps along the real axis from 0 at the nucleus, where
+/// z = 0.
+fn root(nucleus: Complex, period: usize) -> Result<(Complex, Complex)> {
+    let (mut z, mut c) = (Complex::ZERO, nucleus);
+    for step in 1..=ROOT_STEPS {
+        let multiplier = Complex::new(step as f64 / ROOT_STEPS as f64, 0.0);
+        for _ in 0..nucleus::MAX_NEWTON_STEPS {
+            // w = f^p(z) and its derivatives with respect to z, c, z twice, and z and c
+            let mut w = z;
+            let (mut dz, mut dc, mut dzdz, mut dzdc) =
+                (Complex::ONE, Complex::ZERO, Complex::ZERO, Complex::ZERO);
+            for _ in 0..period {
+                dzdc = (dz * dc + w * dzdc) * 2.0;
+                dzdz = (dz * dz + w * dzdz) * 2.0;
+                dc = w * dc * 2.0 + Complex::ONE;
+                dz = w * dz * 2.0;
+                w = w * w + c;
+            }
+            let f = w - z;
+            let g = dz - multiplier;
+            let determinant = (dz - Complex::ONE) * dzdc - dc * dzdz;
+            let step_z = (dc * g - dzdc * f) / determinant;
+            let step_c = (dzdz * f - (dz - Complex::ONE) * g) / determinant;
+            if !(step_c.re.is_finite() && step_c.im.is_finite()) {
+                return Err(anyhow!("couldn't find the root of period {}", period));
+            }
+            z = z + step_z;
+            c = c + step_c;
+            if step_c.norm_sqr() <= f64::EPSILON * f64::EPSILON * c.norm_sqr() {
+                break;
+            }
+        }
+    }
+    Ok((z, c))
+}
+
+/// The period and a point of the parent's cycle if `c` is the root of a satellite, found by
+/// Newton's method on f^p'(z) = z from a point z of the satellite's cycle.
+fn satellite_cycle(z: Complex, c: Complex, period: usize) -> Option<(usize, Complex)> {
+    let cycle = |z: Complex, cycle_period: usize| {
+        (0..cycle_period).fold((z, Complex::ONE), |(w, m), _| (w * w + c, w * m * 2.0))
+    };
+    (1..period)
+        .filter(|cycle_period| period.is_multiple_of(*cycle_period))
+        .find_map(|cycle_period| {
+            let mut z = z;
+            for _ in 0..nucleus::MAX_NEWTON_STEPS {
+                let (w, multiplier) = cycle(z, cycle_period);
+                let delta = (w - z) / (multiplier - Complex::ONE);
+                if !(delta.re.is_finite() && delta.im.is_finite()) {
+                    return None;
+                }
+                z = z - delta;
+                if delta.norm_sqr() <= f64::EPSILON * f64::EPSILON * z.norm_sqr() {
+                    break;
+                }
+            }
+            let multiplier = cycle(z, cycle_period).1;
+            let power =
+                (0..period / cycle_period).fold(Complex::ONE, |power, _| power * multiplier);
+            ((power - Complex::ONE).norm_sqr() <= SATELLITE_TOLERANCE * SATELLITE_TOLERANCE)
+                .then_some((cycle_period, z))
+        })
+}
+
+/// Refines a root from [`root`] in full precision. The equations there are degenerate at the
+/// root of a satellite, so the parent's cycle is followed for its period p' instead, and the
+/// multiplier m solves m^(p / p') = 1. The multiplier is carried in full precision, since that
+/// equation holds to within the tolerance, and the other derivatives in
+/// [`nucleus::DERIVATIVE_BITS`].
+fn refine_root(z: Complex, c: Complex, period: usize, bits: usize) -> Result<BigComplex> {
+    let (cycle_period, z) = satellite_cycle(z, c, period).unwrap_or((period, z));
+    let from_f64 = |z: Complex| {
+        (
+            big::binary_from_f64(z.re, bits),
+            big::binary_from_f64(z.im, bits),
+        )
+    };
+    let (mut z, mut c) = (from_f64(z), from_f64(c));
+    let one = from_f64(Complex::ONE);
+    for _ in 0..nucleus::MAX_NEWTON_STEPS {
+        let mut w = z.clone();
+        let mut dz = one.clone();
+        let mut dc = nucleus::zero(nucleus::DERIVATIVE_BITS);
+        let (mut dzdz, mut dzdc) = (dc.clone(), dc.clone());
+        for _ in 0..cycle_period {
+            let (rough_w, rough_dz) = (nucleus::rough(&w), nucleus::rough(&dz));
+            dzdc = nucleus::double(&nucleus::add(
+                &nucleus::mul(&rough_dz, &dc),
+                &nucleus::mul(&rough_w, &dzdc),
+            ));
+            dzdz = nucleus::double(&nucleus::add(
+                &nucleus::mul(&rough_dz, &rough_dz),
+                &nucleus::mul(&rough_w, &dzdz),
+            ));
+            dc = nucleus::derivative_step(&w, &dc);
+            dz = nucleus::double(&nucleus::mul(&w, &dz));
+            w = nucleus::step(&w, &c);
+        }
+        // g = m^q - 1 with q = p / p', whose derivatives are q m^(q - 1) times those of m
+        let turns = period / cycle_period;
+        let power = (1..turns).fold(one.clone(), |power, _| nucleus::mul(&power, &dz));
+        let g = nucleus::rough(&nucleus::sub(&nucleus::mul(&power, &dz), &one));
+        let scale = nucleus::mul(
+            &nucleus::rough(&power),
+            &(
+                big::binary_from_f64(turns as f64, nucleus::DERIVATIVE_BITS),
+                big::binary_from_f64(0.0, nucleus::DERIVATIVE_BITS),
+            ),
+        );
+        let (gz, gc) = (nucleus::mul(&scale, &dzdz), nucleus::mul(&scale, &dzdc));
+
+        // Solves [dz - 1, dc; gz, gc] [step_z; step_c] = -[f; g]
+        let f = nucleus::rough(&nucleus::sub(&w, &z));
+        let fz = nucleus::rough(&nucleus::sub(&dz, &one));
+        let determinant = nucleus::sub(&nucleus::mul(&fz, &gc), &nucleus::mul(&dc, &gz));
+        let step = |a: &BigComplex, b: &BigComplex, x: &BigComplex, y: &BigComplex| {
+            nucleus::div(
+                &nucleus::sub(&nucleus::mul(a, b), &nucleus::mul(x, y)),
+                &determinant,
+            )
+        };
+        let (Some(step_z), Some(step_c)) = (step(&dc, &g, &gc, &f), step(&gz, &f, &fz, &g)) else {
+            break;
+        };
+        z = nucleus::add(&z, &step_z);
+        c = nucleus::add(&c, &step_c);
+        if nucleus::converged(&step_c, bits) {
+            return Ok(c);
+        }
+    }
+    Err(anyhow!("couldn't refine the root of period {}", period))
+}
+
+/// Newton's method on z_{k+p}(c) = z_k(c), which holds at the Misiurewicz points of preperiod
+/// k and period p. Starting from the end of the ray keeps it on the one the ray lands on.
+fn misiurewicz(c: Complex, preperiod: usize, period: usize, bits: usize) -> Result<BigComplex> {
+    let mut c = (
+        big::binary_from_f64(c.re, bits),
+        big::binary_from_f64(c.im, bits),
+    );
+    for _ in 0..nucleus::MAX_NEWTON_STEPS {
+        let mut z = nucleus::zero(bits);
+        let mut derivative = nucleus::zero(nucleus::DERIVATIVE_BITS);
+        let mut repeated = (z.clone(), derivative.clone());
+        for iteration in 1..=preperiod + period {
+            derivative = nucleus::derivative_step(&z, &derivative);
+            z = nucleus::step(&z, &c);
+            if iteration == preperiod {
+                repeated = (z.clone(), derivative.clone());
+            }
+        }
+        // The difference is taken in full precision, since both are close to the same point
+        let difference = nucleus::sub(&z, &repeated.0);
+        let Some(delta) = nucleus::div(
+            &nucleus::rough(&difference),
+            &(
+                &derivative.0 - &repeated.1 .0,
+                &derivative.1 - &repeated.1 .1,
+            ),
+        ) else {
+            break;
+        };
+        c = nucleus::sub(&c, &delta);
+        if nucleus::converged(&delta, bits) {
+            return Ok(c);
+        }
+    }
+    Err(anyhow!(
+        "couldn't find the Misiurewicz point of preperiod {} and period {}",
+        preperiod,
+        period
+    ))
+}
+
+/// Draws an [`ExternalRay`] over the fractal as a line strip. The points are moved into clip
+/// space on the CPU each frame, relative to the camera in full precision.
+#[derive(Debug)]
+pub struct RayOverlay {
+    pipeline: wgpu::RenderPipeline,
+    vertex_buffer: wgpu::Buffer,
+    vertex_count: u32,
+}
+
+impl RayOverlay {
+    pub fn new(device: &wgpu::Device, format: wgpu::TextureFormat) -> Self {
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("external_ray_shader"),
+            source: wgpu::ShaderSource::Wgsl(
+                palette::output_shader_source(include_str!("external_ray.wgsl"), format).into(),
+            ),
+        });
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("External Ray Pipeline Layout"),
+            bind_group_layouts: &[],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("External Ray Pipeline"),
+            layout: Some(&layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: Some("vs_main"),
+                buffers: &[wgpu::VertexBufferLayout {
+                    array_stride: std::mem::size_of::<[f32; 2]>() as wgpu::BufferAddress,
+                    step_mode: wgpu::VertexStepMode::Vertex,
+                    attributes: &[wgpu::VertexAttribute {
+                        offset: 0,
+                        shader_location: 0,
+                        format: wgpu::VertexFormat::Float32x2,
+                    }],
+                }],
+                compilation_options: Default::default(),
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: Some("fs_main"),
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend: Some(wgpu::BlendState::REPLACE),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+                compilation_options: Default::default(),
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::LineStrip,
+                ..Default::default()
+            },
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+            cache: None,
+        });
+        let vertex_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("external_ray_vertex_buffer"),
+            size: (MAX_RAY_POINTS * std::mem::size_of::<[f32; 2]>()) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        Self {
+            pipeline,
+            vertex_buffer,
+            vertex_count: 0,
+        }
+    }
+
+    /// Moves the ray's points into clip space for the camera, matching `vs_main` in
+    /// fullscreen.wgsl.
+    pub fn update(&mut self, queue: &wgpu::Queue, ray: Option<&ExternalRay>, camera: &Camera) {
+        let Some(ray) = ray else {
+            self.vertex_count = 0;
+            return;
+        };
+        let origin = ray.landing.offset_from(camera.position());
+        let scale = (-camera.zoom() as f64).exp();
+        let vertices = ray
+            .points
+            .iter()
+            .map(|point| {
+                let x = (origin.x + point.x) / (scale * camera.aspect as f64);
+                let y = -(origin.y + point.y) / scale;
+                // Far off screen points are clamped to keep them finite in f32
+                [
+                    x.clamp(-CLIP_LIMIT, CLIP_LIMIT) as f32,
+                    y.clamp(-CLIP_LIMIT, CLIP_LIMIT) as f32,
+                ]
+            })
+            .collect::<Vec<_>>();
+        queue.write_buffer(&self.vertex_buffer, 0, bytemuck::cast_slice(&vertices));
+        self.vertex_count = vertices.len() as u32;
+    }
+
+    pub fn draw(&self, render_pass: &mut wgpu::RenderPass) {
+        if self.vertex_count == 0 {
+            return;
+        }
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        render_pass.draw(0..self.vertex_count, 0..1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use dashu_float::DBig;
+
+    use super::*;
+
+    fn angle(text: &str) -> ExternalAngle {
+        ExternalAngle::parse(text).unwrap()
+    }
+
+    #[test]
+    fn parses_binary_expansions() {
+        let sixth = angle(".0(01)");
+        assert_eq!((sixth.numerator, sixth.denominator), (1, 6));
+        assert_eq!((sixth.preperiod, sixth.period), (1, 2));
+        assert_eq!(angle("0.0(01)"), sixth);
+        assert_eq!(angle(".011"), angle("3/8"));
+        assert_eq!(angle(".(001)"), angle("1/7"));
+    }
+
+    #[test]
+    fn parses_fractions() {
+        let third = angle("1/3");
+        assert_eq!((third.numerator, third.denominator), (1, 3));
+        assert_eq!((third.preperiod, third.period), (0, 2));
+        assert_eq!(angle(" 2 / 6 "), third);
+        assert_eq!(angle("4/3"), third);
+    }
+
+    #[test]
+    fn finds_preperiod_and_period() {
+        let periods = |text| {
+            let angle = angle(text);
+            (angle.preperiod, angle.period)
+        };
+        assert_eq!(periods("0/1"), (0, 1));
+        assert_eq!(periods("1/2"), (1, 1));
+        assert_eq!(periods("3/8"), (3, 1));
+        assert_eq!(periods("1/15"), (0, 4));
+        assert_eq!(periods("1/12"), (2, 2));
+        assert_eq!(periods(".10(001)"), (2, 3));
+    }
+
+    #[test]
+    fn rejects_angles_beyond_the_limits() {
+        assert!(ExternalAngle::parse("1/0").is_err());
+        assert!(ExternalAngle::new(1, 1 << MAX_DENOMINATOR_BITS).is_ok());
+        assert!(ExternalAngle::new(1, (1 << MAX_DENOMINATOR_BITS) + 1).is_err());
+
+        let digits = |count| format!(".{}1", "0".repeat(count - 1));
+        assert!(ExternalAngle::parse(&digits(MAX_DENOMINATOR_BITS as usize)).is_ok());
+        assert!(ExternalAngle::parse(&digits(MAX_DENOMINATOR_BITS as usize + 1)).is_err());
+
+        let repeating = |count| format!(".({}1)", "0".repeat(count - 1));
+        assert_eq!(angle(&repeating(MAX_PERIOD)).period, MAX_PERIOD);
+        assert!(ExternalAngle::parse(&repeating(MAX_PERIOD + 1)).is_err());
+    }
+
+    #[test]
+    fn rejects_malformed_angles() {
+        for text in ["", "1/3/4", "x/3", ".2", ".0()", ".0(1", "0.5"] {
+            assert!(ExternalAngle::parse(text).is_err(), "{:?}", text);
+        }
+    }
+
+    #[test]
+    fn periodic_rays_land_on_roots_in_full_precision() {
+        let error =
+            |value: DBig, exact: &str| (value - big::parse(exact).unwrap()).to_f64().value().abs();
+        // The root of the period 2 component, a satellite of the main cardioid
+        let root = ExternalRay::trace(angle("1/3")).unwrap().landing;
+        assert!(error(root.x, "-0.75") < 1e-40);
+        assert!(error(root.y, "0") < 1e-40);
+
+        // The root of the period 3 bulb is -1/8 + 3 sqrt(3) / 8 i
+        let root = ExternalRay::trace(angle("1/7")).unwrap().landing;
+        assert!(error(root.x, "-0.125") < 1e-40);
+        assert!(error(root.y.sqr() * DBig::from(64), "27") < 1e-40);
+    }
+}
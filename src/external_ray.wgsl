@@ -0,0 +1,11 @@
+// Draws the points of an external ray, already in clip space, as a line over the fractal.
+
+@vertex
+fn vs_main(@location(0) position: vec2<f32>) -> @builtin(position) vec4<f32> {
+    return vec4<f32>(position, 0.0, 1.0);
+}
+
+@fragment
+fn fs_main() -> @location(0) vec4<f32> {
+    return output_colour(vec3<f32>(1.0, 0.35, 0.2));
+}
@@ -0,0 +1,419 @@
+use anyhow::{anyhow, Context, Result};
+use cgmath::Point2;
+use winit::dpi::PhysicalSize;
+
+use crate::histogram::{self, HistogramPass};
+use crate::precision::Precision;
+
+pub const MAX_TRANSFORMS: usize = 12;
+pub const PALETTE_LENGTH: usize = 256;
+/// The variations in the order of their weights in the shader, named as in flame files.
+pub const VARIATIONS: [&str; 16] = [
+    "linear",
+    "sinusoidal",
+    "spherical",
+    "swirl",
+    "horseshoe",
+    "polar",
+    "handkerchief",
+    "heart",
+    "disc",
+    "spiral",
+    "hyperbolic",
+    "diamond",
+    "ex",
+    "julia",
+    "bubble",
+    "eyefish",
+];
+
+/// One function of the system, an affine transform followed by a weighted sum of variations.
+#[derive(Debug, Clone)]
+pub struct Transform {
+    /// x' = a·x + b·y + c, y' = d·x + e·y + f
+    pub affine: [f32; 6],
+    /// The relative chance of picking this transform.
+    pub weight: f32,
+    /// The palette index this transform pulls the point's colour towards.
+    pub colour: f32,
+    /// How far the colour moves towards `colour` each time the transform is picked.
+    pub colour_speed: f32,
+    pub variations: [f32; VARIATIONS.len()],
+}
+
+impl Transform {
+    fn new(affine: [f32; 6], colour: f32, variations: &[(usize, f32)]) -> Self {
+        let mut transform = Self {
+            affine,
+            weight: 1.0,
+            colour,
+            colour_speed: 0.5,
+            variations: [0.0; VARIATIONS.len()],
+        };
+        for &(variation, weight) in variations {
+            transform.variations[variation] = weight;
+        }
+        transform
+    }
+}
+
+/// A fractal flame, an iterated function system rendered with the chaos game.
+#[derive(Debug, Clone)]
+pub struct Flame {
+    pub transforms: Vec<Transform>,
+    pub palette: [[f32; 4]; PALETTE_LENGTH],
+    pub gamma: f32,
+    pub brightness: f32,
+    /// Where the camera should look, if the flame file says.
+    pub view: Option<(Point2<f64>, f32)>,
+}
+
+impl Flame {
+    pub fn new() -> Self {
+        // A Sierpinski triangle, made curly by a touch of swirl
+        let transforms = vec![
+            Transform::new([0.5, 0.0, -0.5, 0.0, 0.5, -0.5], 0.0, &[(0, 1.0)]),
+            Transform::new([0.5, 0.0, 0.5, 0.0, 0.5, -0.5], 0.5, &[(0, 0.9), (3, 0.1)]),
+            Transform::new([0.5, 0.0, 0.0, 0.0, 0.5, 0.5], 1.0, &[(0, 0.9), (2, 0.1)]),
+        ];
+        let mut palette = [[0.0; 4]; PALETTE_LENGTH];
+        for (i, colour) in palette.iter_mut().enumerate() {
+            let t = i as f32 / (PALETTE_LENGTH - 1) as f32;
+            *colour = [1.0 - 0.5 * t, 0.3 + 0.5 * t, 0.2 + 0.8 * t, 1.0];
+        }
+        Self {
+            transforms,
+            palette,
+            gamma: 4.0,
+            brightness: 4.0,
+            view: None,
+        }
+    }
+
+    /// Reads the first `<flame>` of a file in the XML format written by flam3 and Apophysis.
+    /// Final and post transforms, and variations that aren't in [`VARIATIONS`], are skipped,
+    /// and the names of the skipped attributes are returned with the flame.
+    pub fn load(path: &str) -> Result<(Self, Vec<String>)> {
+        let text = std::fs::read_to_string(path).with_context(|| format!("reading {}", path))?;
+        Flame::parse(&text).with_context(|| format!("parsing {}", path))
+    }
+
+    fn parse(text: &str) -> Result<(Self, Vec<String>)> {
+        let document = roxmltree::Document::parse(text)?;
+        let flame = document
+            .descendants()
+            .find(|node| node.has_tag_name("flame"))
+            .ok_or_else(|| anyhow!("no <flame> in the file"))?;
+
+        let mut result = Flame::new();
+        result.transforms.clear();
+        let mut skipped = Vec::new();
+        for xform in flame.children().filter(|node| node.has_tag_name("xform")) {
+            if result.transforms.len() == MAX_TRANSFORMS {
+                return Err(anyhow!(
+                    "flames can have at most {} transforms",
+                    MAX_TRANSFORMS
+                ));
+            }
+            result
+                .transforms
+                .push(parse_transform(xform, &mut skipped)?);
+        }
+        if result.transforms.is_empty() {
+            return Err(anyhow!("the flame has no transforms"));
+        }
+
+        if let Some(palette) = flame.children().find(|node| node.has_tag_name("palette")) {
+            result.palette = parse_hex_palette(palette.text().unwrap_or(""))?;
+        } else {
+            for colour in flame.children().filter(|node| node.has_tag_name("color")) {
+                let index = attribute::<usize>(colour, "index")?.unwrap_or(0);
+                let rgb = numbers(colour.attribute("rgb").unwrap_or(""), "rgb")?;
+                let (Some(entry), &[r, g, b]) = (result.palette.get_mut(index), &rgb[..]) else {
+                    return Err(anyhow!("invalid palette colour {}", index));
+                };
+                *entry = [r / 255.0, g / 255.0, b / 255.0, 1.0];
+            }
+        }
+
+        result.gamma = attribute(flame, "gamma")?.unwrap_or(result.gamma);
+        result.brightness = attribute(flame, "brightness")?.unwrap_or(result.brightness);
+        // flam3 gives the centre, the image size and the pixels per unit, while the camera
+        // shows exp(-zoom) units either side of the centre vertically
+        if let (Some(centre), Some(size), Some(scale)) = (
+            flame.attribute("center"),
+            flame.attribute("size"),
+            attribute::<f64>(flame, "scale")?,
+        ) {
+            let (centre, size) = (numbers(centre, "center")?, numbers(size, "size")?);
+            if let (&[x, y], &[_, height]) = (&centre[..], &size[..]) {
+                let zoom = -(height as f64 / 2.0 / scale).ln() as f32;
+                result.view = Some((Point2::new(x as f64, y as f64), zoom));
+            }
+        }
+        Ok((result, skipped))
+    }
+}
+
+fn attribute<T: std::str::FromStr>(node: roxmltree::Node, name: &str) -> Result<Option<T>> {
+    node.attribute(name)
+        .map(|value| {
+            value
+                .trim()
+                .parse()
+                .map_err(|_| anyhow!("invalid {} {:?}", name, value))
+        })
+        .transpose()
+}
+
+fn numbers(text: &str, name: &str) -> Result<Vec<f32>> {
+    text.split_whitespace()
+        .map(str::parse)
+        .collect::<Result<_, _>>()
+        .map_err(|_| anyhow!("invalid {} {:?}", name, text))
+}
+
+fn parse_transform(xform: roxmltree::Node, skipped: &mut Vec<String>) -> Result<Transform> {
+    let coefs = numbers(xform.attribute("coefs").unwrap_or("1 0 0 1 0 0"), "coefs")?;
+    // flam3 stores the columns of the matrix then the offset
+    let [xx, yx, xy, yy, ox, oy] = coefs[..] else {
+        return Err(anyhow!("expected 6 coefs, got {}", coefs.len()));
+    };
+    let mut transform = Transform::new([xx, xy, ox, yx, yy, oy], 0.0, &[]);
+    transform.weight = attribute(xform, "weight")?.unwrap_or(1.0);
+    transform.colour = attribute(xform, "color")?.unwrap_or(0.0);
+    // Older files give the symmetry, where the speed is (1 - symmetry) / 2
+    transform.colour_speed = match attribute::<f32>(xform, "color_speed")? {
+        Some(speed) => speed,
+        None => (1.0 - attribute(xform, "symmetry")?.unwrap_or(0.0)) / 2.0,
+    };
+
+    const PROPERTIES: [&str; 8] = [
+        "weight",
+        "color",
+        "color_speed",
+        "symmetry",
+        "coefs",
+        "post",
+        "opacity",
+        "animate",
+    ];
+    for attr in xform.attributes() {
+        match VARIATIONS.iter().position(|&name| name == attr.name()) {
+            Some(variation) => {
+                transform.variations[variation] = attr
+                    .value()
+                    .trim()
+                    .parse()
+                    .map_err(|_| anyhow!("invalid {} {:?}", attr.name(), attr.value()))?
+            }
+            None if PROPERTIES.contains(&attr.name()) => {}
+            None => skipped.push(attr.name().to_string()),
+        }
+    }
+    Ok(transform)
+}
+
+fn parse_hex_palette(text: &str) -> Result<[[f32; 4]; PALETTE_LENGTH]> {
+    let digits = text.split_whitespace().collect::<String>();
+    let mut palette = [[0.0; 4]; PALETTE_LENGTH];
+    if digits.len() != PALETTE_LENGTH * 6 {
+        return Err(anyhow!("expected {} hex colours", PALETTE_LENGTH));
+    }
+    for (colour, hex) in palette.iter_mut().zip(digits.as_bytes().chunks(6)) {
+        let hex = std::str::from_utf8(hex)?;
+        let value =
+            u32::from_str_radix(hex, 16).map_err(|_| anyhow!("invalid colour {:?}", hex))?;
+        let channel = |shift: u32| ((value >> shift) & 0xff) as f32 / 255.0;
+        *colour = [channel(16), channel(8), channel(0), 1.0];
+    }
+    Ok(palette)
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct TransformUniform {
+    /// The top row of the affine transform, a b c.
+    pub x_row: [f32; 3],
+    pub weight: f32,
+    /// The bottom row of the affine transform, d e f.
+    pub y_row: [f32; 3],
+    pub colour: f32,
+    pub colour_speed: f32,
+    pub _padding: [f32; 3],
+    pub variations: [[f32; 4]; VARIATIONS.len() / 4],
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct FlameUniform {
+    pub transforms: [TransformUniform; MAX_TRANSFORMS],
+    pub palette: [[f32; 4]; PALETTE_LENGTH],
+    pub transform_count: u32,
+    pub width: u32,
+    pub height: u32,
+    pub gamma: f32,
+    pub brightness: f32,
+    pub _padding: [u32; 3],
+}
+
+impl FlameUniform {
+    pub fn update(&mut self, flame: &Flame, size: PhysicalSize<u32>) {
+        for (uniform, transform) in self.transforms.iter_mut().zip(&flame.transforms) {
+            let [a, b, c, d, e, f] = transform.affine;
+            uniform.x_row = [a, b, c];
+            uniform.y_row = [d, e, f];
+            uniform.weight = transform.weight;
+            uniform.colour = transform.colour;
+            uniform.colour_speed = transform.colour_speed;
+            for (i, &weight) in transform.variations.iter().enumerate() {
+                uniform.variations[i / 4][i % 4] = weight;
+            }
+        }
+        self.palette = flame.palette;
+        self.transform_count = flame.transforms.len() as u32;
+        self.width = size.width;
+        self.height = size.height;
+        self.gamma = flame.gamma;
+        self.brightness = flame.brightness;
+    }
+}
+
+pub type FlamePass = HistogramPass<FlameUniform>;
+
+/// The compute pass that plays the chaos game into a histogram, which `flame_render.wgsl` then
+/// tone maps. Every pixel holds its hit count then the red, green and blue sums in 1/255ths.
+pub fn create_pass(
+    device: &wgpu::Device,
+    camera_bind_group_layout: &wgpu::BindGroupLayout,
+    size: PhysicalSize<u32>,
+    precision: Precision,
+    compute_shaders: bool,
+) -> FlamePass {
+    HistogramPass::new(
+        device,
+        "flame",
+        precision.shader_source(&histogram::shader_source(include_str!("flame.wgsl"))),
+        4,
+        &[camera_bind_group_layout],
+        size,
+        compute_shaders,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn xform(xml: &str) -> (Transform, Vec<String>) {
+        let document = roxmltree::Document::parse(xml).unwrap();
+        let mut skipped = Vec::new();
+        let transform = parse_transform(document.root_element(), &mut skipped).unwrap();
+        (transform, skipped)
+    }
+
+    /// A hex palette of `colours` followed by black.
+    fn hex_palette(colours: &[&str]) -> String {
+        let mut text = colours.join("");
+        text.push_str(&"000000".repeat(PALETTE_LENGTH - colours.len()));
+        // flam3 wraps the digits over lines of 48
+        text.as_bytes()
+            .chunks(48)
+            .map(|line| format!("      {}\n", std::str::from_utf8(line).unwrap()))
+            .collect()
+    }
+
+    #[test]
+    fn coefs_are_columns_then_offset() {
+        let (transform, _) = xform(r#"<xform coefs="1 2 3 4 5 6" linear="1"/>"#);
+        assert_eq!(transform.affine, [1.0, 3.0, 5.0, 2.0, 4.0, 6.0]);
+        let (transform, _) = xform(r#"<xform linear="1"/>"#);
+        assert_eq!(transform.affine, [1.0, 0.0, 0.0, 0.0, 1.0, 0.0]);
+        let document = roxmltree::Document::parse(r#"<xform coefs="1 2 3"/>"#).unwrap();
+        assert!(parse_transform(document.root_element(), &mut Vec::new()).is_err());
+    }
+
+    #[test]
+    fn colour_speed_falls_back_to_symmetry() {
+        let (transform, _) = xform(r#"<xform color="0.25" symmetry="0.5"/>"#);
+        assert_eq!(transform.colour, 0.25);
+        assert_eq!(transform.colour_speed, 0.25);
+        let (transform, _) = xform(r#"<xform symmetry="1" color_speed="0.1"/>"#);
+        assert_eq!(transform.colour_speed, 0.1);
+        let (transform, _) = xform(r#"<xform/>"#);
+        assert_eq!(transform.colour_speed, 0.5);
+    }
+
+    #[test]
+    fn unknown_attributes_are_skipped() {
+        let (transform, skipped) =
+            xform(r#"<xform weight="0.5" swirl="0.25" blur="1" post="1 0 0 1 0 0" julian="2"/>"#);
+        assert_eq!(transform.weight, 0.5);
+        assert_eq!(transform.variations[3], 0.25);
+        assert_eq!(transform.variations.iter().sum::<f32>(), 0.25);
+        assert_eq!(skipped, ["blur", "julian"]);
+    }
+
+    #[test]
+    fn hex_palettes() {
+        let palette = parse_hex_palette(&hex_palette(&["FF0000", "00ff80"])).unwrap();
+        assert_eq!(palette[0], [1.0, 0.0, 0.0, 1.0]);
+        assert_eq!(palette[1], [0.0, 1.0, 128.0 / 255.0, 1.0]);
+        assert_eq!(palette[255], [0.0, 0.0, 0.0, 1.0]);
+        assert!(parse_hex_palette("FF0000").is_err());
+        assert!(parse_hex_palette(&hex_palette(&["GG0000"])).is_err());
+    }
+
+    #[test]
+    fn load_reads_transforms_colours_and_view() {
+        let (flame, skipped) = Flame::parse(
+            r#"<flames>
+                <flame gamma="2.5" center="1 2" size="100 200" scale="50">
+                    <xform weight="2" coefs="1 0 0 1 0.5 0" linear="1" blur="1"/>
+                    <xform color="1" spherical="1"/>
+                    <finalxform coefs="1 0 0 1 0 0" linear="1"/>
+                    <color index="0" rgb="255 0 0"/>
+                    <color index="255" rgb="0 0 255"/>
+                </flame>
+            </flames>"#,
+        )
+        .unwrap();
+        assert_eq!(flame.transforms.len(), 2);
+        assert_eq!(flame.transforms[0].weight, 2.0);
+        assert_eq!(flame.transforms[0].affine[2], 0.5);
+        assert_eq!(flame.transforms[1].variations[2], 1.0);
+        assert_eq!(skipped, ["blur"]);
+        assert_eq!(flame.palette[0], [1.0, 0.0, 0.0, 1.0]);
+        assert_eq!(flame.palette[255], [0.0, 0.0, 1.0, 1.0]);
+        assert_eq!(flame.gamma, 2.5);
+        assert_eq!(flame.brightness, Flame::new().brightness);
+        let (position, zoom) = flame.view.unwrap();
+        assert_eq!(position, Point2::new(1.0, 2.0));
+        assert!((zoom + 2f32.ln()).abs() < 1e-6);
+    }
+
+    #[test]
+    fn load_prefers_the_hex_palette() {
+        let xml = format!(
+            r#"<flame><xform linear="1"/><color index="0" rgb="255 0 0"/>
+                <palette count="256" format="RGB">{}</palette></flame>"#,
+            hex_palette(&["00FF00"])
+        );
+        let (flame, skipped) = Flame::parse(&xml).unwrap();
+        assert_eq!(flame.palette[0], [0.0, 1.0, 0.0, 1.0]);
+        assert!(skipped.is_empty());
+        assert_eq!(flame.view, None);
+    }
+
+    #[test]
+    fn load_errors() {
+        assert!(Flame::parse("<flames/>").is_err());
+        assert!(Flame::parse("<flame/>").is_err());
+        assert!(Flame::parse(r#"<flame><xform linear="x"/></flame>"#).is_err());
+        assert!(
+            Flame::parse(r#"<flame><xform/><color index="256" rgb="0 0 0"/></flame>"#).is_err()
+        );
+        let too_many = format!("<flame>{}</flame>", "<xform/>".repeat(MAX_TRANSFORMS + 1));
+        assert!(Flame::parse(&too_many).is_err());
+    }
+}
@@ -0,0 +1,177 @@
+struct Transform {
+    x_row: vec3<f32>,
+    weight: f32,
+    y_row: vec3<f32>,
+    colour: f32,
+    colour_speed: f32,
+    variations: array<vec4<f32>, 4>,
+};
+
+struct FlameUniform {
+    transforms: array<Transform, 12>,
+    palette: array<vec4<f32>, 256>,
+    transform_count: u32,
+    width: u32,
+    height: u32,
+    gamma: f32,
+    brightness: f32,
+};
+@group(0)
+@binding(0)
+var<uniform> flame: FlameUniform;
+// The largest count and three padding words, then the count and red, green and blue sums of
+// every pixel
+@group(0)
+@binding(1)
+var<storage, read_write> histogram: array<atomic<u32>>;
+
+const PI: f32 = 3.14159265;
+// Points are only plotted once the orbit has had time to settle onto the attractor
+const SKIPPED_ITERATIONS: u32 = 20u;
+const ITERATIONS: u32 = 1000u;
+// Each hit adds up to 255 to a pixel's colour sums, which would overflow after about 16.8M hits,
+// so pixels stop accumulating at half that. Only a frame that plotted over half its points to
+// one pixel could still carry it past.
+const MAX_PIXEL_COUNT: u32 = 8388608u;
+
+fn plot(p: vec2<f32>, colour: f32) {
+    let view = vec2<f32>((vec2<real>(p) - unpack2(camera.pos)) / unpack(camera.scale));
+    let position = (vec2<f32>(view.x / camera.aspect, view.y) + 1.0) * 0.5
+        * vec2<f32>(f32(flame.width), f32(flame.height));
+    // The negated test also throws away points that have become NaN
+    if !all(position >= vec2<f32>(0.0)) || position.x >= f32(flame.width) || position.y >= f32(flame.height) {
+        return;
+    }
+
+    let index = HISTOGRAM_HEADER + (u32(position.y) * flame.width + u32(position.x)) * 4u;
+    if atomicLoad(&histogram[index]) >= MAX_PIXEL_COUNT {
+        return;
+    }
+    let rgb = vec3<u32>(flame.palette[u32(clamp(colour, 0.0, 1.0) * 255.0)].rgb * 255.0);
+    let count = atomicAdd(&histogram[index], 1u) + 1u;
+    atomicMax(&histogram[0], count);
+    atomicAdd(&histogram[index + 1u], rgb.r);
+    atomicAdd(&histogram[index + 2u], rgb.g);
+    atomicAdd(&histogram[index + 3u], rgb.b);
+}
+
+fn variation_weight(transform: Transform, variation: u32) -> f32 {
+    return transform.variations[variation / 4u][variation % 4u];
+}
+
+// The variations as defined by flam3, in the order of `VARIATIONS`.
+fn apply_variation(variation: u32, p: vec2<f32>, seed: ptr<function, u32>) -> vec2<f32> {
+    let r2 = dot(p, p);
+    let r = sqrt(r2);
+    let theta = atan2(p.x, p.y);
+    switch variation {
+        case 0u: {
+            return p;
+        }
+        case 1u: {
+            return sin(p);
+        }
+        case 2u: {
+            return p / (r2 + 1e-10);
+        }
+        case 3u: {
+            return vec2<f32>(p.x * sin(r2) - p.y * cos(r2), p.x * cos(r2) + p.y * sin(r2));
+        }
+        case 4u: {
+            return vec2<f32>((p.x - p.y) * (p.x + p.y), 2.0 * p.x * p.y) / (r + 1e-10);
+        }
+        case 5u: {
+            return vec2<f32>(theta / PI, r - 1.0);
+        }
+        case 6u: {
+            return r * vec2<f32>(sin(theta + r), cos(theta - r));
+        }
+        case 7u: {
+            return r * vec2<f32>(sin(theta * r), -cos(theta * r));
+        }
+        case 8u: {
+            return theta / PI * vec2<f32>(sin(PI * r), cos(PI * r));
+        }
+        case 9u: {
+            return vec2<f32>(cos(theta) + sin(r), sin(theta) - cos(r)) / (r + 1e-10);
+        }
+        case 10u: {
+            return vec2<f32>(sin(theta) / (r + 1e-10), r * cos(theta));
+        }
+        case 11u: {
+            return vec2<f32>(sin(theta) * cos(r), cos(theta) * sin(r));
+        }
+        case 12u: {
+            let n0 = sin(theta + r);
+            let n1 = cos(theta - r);
+            let m0 = n0 * n0 * n0;
+            let m1 = n1 * n1 * n1;
+            return r * vec2<f32>(m0 + m1, m0 - m1);
+        }
+        case 13u: {
+            // Picks either square root at random. Unlike the other variations, flam3 measures
+            // this angle from the x axis.
+            var angle = 0.5 * atan2(p.y, p.x);
+            if random(seed) < 0.5 {
+                angle += PI;
+            }
+            return sqrt(r) * vec2<f32>(cos(angle), sin(angle));
+        }
+        case 14u: {
+            return p * 4.0 / (r2 + 4.0);
+        }
+        default: {
+            return p * 2.0 / (r + 1.0);
+        }
+    }
+}
+
+fn apply_transform(transform: Transform, p: vec2<f32>, seed: ptr<function, u32>) -> vec2<f32> {
+    let affine = vec2<f32>(dot(transform.x_row, vec3<f32>(p, 1.0)), dot(transform.y_row, vec3<f32>(p, 1.0)));
+    var result = vec2<f32>(0.0, 0.0);
+    for (var variation = 0u; variation < 16u; variation++) {
+        let weight = variation_weight(transform, variation);
+        if weight != 0.0 {
+            result += weight * apply_variation(variation, affine, seed);
+        }
+    }
+    return result;
+}
+
+fn pick_transform(seed: ptr<function, u32>) -> u32 {
+    var total = 0.0;
+    for (var i = 0u; i < flame.transform_count; i++) {
+        total += flame.transforms[i].weight;
+    }
+    var choice = random(seed) * total;
+    for (var i = 0u; i < flame.transform_count; i++) {
+        choice -= flame.transforms[i].weight;
+        if choice < 0.0 {
+            return i;
+        }
+    }
+    return flame.transform_count - 1u;
+}
+
+@compute
+@workgroup_size(64)
+fn cs_main(@builtin(global_invocation_id) id: vec3<u32>) {
+    var seed = hash(id.x ^ hash(batch));
+    var p = vec2<f32>(random(&seed), random(&seed)) * 2.0 - 1.0;
+    var colour = random(&seed);
+
+    for (var i = 0u; i < SKIPPED_ITERATIONS + ITERATIONS; i++) {
+        let transform = flame.transforms[pick_transform(&seed)];
+        p = apply_transform(transform, p, &seed);
+        // Blending towards the transform's colour tints each part by the transforms that
+        // led there
+        colour = mix(colour, transform.colour, transform.colour_speed);
+        if any(abs(p) > vec2<f32>(1e10)) {
+            p = vec2<f32>(random(&seed), random(&seed)) * 2.0 - 1.0;
+            continue;
+        }
+        if i >= SKIPPED_ITERATIONS {
+            plot(p, colour);
+        }
+    }
+}
@@ -0,0 +1,53 @@
+struct Transform {
+    x_row: vec3<f32>,
+    weight: f32,
+    y_row: vec3<f32>,
+    colour: f32,
+    colour_speed: f32,
+    variations: array<vec4<f32>, 4>,
+};
+
+struct FlameUniform {
+    transforms: array<Transform, 12>,
+    palette: array<vec4<f32>, 256>,
+    transform_count: u32,
+    width: u32,
+    height: u32,
+    gamma: f32,
+    brightness: f32,
+};
+@group(0)
+@binding(0)
+var<uniform> flame: FlameUniform;
+@group(0)
+@binding(1)
+var<storage, read> histogram: array<u32>;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+};
+
+@vertex
+fn vs_main(
+    @location(0) pos: vec3<f32>
+) -> VertexOutput {
+    var out: VertexOutput;
+    out.clip_position = vec4<f32>(pos, 1.0);
+    return out;
+}
+
+// Log scales the density of each pixel against the densest pixel, then shades the average
+// colour of the points that landed there by it.
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let index = 4u + (u32(in.clip_position.y) * flame.width + u32(in.clip_position.x)) * 4u;
+    let count = f32(histogram[index]);
+    if count == 0.0 {
+        return output_colour(vec3<f32>(0.0));
+    }
+    let largest = f32(histogram[0]);
+    let sums = vec3<f32>(f32(histogram[index + 1u]), f32(histogram[index + 2u]), f32(histogram[index + 3u]));
+    let colour = sums / (255.0 * count);
+    let alpha = log(1.0 + flame.brightness * count) / log(1.0 + flame.brightness * largest);
+    return output_colour(colour * pow(alpha, 1.0 / flame.gamma));
+}
@@ -0,0 +1,421 @@
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use cgmath::Point2;
+
+use crate::iterations::IterationLimit;
+use crate::perturbation::ReferenceOrbit;
+
+/// What `fs_main` renders.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    EscapeTime,
+    Newton,
+    Lyapunov,
+    Buddhabrot,
+    Mandelbulb,
+    Mandelbox,
+    Flame,
+}
+
+impl Mode {
+    const ALL: [Mode; 7] = [
+        Mode::EscapeTime,
+        Mode::Newton,
+        Mode::Lyapunov,
+        Mode::Buddhabrot,
+        Mode::Mandelbulb,
+        Mode::Mandelbox,
+        Mode::Flame,
+    ];
+
+    pub fn next(self) -> Self {
+        Self::ALL[(self as usize + 1) % Self::ALL.len()]
+    }
+
+    /// Whether the mode plots points with a compute shader, which some adapters can't run.
+    pub fn uses_compute(self) -> bool {
+        matches!(self, Mode::Buddhabrot | Mode::Flame)
+    }
+
+    /// Whether the mode is viewed through [`crate::camera::Camera3d`].
+    pub fn is_3d(self) -> bool {
+        matches!(self, Mode::Mandelbulb | Mode::Mandelbox)
+    }
+
+    /// Where the 2D camera is centred on switching to the mode. The Lyapunov fractal's (a, b)
+    /// are growth rates, which are interesting between 2 and 4 and diverge below 0.
+    pub fn home_2d(self) -> (f64, f64) {
+        match self {
+            Mode::Lyapunov => (3.0, 3.0),
+            _ => (0.0, 0.0),
+        }
+    }
+
+    /// Where the 3D camera starts, far enough back to see the whole object.
+    pub fn home_3d(self) -> (f32, f32, f32) {
+        match self {
+            Mode::Mandelbox => (0.0, 0.0, -12.0),
+            _ => (0.0, 0.0, -2.5),
+        }
+    }
+}
+
+/// The escape-time iteration, matching the cases in `compute_next`. Each is written for the
+/// exponent d, which is 2 for the classic forms.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Formula {
+    /// z^d + c
+    Mandelbrot,
+    /// (|re| + i|im|)^d + c
+    BurningShip,
+    /// conj(z)^d + c, also known as the Mandelbar
+    Tricorn,
+    /// |re(z^d)| + i im(z^d) + c
+    Celtic,
+    /// |re(z^d)| + i|im(z^d)| + c
+    Buffalo,
+    /// (|re| - i im)^d + c
+    Perpendicular,
+    /// Typed into the console, see [`crate::user_formula::UserFormula`].
+    Custom,
+}
+
+impl Formula {
+    const ALL: [Formula; 7] = [
+        Formula::Mandelbrot,
+        Formula::BurningShip,
+        Formula::Tricorn,
+        Formula::Celtic,
+        Formula::Buffalo,
+        Formula::Perpendicular,
+        Formula::Custom,
+    ];
+
+    pub fn next(self) -> Self {
+        Self::ALL[(self as usize + 1) % Self::ALL.len()]
+    }
+}
+
+/// The quantity compared against the bailout radius to decide that an orbit has escaped,
+/// matching the cases in `escape_norm`. The ones other than the Euclidean norm give the
+/// "bailout art" variants, with bands shaped after their level sets.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Norm {
+    /// |z|
+    Euclidean,
+    /// |re|
+    Real,
+    /// |im|
+    Imaginary,
+    /// |re| + |im|
+    Manhattan,
+    /// max(|re|, |im|)
+    Max,
+    /// sqrt(|re·im|), rooted so that like the others it scales with |z|
+    Product,
+}
+
+impl Norm {
+    const ALL: [Norm; 6] = [
+        Norm::Euclidean,
+        Norm::Real,
+        Norm::Imaginary,
+        Norm::Manhattan,
+        Norm::Max,
+        Norm::Product,
+    ];
+
+    pub fn next(self) -> Self {
+        Self::ALL[(self as usize + 1) % Self::ALL.len()]
+    }
+
+    pub fn parse(name: &str) -> Result<Self> {
+        match name.trim() {
+            "euclidean" => Ok(Norm::Euclidean),
+            "re" => Ok(Norm::Real),
+            "im" => Ok(Norm::Imaginary),
+            "manhattan" => Ok(Norm::Manhattan),
+            "max" => Ok(Norm::Max),
+            "product" => Ok(Norm::Product),
+            _ => Err(anyhow!(
+                "unknown norm {:?}, expected euclidean, re, im, manhattan, max or product",
+                name
+            )),
+        }
+    }
+}
+
+/// How `fs_main` colours the escape-time modes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Colouring {
+    /// By the smooth iteration count.
+    Iterations,
+    /// By the distance estimate, which draws the boundary as a line of constant width in
+    /// pixels at any zoom, so that filaments thinner than a pixel still show up.
+    Distance,
+    /// By how close the orbit comes to the [`crate::trap::OrbitTrap`].
+    Trap,
+    /// The statistical colourings average a quantity over the orbit of escaping points, which
+    /// picks out its shape rather than its speed. The triangle inequality average is where each
+    /// |z| falls between the bounds the triangle inequality puts on it.
+    TriangleInequality,
+    /// The average of how sharply the orbit turns at each point.
+    Curvature,
+    /// The average of a sine of the argument of z, which draws stripes
+    /// [`Fractal::stripe_density`] to a turn.
+    Stripe,
+}
+
+impl Colouring {
+    const STATISTICS: [Colouring; 3] = [
+        Colouring::TriangleInequality,
+        Colouring::Curvature,
+        Colouring::Stripe,
+    ];
+
+    /// Cycles through the statistical colourings, starting from the first.
+    pub fn next_statistic(self) -> Self {
+        match Self::STATISTICS
+            .iter()
+            .position(|&colouring| colouring == self)
+        {
+            Some(i) => Self::STATISTICS[(i + 1) % Self::STATISTICS.len()],
+            None => Self::STATISTICS[0],
+        }
+    }
+
+    pub fn parse(name: &str) -> Result<Self> {
+        match name.trim() {
+            "iterations" => Ok(Colouring::Iterations),
+            "distance" => Ok(Colouring::Distance),
+            "trap" => Ok(Colouring::Trap),
+            "tia" => Ok(Colouring::TriangleInequality),
+            "curvature" => Ok(Colouring::Curvature),
+            "stripe" => Ok(Colouring::Stripe),
+            _ => Err(anyhow!(
+                "unknown colouring {:?}, expected iterations, distance, trap, tia, curvature or \
+                 stripe",
+                name
+            )),
+        }
+    }
+}
+
+/// How `fs_main` colours pixels inside the set, matching the cases in `get_interior_colour`.
+/// The period and multiplier need periodicity checking to find the cycle, and only the
+/// multiplier of z² + c is known.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Interior {
+    Flat,
+    /// The period of the attracting cycle, which colours each hyperbolic component evenly.
+    Period,
+    /// The multiplier of the attracting cycle, which shades each component from its nucleus
+    /// out to its edge.
+    Multiplier,
+    /// |z| after the last iteration.
+    FinalMagnitude,
+    /// The mean position of the orbit.
+    AverageOrbit,
+}
+
+impl Interior {
+    const ALL: [Interior; 5] = [
+        Interior::Flat,
+        Interior::Period,
+        Interior::Multiplier,
+        Interior::FinalMagnitude,
+        Interior::AverageOrbit,
+    ];
+
+    pub fn next(self) -> Self {
+        Self::ALL[(self as usize + 1) % Self::ALL.len()]
+    }
+
+    pub fn parse(name: &str) -> Result<Self> {
+        match name.trim() {
+            "flat" => Ok(Interior::Flat),
+            "period" => Ok(Interior::Period),
+            "multiplier" => Ok(Interior::Multiplier),
+            "magnitude" => Ok(Interior::FinalMagnitude),
+            "average" => Ok(Interior::AverageOrbit),
+            _ => Err(anyhow!(
+                "unknown interior colouring {:?}, expected flat, period, multiplier, magnitude \
+                 or average",
+                name
+            )),
+        }
+    }
+}
+
+/// The range the exponent sweeps through while animating.
+const ANIMATED_POWER_RANGE: (f32, f32) = (2.0, 8.0);
+const ANIMATED_POWER_PERIOD: f32 = 20.0;
+
+#[derive(Debug)]
+pub struct Fractal {
+    pub mode: Mode,
+    pub formula: Formula,
+    pub power: f32,
+    pub animate_power: bool,
+    animation_time: f32,
+    pub julia: bool,
+    pub julia_constant: Point2<f64>,
+    pub iterations: IterationLimit,
+    /// Orbits escape once their norm exceeds this. Larger radii cost a few iterations but make
+    /// the smooth colouring more accurate.
+    pub bailout: f32,
+    pub norm: Norm,
+    /// Skips the iteration for points in the main cardioid or the period 2 bulb.
+    pub cardioid_test: bool,
+    /// Stops iterating orbits that come back to an earlier point, recording the period.
+    pub periodicity: bool,
+    pub colouring: Colouring,
+    /// The width of the boundary in pixels when colouring by distance.
+    pub line_width: f32,
+    pub interior: Interior,
+    /// Stripes per turn of the argument of z for the stripe average.
+    pub stripe_density: f32,
+}
+
+impl Fractal {
+    pub fn new() -> Self {
+        Self {
+            mode: Mode::EscapeTime,
+            formula: Formula::Mandelbrot,
+            power: 2.0,
+            animate_power: false,
+            animation_time: 0.0,
+            julia: false,
+            julia_constant: Point2::new(-0.8, 0.156),
+            iterations: IterationLimit::new(),
+            bailout: 16.0,
+            norm: Norm::Euclidean,
+            cardioid_test: true,
+            periodicity: true,
+            colouring: Colouring::Iterations,
+            line_width: 1.0,
+            interior: Interior::Flat,
+            stripe_density: 5.0,
+        }
+    }
+
+    /// Sweeps the exponent back and forth across [`ANIMATED_POWER_RANGE`] while animating.
+    pub fn update(&mut self, dt: Duration) {
+        if !self.animate_power {
+            return;
+        }
+        self.animation_time += dt.as_secs_f32();
+        let (low, high) = ANIMATED_POWER_RANGE;
+        let phase = self.animation_time / ANIMATED_POWER_PERIOD * std::f32::consts::TAU;
+        self.power = low + (high - low) * 0.5 * (1.0 - phase.cos());
+    }
+
+    /// The smoothing takes the logarithm of the radius, so it has to be above 1.
+    pub fn set_bailout(&mut self, arguments: &str) -> Result<()> {
+        match arguments.trim().parse::<f32>() {
+            Ok(radius) if radius > 1.0 && radius.is_finite() => {
+                self.bailout = radius;
+                Ok(())
+            }
+            _ => Err(anyhow!("expected a bailout radius above 1")),
+        }
+    }
+
+    /// Whether the view is the Mandelbrot set itself, z² + c over c, where minibrots can be
+    /// located.
+    pub fn is_mandelbrot_set(&self) -> bool {
+        self.supports_perturbation() && !self.julia
+    }
+
+    /// Whether `ds_compute_iterations` covers the view. It only has the built-in formulas at
+    /// power 2, and anything else is drawn in f32 by the double-single shader.
+    pub fn supports_double_single(&self) -> bool {
+        self.mode == Mode::EscapeTime && self.formula != Formula::Custom && self.power == 2.0
+    }
+
+    /// Only the classic z² + c has a delta iteration for perturbation.
+    pub fn supports_perturbation(&self) -> bool {
+        self.mode == Mode::EscapeTime && self.formula == Formula::Mandelbrot && self.power == 2.0
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct FractalUniform {
+    pub julia_constant: [f64; 2],
+    pub julia: u32,
+    pub reference_length: u32,
+    pub perturbation: u32,
+    pub formula: u32,
+    pub power: f32,
+    pub mode: u32,
+    pub max_iterations: u32,
+    /// Makes `fs_main` count the pixels the limit cuts short.
+    pub adaptive_iterations: u32,
+    pub bailout: f32,
+    pub norm: u32,
+    pub cardioid_test: u32,
+    pub periodicity: u32,
+    pub colouring: u32,
+    pub line_width: f32,
+    pub interior: u32,
+    pub stripe_density: f32,
+}
+
+impl FractalUniform {
+    pub fn new() -> Self {
+        Self {
+            julia_constant: [0.0; 2],
+            julia: 0,
+            reference_length: 0,
+            perturbation: 0,
+            formula: 0,
+            power: 2.0,
+            mode: 0,
+            max_iterations: 200,
+            adaptive_iterations: 0,
+            bailout: 16.0,
+            norm: 0,
+            cardioid_test: 1,
+            periodicity: 1,
+            colouring: 0,
+            line_width: 1.0,
+            interior: 0,
+            stripe_density: 5.0,
+        }
+    }
+
+    /// `preview` shows the Julia set even when the fractal is in Mandelbrot mode, which is used
+    /// while the constant is being picked with the cursor. Passing a reference orbit makes the
+    /// shader render by perturbation around it.
+    pub fn update(&mut self, fractal: &Fractal, preview: bool, reference: Option<&ReferenceOrbit>) {
+        self.julia_constant = fractal.julia_constant.into();
+        self.julia = (fractal.julia || preview) as u32;
+        self.formula = fractal.formula as u32;
+        self.power = fractal.power;
+        self.mode = fractal.mode as u32;
+        self.max_iterations = fractal.iterations.limit;
+        self.adaptive_iterations = fractal.iterations.adaptive as u32;
+        self.bailout = fractal.bailout;
+        self.norm = fractal.norm as u32;
+        self.cardioid_test = fractal.cardioid_test as u32;
+        self.periodicity = fractal.periodicity as u32;
+        // Distance estimation and the triangle inequality work from the derivative and
+        // inverse of z^d + c, so custom formulas are coloured by iterations instead
+        let colouring = match fractal.colouring {
+            Colouring::Distance | Colouring::TriangleInequality
+                if fractal.formula == Formula::Custom =>
+            {
+                Colouring::Iterations
+            }
+            colouring => colouring,
+        };
+        self.colouring = colouring as u32;
+        self.line_width = fractal.line_width;
+        self.stripe_density = fractal.stripe_density;
+        self.interior = fractal.interior as u32;
+        self.perturbation = reference.is_some() as u32;
+        self.reference_length = reference.map_or(0, |orbit| orbit.points.len() as u32);
+    }
+}
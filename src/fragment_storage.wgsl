@@ -0,0 +1,50 @@
+// The fullscreen shader's storage buffers, which some adapters can't bind to fragment shaders.
+// fragment_storage_none.wgsl stands in for this there.
+
+@group(0)
+@binding(1)
+var<storage, read> reference_orbit: array<wide2>;
+
+// Read back by `IterationCounter` to adapt the iteration limit.
+struct IterationCounts {
+    limited: atomic<u32>,
+    late: atomic<u32>,
+};
+@group(0)
+@binding(5)
+var<storage, read_write> iteration_counts: IterationCounts;
+
+struct BuddhabrotUniform {
+    limits: vec3<u32>,
+    anti: u32,
+    width: u32,
+    height: u32,
+};
+@group(3)
+@binding(0)
+var<uniform> buddhabrot: BuddhabrotUniform;
+// Filled in by cs_main in buddhabrot.wgsl
+@group(3)
+@binding(1)
+var<storage, read> histogram: array<u32>;
+
+fn reference_point(i: i32) -> vec2<real> {
+    return unpack2(reference_orbit[i]);
+}
+
+// Tallies a pixel that hit the limit, or escaped late.
+fn count_pixel(limited: bool) {
+    if limited {
+        atomicAdd(&iteration_counts.limited, 1u);
+    } else {
+        atomicAdd(&iteration_counts.late, 1u);
+    }
+}
+
+// Log scales the orbit density of each channel against the densest pixel of that channel.
+fn get_buddhabrot_colour(position: vec2<f32>) -> vec3<f32> {
+    let index = 4u + (u32(position.y) * buddhabrot.width + u32(position.x)) * 3u;
+    let counts = vec3<f32>(f32(histogram[index]), f32(histogram[index + 1u]), f32(histogram[index + 2u]));
+    let largest = vec3<f32>(f32(histogram[0]), f32(histogram[1]), f32(histogram[2]));
+    return log(counts + 1.0) / log(max(largest, vec3<f32>(1.0)) + 1.0);
+}
@@ -0,0 +1,13 @@
+// Stands in for fragment_storage.wgsl on adapters that can't bind storage buffers to fragment
+// shaders. Perturbation, adaptive iteration limits and the Buddhabrot are switched off there, so
+// none of these are reached.
+
+fn reference_point(i: i32) -> vec2<real> {
+    return vec2<real>(real(0.0), real(0.0));
+}
+
+fn count_pixel(limited: bool) {}
+
+fn get_buddhabrot_colour(position: vec2<f32>) -> vec3<f32> {
+    return vec3<f32>(0.0);
+}
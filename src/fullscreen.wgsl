@@ -1,12 +1,113 @@
+struct FractalUniform {
+    julia_constant: wide2,
+    julia: u32,
+    reference_length: u32,
+    perturbation: u32,
+    formula: u32,
+    power: f32,
+    mode: u32,
+    max_iterations: u32,
+    adaptive_iterations: u32,
+    bailout: f32,
+    norm: u32,
+    cardioid_test: u32,
+    periodicity: u32,
+    colouring: u32,
+    line_width: f32,
+    interior: u32,
+    stripe_density: f32,
+};
+@group(0)
+@binding(0)
+var<uniform> fractal: FractalUniform;
+
+struct NewtonUniform {
+    coefficients: array<wide2, 9>,
+    roots: array<wide2, 8>,
+    nova_start: wide2,
+    degree: u32,
+    relaxation: f32,
+    nova: u32,
+};
+@group(0)
+@binding(2)
+var<uniform> newton: NewtonUniform;
+
+struct LyapunovUniform {
+    sequence: u32,
+    sequence_length: u32,
+    warmup: u32,
+    stable_colour: vec3<f32>,
+    chaotic_colour: vec3<f32>,
+};
+@group(0)
+@binding(3)
+var<uniform> lyapunov: LyapunovUniform;
+
+struct RaymarchUniform {
+    bulb_power: f32,
+    box_scale: f32,
+    fold_limit: f32,
+    min_radius: f32,
+    fixed_radius: f32,
+    chain: u32,
+    chain_length: u32,
+};
+@group(0)
+@binding(4)
+var<uniform> raymarch: RaymarchUniform;
+
+struct TrapUniform {
+    position: vec2<f32>,
+    // The cosine and sine of the rotation
+    rotation: vec2<f32>,
+    scale: f32,
+    shape: u32,
+};
+@group(0)
+@binding(6)
+var<uniform> trap: TrapUniform;
+@group(0)
+@binding(7)
+var trap_image: texture_2d<f32>;
+@group(0)
+@binding(8)
+var trap_sampler: sampler;
+
+struct PaletteUniform {
+    offset: f32,
+    scale: f32,
+    repeat: u32,
+};
+@group(0)
+@binding(9)
+var<uniform> palette: PaletteUniform;
+@group(0)
+@binding(10)
+var palette_texture: texture_1d<f32>;
+
 struct CameraUniform {
-    pos: vec2<f64>,
+    pos: wide2,
     zoom: f32,
     aspect: f32,
+    scale: wide,
+    offset: wide2,
 };
 @group(1)
 @binding(0)
 var<uniform> camera: CameraUniform;
 
+struct Camera3dUniform {
+    position: vec3<f32>,
+    focal_length: f32,
+    forward: vec3<f32>,
+    right: vec3<f32>,
+    up: vec3<f32>,
+};
+@group(1)
+@binding(1)
+var<uniform> camera_3d: Camera3dUniform;
+
 struct VertexOutput {
     @builtin(position) clip_position: vec4<f32>,
     @location(0) tex_coords: vec2<f32>,
@@ -25,6 +126,7 @@ fn vs_main(
 // Fragment shader
 
 struct FragmentInput {
+    @builtin(position) position: vec4<f32>,
     @location(0) tex_coords: vec2<f32>,
 };
 
@@ -32,62 +134,994 @@ struct FragmentInput {
 @binding(0)
 var<uniform> frame_count: f32;
 
-fn compute_next(current: vec2<f64>, constant: vec2<f64>) -> vec2<f64> {
-    let zr = current.x * current.x - current.y * current.y;
-    let zi = current.x * current.y * f64(2.0);
-    return vec2<f64>(zr, zi) + constant;
+fn complex_mul(a: vec2<real>, b: vec2<real>) -> vec2<real> {
+    return vec2<real>(a.x * b.x - a.y * b.y, a.x * b.y + a.y * b.x);
+}
+
+fn complex_div(a: vec2<real>, b: vec2<real>) -> vec2<real> {
+    let denominator = b.x * b.x + b.y * b.y;
+    return vec2<real>(a.x * b.x + a.y * b.y, a.y * b.x - a.x * b.y) / denominator;
+}
+
+// Integer exponents are raised by repeated multiplication to keep full precision. Anything
+// else goes through the polar form, which is only available in f32.
+fn complex_pow(z: vec2<real>, power: f32) -> vec2<real> {
+    let whole = i32(power);
+    if f32(whole) == power && whole >= 1 {
+        var result = z;
+        for (var i = 1; i < whole; i++) {
+            result = complex_mul(result, z);
+        }
+        return result;
+    }
+
+    let radius = pow(f32(z.x * z.x + z.y * z.y), power * 0.5);
+    let angle = atan2(f32(z.y), f32(z.x)) * power;
+    return vec2<real>(vec2<f32>(cos(angle), sin(angle)) * radius);
+}
+
+// `user_formula` is generated from the formula typed into the console and appended to this
+// file, along with the functions in user_formula.wgsl.
+fn compute_next(current: vec2<real>, constant: vec2<real>) -> vec2<real> {
+    if fractal.formula == 6u {
+        return user_formula(current, constant);
+    }
+
+    var z = current;
+    switch fractal.formula {
+        // Burning Ship
+        case 1u: {
+            z = abs(z);
+        }
+        // Tricorn
+        case 2u: {
+            z.y = -z.y;
+        }
+        // Perpendicular
+        case 5u: {
+            z = vec2<real>(abs(z.x), -z.y);
+        }
+        default: {}
+    }
+
+    if fractal.power == 2.0 {
+        z = vec2<real>(z.x * z.x - z.y * z.y, z.x * z.y * real(2.0));
+    } else {
+        z = complex_pow(z, fractal.power);
+    }
+
+    switch fractal.formula {
+        // Celtic
+        case 3u: {
+            z.x = abs(z.x);
+        }
+        // Buffalo
+        case 4u: {
+            z = abs(z);
+        }
+        default: {}
+    }
+    return z + constant;
+}
+
+// Orbits past this |z| have escaped whatever the norm. The axis and product norms can stay
+// small while the orbit runs off along an axis, until it overflows.
+const ESCAPE_LIMIT: f32 = 1e16;
+
+// The quantity compared against the bailout radius, matching `Norm`. Each scales with |z|, so
+// far out every iteration raises it to roughly the power d, whichever is chosen.
+fn escape_norm(z: vec2<real>) -> f32 {
+    let w = abs(vec2<f32>(z));
+    if fractal.norm != 0u && length(w) > ESCAPE_LIMIT {
+        // Past the bailout by a whole iteration for d = 2, which keeps the smoothing finite
+        return max(fractal.bailout * fractal.bailout, chosen_norm(w));
+    }
+    return chosen_norm(w);
+}
+
+fn chosen_norm(w: vec2<f32>) -> f32 {
+    switch fractal.norm {
+        case 1u: {
+            return w.x;
+        }
+        case 2u: {
+            return w.y;
+        }
+        case 3u: {
+            return w.x + w.y;
+        }
+        case 4u: {
+            return max(w.x, w.y);
+        }
+        case 5u: {
+            return sqrt(w.x * w.y);
+        }
+        default: {
+            return length(w);
+        }
+    }
+}
+
+// The point of an orbit closest to the trap so far, in the trap's frame.
+struct TrapHit {
+    distance: f32,
+    point: vec2<f32>,
+};
+
+fn no_trap_hit() -> TrapHit {
+    return TrapHit(3.4e38, vec2<f32>(0.0));
+}
+
+// Moves z into the trap's frame, where the trap is centred on 0 with scale 1.
+fn trap_coordinates(z: vec2<real>) -> vec2<f32> {
+    let offset = (vec2<f32>(z) - trap.position) / trap.scale;
+    let rotation = trap.rotation;
+    return vec2<f32>(
+        rotation.x * offset.x + rotation.y * offset.y,
+        rotation.x * offset.y - rotation.y * offset.x,
+    );
+}
+
+// Where a point in the trap's frame falls on the image, which covers the square from -1 to 1.
+fn trap_image_coordinates(point: vec2<f32>) -> vec2<f32> {
+    return vec2<f32>(0.5, -0.5) * point + 0.5;
+}
+
+// The distance from a point in the trap's frame to the shape, matching `TrapShape`. For the
+// image it is the transparency under the point, so opaque pixels are at 0.
+fn trap_distance(point: vec2<f32>) -> f32 {
+    switch trap.shape {
+        case 1u: {
+            return abs(point.y);
+        }
+        case 2u: {
+            return min(abs(point.x), abs(point.y));
+        }
+        case 3u: {
+            return abs(length(point) - 1.0);
+        }
+        case 4u: {
+            if any(abs(point) > vec2<f32>(1.0)) {
+                return 1.0;
+            }
+            let texel = textureSampleLevel(trap_image, trap_sampler, trap_image_coordinates(point), 0.0);
+            return 1.0 - texel.a;
+        }
+        default: {
+            return length(point);
+        }
+    }
+}
+
+// Keeps whichever point of the orbit has come closest to the trap, the earliest if tied.
+fn next_trap_hit(hit: TrapHit, z: vec2<real>) -> TrapHit {
+    if fractal.colouring != 2u {
+        return hit;
+    }
+    let point = trap_coordinates(z);
+    let distance = trap_distance(point);
+    if distance < hit.distance {
+        return TrapHit(distance, point);
+    }
+    return hit;
+}
+
+// The running sum of a statistical colouring's terms, matching `Colouring`. The sum before the
+// last term is kept too, so that the average can be interpolated like the smooth iteration
+// count.
+struct Statistic {
+    sum: f32,
+    previous_sum: f32,
+    count: f32,
+    // The orbit's last two points, z_{n-1} and z_{n-2}
+    last: vec2<f32>,
+    before_last: vec2<f32>,
+    // How many points of the orbit have been seen
+    points: i32,
+};
+
+fn new_statistic(z0: vec2<real>) -> Statistic {
+    return Statistic(0.0, 0.0, 0.0, vec2<f32>(z0), vec2<f32>(z0), 1);
+}
+
+// The term a point of the orbit adds to the statistic, or -1 where it isn't defined.
+fn statistic_term(z: vec2<f32>, statistic: Statistic) -> f32 {
+    let last = statistic.last;
+    switch fractal.colouring {
+        // Triangle inequality average: the triangle inequality puts |z| between
+        // ||z_{n-1}^d| - |c|| and |z_{n-1}^d| + |c|. c is recovered from the last step, which
+        // also works for the perturbed iteration, where c isn't known absolutely.
+        case 3u: {
+            let power = vec2<f32>(complex_pow(vec2<real>(last), fractal.power));
+            let c = length(z - power);
+            let lower = abs(length(power) - c);
+            let upper = length(power) + c;
+            if upper - lower <= 0.0 {
+                return -1.0;
+            }
+            return (length(z) - lower) / (upper - lower);
+        }
+        // Curvature average: the angle the orbit turns through, from 0 for carrying straight
+        // on to 1 for doubling back
+        case 4u: {
+            if statistic.points < 2 {
+                return -1.0;
+            }
+            let step = z - last;
+            let previous_step = last - statistic.before_last;
+            let cross = previous_step.x * step.y - previous_step.y * step.x;
+            return abs(atan2(cross, dot(previous_step, step))) / 3.14159265;
+        }
+        // Stripe average
+        default: {
+            return 0.5 + 0.5 * sin(fractal.stripe_density * atan2(z.y, z.x));
+        }
+    }
+}
+
+fn next_statistic(statistic: Statistic, z: vec2<real>) -> Statistic {
+    if fractal.colouring < 3u {
+        return statistic;
+    }
+    let point = vec2<f32>(z);
+    var next = statistic;
+    let term = statistic_term(point, statistic);
+    if term >= 0.0 {
+        next.previous_sum = statistic.sum;
+        next.sum += term;
+        next.count += 1.0;
+    }
+    next.before_last = statistic.last;
+    next.last = point;
+    next.points += 1;
+    return next;
+}
+
+// The average with and without the last term, mixed by how far into its last iteration the
+// orbit escaped, which joins up the bands between neighbouring iteration counts.
+fn average_statistic(statistic: Statistic, norm: f32) -> f32 {
+    if statistic.count < 2.0 {
+        return 0.0;
+    }
+    let average = statistic.sum / statistic.count;
+    let previous_average = statistic.previous_sum / (statistic.count - 1.0);
+    return mix(previous_average, average, clamp(1.0 - escape_overshoot(norm), 0.0, 1.0));
+}
+
+// What the escape-time iteration found out about a pixel's orbit.
+struct Orbit {
+    // The smooth iteration count as a fraction of the limit, or -1 for pixels inside the set
+    iterations: f32,
+    // The period of the cycle an inside orbit was caught in, or 0 if none was detected
+    period: u32,
+    // The estimated distance to the boundary in the units of tex_coords, or -1 if it isn't
+    // known. Only estimated when colouring by distance.
+    distance: f32,
+    // Where the orbit ended up
+    z: vec2<f32>,
+    // The mean of the points of an inside orbit, when colouring the interior by it
+    average: vec2<f32>,
+    // The multiplier of the cycle an inside orbit was caught in, when colouring the interior
+    // by it
+    multiplier: vec2<f32>,
+    // Where the orbit came closest to the trap, when colouring by it
+    trap: TrapHit,
+    // The average of a statistical colouring for an escaped orbit, when colouring by one
+    statistic: f32,
+    // Whether the orbit ran to the iteration limit, rather than escaping or being caught by the
+    // interior checks
+    limited: bool,
+};
+
+fn escaped_orbit(iteration: i32, norm: f32, max_iteration: i32, z: vec2<real>, derivative: vec2<real>, trap_hit: TrapHit, statistic: Statistic) -> Orbit {
+    var distance = -1.0;
+    if fractal.colouring == 1u {
+        distance = exterior_distance(z, derivative);
+    }
+    let unknown = vec2<f32>(0.0);
+    return Orbit(smooth_iterations(iteration, norm, max_iteration), 0u, distance, vec2<f32>(z), unknown, unknown, trap_hit, average_statistic(statistic, norm), false);
+}
+
+// Advances the derivative of z with respect to c, or to z0 for Julia sets, as for z^d + c. The
+// other formulas fold z, which turns the derivative but keeps its length close to this.
+fn next_derivative(z: vec2<real>, derivative: vec2<real>) -> vec2<real> {
+    var next: vec2<real>;
+    if fractal.power == 2.0 {
+        next = real(2.0) * complex_mul(z, derivative);
+    } else {
+        next = real(fractal.power) * complex_mul(complex_pow(z, fractal.power - 1.0), derivative);
+    }
+    if fractal.julia == 0u {
+        next.x += real(1.0);
+    }
+    return next;
+}
+
+// The exterior distance estimate |z| log|z| / |dz|, which is within a factor of two of the
+// distance to the set.
+fn exterior_distance(z: vec2<real>, derivative: vec2<real>) -> f32 {
+    let z_length = sqrt(z.x * z.x + z.y * z.y);
+    let derivative_length = sqrt(derivative.x * derivative.x + derivative.y * derivative.y);
+    return f32(z_length * real(log(f32(z_length))) / (derivative_length * unpack(camera.scale)));
+}
+
+// The square root with a non-negative real part.
+fn principal_sqrt(w: vec2<real>) -> vec2<real> {
+    let w_length = sqrt(w.x * w.x + w.y * w.y);
+    let root = vec2<real>(sqrt((w_length + w.x) * real(0.5)), sqrt((w_length - w.x) * real(0.5)));
+    return select(root, vec2<real>(root.x, -root.y), w.y < real(0.0));
+}
+
+// A point of the attracting cycle of c in the main cardioid or the period 2 bulb.
+fn component_cycle_point(c: vec2<real>, period: u32) -> vec2<real> {
+    let one = vec2<real>(real(1.0), real(0.0));
+    if period == 1u {
+        return (one - principal_sqrt(one - real(4.0) * c)) * real(0.5);
+    }
+    return (principal_sqrt(real(-4.0) * c - real(3.0) * one) - one) * real(0.5);
+}
+
+// Whether the cycles are those of z^2 + c, which the interior calculations assume.
+fn is_quadratic() -> bool {
+    return fractal.formula == 0u && fractal.power == 2.0;
+}
+
+// Refines a point near the attracting cycle of z^2 + c onto it with Newton's method.
+fn refine_cycle_point(z: vec2<real>, c: vec2<real>, period: u32) -> vec2<real> {
+    let one = vec2<real>(real(1.0), real(0.0));
+    var z0 = z;
+    for (var step = 0; step < 8; step++) {
+        var zn = z0;
+        var dz = one;
+        for (var i = 0u; i < period; i++) {
+            dz = real(2.0) * complex_mul(zn, dz);
+            zn = complex_mul(zn, zn) + c;
+        }
+        z0 -= complex_div(zn - z0, dz - one);
+    }
+    return z0;
+}
+
+// The derivative of the period-th iterate around the cycle through z0, which is 0 at the
+// nucleus of a hyperbolic component and has magnitude 1 at its edge.
+fn cycle_multiplier(z0: vec2<real>, c: vec2<real>, period: u32) -> vec2<f32> {
+    var zn = z0;
+    var multiplier = vec2<real>(real(1.0), real(0.0));
+    for (var i = 0u; i < period; i++) {
+        multiplier = real(2.0) * complex_mul(zn, multiplier);
+        zn = complex_mul(zn, zn) + c;
+    }
+    return vec2<f32>(multiplier);
+}
+
+// The distance from c to the edge of its hyperbolic component, for the Mandelbrot set, from the
+// derivatives of the period-th iterate around the cycle through z0:
+// (1 - |dz|^2) / |dcdz + dzdz dc / (1 - dz)|.
+fn interior_distance(z0: vec2<real>, c: vec2<real>, period: u32) -> f32 {
+    let one = vec2<real>(real(1.0), real(0.0));
+    var zn = z0;
+    var dz = one;
+    var dc = vec2<real>(real(0.0), real(0.0));
+    var dzdz = dc;
+    var dcdz = dc;
+    for (var i = 0u; i < period; i++) {
+        dcdz = real(2.0) * (complex_mul(zn, dcdz) + complex_mul(dz, dc));
+        dzdz = real(2.0) * (complex_mul(dz, dz) + complex_mul(zn, dzdz));
+        dc = real(2.0) * complex_mul(zn, dc) + one;
+        dz = real(2.0) * complex_mul(zn, dz);
+        zn = complex_mul(zn, zn) + c;
+    }
+    let denominator = dcdz + complex_div(complex_mul(dzdz, dc), one - dz);
+    let numerator = real(1.0) - (dz.x * dz.x + dz.y * dz.y);
+    let denominator_length = sqrt(denominator.x * denominator.x + denominator.y * denominator.y);
+    return f32(numerator / (denominator_length * unpack(camera.scale)));
+}
+
+// Fills in what the colourings need to know about an orbit that didn't escape. z is where it
+// ended up, in a cycle of the given period if one was detected, sum is the total of its points
+// over the iterations, and trap_hit is where it came closest to the trap. Orbits without a
+// period ran to the limit. The distance estimate needs z and c to the precision of the
+// pixels, so callers that only have them roughly leave it out.
+fn interior_orbit(z: vec2<real>, c: vec2<real>, period: u32, sum: vec2<real>, iterations: i32, trap_hit: TrapHit, estimate_distance: bool) -> Orbit {
+    var orbit = Orbit(-1.0, period, -1.0, vec2<f32>(z), vec2<f32>(sum / real(max(iterations, 1))), vec2<f32>(0.0), trap_hit, 0.0, period == 0u);
+    let multiplier = fractal.interior == 2u;
+    let distance = estimate_distance && fractal.colouring == 1u && fractal.julia == 0u;
+    if period == 0u || !is_quadratic() || !(multiplier || distance) {
+        return orbit;
+    }
+    let z0 = refine_cycle_point(z, c, period);
+    if multiplier {
+        orbit.multiplier = cycle_multiplier(z0, c, period);
+    }
+    if distance {
+        orbit.distance = interior_distance(z0, c, period);
+    }
+    return orbit;
+}
+
+// Orbits that come back within this fraction of the view's half height of an earlier point
+// are taken to be periodic.
+const PERIODICITY_TOLERANCE = 1e-5;
+
+// The perturbed iteration has z to within a few ulps, so its cycles are matched to this many.
+const PERTURBED_PERIODICITY_ULPS = 1024.0;
+
+// The final |z| and average orbit colourings need the orbit itself rather than wherever it was
+// when its cycle was found, so it isn't cut short for them.
+fn checks_periodicity() -> bool {
+    return fractal.periodicity != 0u && fractal.interior < 3u;
+}
+
+fn periodicity_epsilon() -> real {
+    return real(PERIODICITY_TOLERANCE) * unpack(camera.scale);
+}
+
+// The closed-form tests for the main cardioid and the period 2 bulb, which between them hold
+// most of the interior at shallow zooms. Returns the period of the component c is in, or 0.
+// Points within margin of the boundaries are left to the iteration, for callers that can only
+// evaluate the tests roughly. The final |z|, average orbit and trap colourings need the orbit
+// itself, so the tests are skipped for them.
+fn known_component(c: vec2<real>, margin: f32) -> u32 {
+    if fractal.cardioid_test == 0u || !is_quadratic() || fractal.julia != 0u || fractal.interior >= 3u || fractal.colouring == 2u {
+        return 0u;
+    }
+    let x = c.x - real(0.25);
+    let q = x * x + c.y * c.y;
+    if q * (q + x) <= real(0.25) * c.y * c.y - real(margin) {
+        return 1u;
+    }
+    let bulb_x = c.x + real(1.0);
+    if bulb_x * bulb_x + c.y * c.y <= real(0.0625) - real(margin) {
+        return 2u;
+    }
+    return 0u;
+}
+
+// How far past the bailout radius R an orbit's norm was when it escaped, as a fraction of an
+// iteration. The norm grows by a power of d per iteration, so log_d(log(norm) / log(R)) runs
+// from 0 to 1 as the norm at escape runs from R up to R^d. Custom formulas needn't grow like
+// that, so their counts aren't smoothed.
+fn escape_overshoot(norm: f32) -> f32 {
+    if fractal.formula == 6u {
+        return 0.0;
+    }
+    return log2(log(norm) / log(fractal.bailout)) / log2(fractal.power);
 }
 
-fn compute_iterations(z0: vec2<f64>, constant: vec2<f64>, max_iteration: i32) -> f32 {
+// Fractional iteration count for an orbit whose norm first exceeded the bailout radius R at
+// the given iteration. n + 1 minus the overshoot runs from n to n + 1 as the norm at escape
+// runs from R^d down to R, which joins up the bands of neighbouring counts.
+fn smooth_iterations(iteration: i32, norm: f32, max_iteration: i32) -> f32 {
+    let smooth_iteration = max(f32(iteration) + 1.0 - escape_overshoot(norm), 0.0);
+    return smooth_iteration / f32(max_iteration);
+}
+
+fn compute_iterations(z0: vec2<real>, constant: vec2<real>, max_iteration: i32) -> Orbit {
+    let component = known_component(constant, 0.0);
+    if component != 0u {
+        let cycle_point = component_cycle_point(constant, component);
+        return interior_orbit(cycle_point, constant, component, cycle_point, 1, no_trap_hit(), true);
+    }
+
+    let epsilon = periodicity_epsilon();
     var zn = z0;
+    var derivative = vec2<real>(real(f32(fractal.julia != 0u)), real(0.0));
+    var sum = vec2<real>(real(0.0), real(0.0));
+    var trap_hit = no_trap_hit();
+    var statistic = new_statistic(zn);
+    var check = zn;
+    var check_iteration = 0;
     var iteration = 0;
-    var length = zn.x * zn.x + zn.y * zn.y;
-    var is_inside = true;
+    var norm = escape_norm(zn);
     while iteration < max_iteration {
-        if length > f64(5.0) {
-            is_inside = false;
-            break;
+        if norm > fractal.bailout {
+            return escaped_orbit(iteration, norm, max_iteration, zn, derivative, trap_hit, statistic);
+        }
+        if fractal.colouring == 1u {
+            derivative = next_derivative(zn, derivative);
         }
         zn = compute_next(zn, constant);
-        length = zn.x * zn.x + zn.y * zn.y;
+        norm = escape_norm(zn);
         iteration += 1;
+        if fractal.interior == 4u {
+            sum += zn;
+        }
+        trap_hit = next_trap_hit(trap_hit, zn);
+        statistic = next_statistic(statistic, zn);
+
+        // Brent's method: the orbit is compared against a checkpoint that moves up to it at
+        // every power of two, so once the gap outgrows the cycle the first match gives its
+        // period
+        if checks_periodicity() {
+            let difference = zn - check;
+            if difference.x * difference.x + difference.y * difference.y < epsilon * epsilon {
+                return interior_orbit(zn, constant, u32(iteration - check_iteration), sum, iteration, trap_hit, true);
+            }
+            if (iteration & (iteration - 1)) == 0 {
+                check = zn;
+                check_iteration = iteration;
+            }
+        }
     }
+    return interior_orbit(zn, constant, 0u, sum, iteration, trap_hit, true);
+}
+
+// Iterates the offset of a pixel from the reference orbit, using
+// dz' = 2 * Z * dz + dz^2 + dc. Whenever the pixel's orbit passes closer to zero than its offset
+// (where the offset would lose precision), or the reference orbit runs out, the offset is
+// rebased onto the start of the reference orbit instead of being iterated further. z is only
+// known to the precision of a real rather than of the pixels here, so the closed-form interior
+// tests and the distance estimate are skipped, and periodicity is checked to a tolerance no
+// finer than z's rounding.
+fn compute_iterations_perturbed(dz0: vec2<real>, dc: vec2<real>, c: vec2<real>, max_iteration: i32) -> Orbit {
+    let last_reference = i32(fractal.reference_length) - 1;
+    let epsilon = max(periodicity_epsilon(), real(PERTURBED_PERIODICITY_ULPS * REAL_EPSILON));
+    var dz = dz0;
+    var reference_iteration = 0;
+    var zn = reference_point(0) + dz;
+    var derivative = vec2<real>(real(f32(fractal.julia != 0u)), real(0.0));
+    var sum = vec2<real>(real(0.0), real(0.0));
+    var trap_hit = no_trap_hit();
+    var statistic = new_statistic(zn);
+    var check = zn;
+    var check_iteration = 0;
+    var norm = escape_norm(zn);
+    var iteration = 0;
+    while iteration < max_iteration {
+        if norm > fractal.bailout {
+            return escaped_orbit(iteration, norm, max_iteration, zn, derivative, trap_hit, statistic);
+        }
+        if fractal.colouring == 1u {
+            derivative = next_derivative(zn, derivative);
+        }
+        let length = zn.x * zn.x + zn.y * zn.y;
+        if reference_iteration >= last_reference || length < dz.x * dz.x + dz.y * dz.y {
+            dz = zn - reference_point(0);
+            reference_iteration = 0;
+        }
+        dz = complex_mul(real(2.0) * reference_point(reference_iteration) + dz, dz) + dc;
+        reference_iteration += 1;
+        zn = reference_point(reference_iteration) + dz;
+        norm = escape_norm(zn);
+        iteration += 1;
+        if fractal.interior == 4u {
+            sum += zn;
+        }
+        trap_hit = next_trap_hit(trap_hit, zn);
+        statistic = next_statistic(statistic, zn);
 
-    if is_inside {
-        return -1.0;
+        if checks_periodicity() {
+            let difference = zn - check;
+            if difference.x * difference.x + difference.y * difference.y < epsilon * epsilon {
+                return interior_orbit(zn, c, u32(iteration - check_iteration), sum, iteration, trap_hit, false);
+            }
+            if (iteration & (iteration - 1)) == 0 {
+                check = zn;
+                check_iteration = iteration;
+            }
+        }
     }
+    return interior_orbit(zn, c, 0u, sum, iteration, trap_hit, false);
+}
 
-    zn = compute_next(zn, constant);
-    length = zn.x * zn.x + zn.y * zn.y;
-    iteration += 1;
-    zn = compute_next(zn, constant);
-    length = zn.x * zn.x + zn.y * zn.y;
-    iteration += 1;
+// Runs z <- z - R * p(z) / p'(z) + c until the steps become negligible. Returns the index of
+// the nearest root, or -1 if the orbit didn't settle, along with the smoothed iteration count.
+fn newton_iterations(z0: vec2<real>, constant: vec2<real>, max_iteration: i32) -> vec2<f32> {
+    let tolerance = real(1e-12);
+    var z = z0;
+    var step_length = real(1.0);
+    var iteration = 0;
+    while iteration < max_iteration {
+        // Horner's method for p and p' together
+        var p = unpack2(newton.coefficients[newton.degree]);
+        var dp = vec2<real>(real(0.0), real(0.0));
+        for (var i = i32(newton.degree) - 1; i >= 0; i--) {
+            dp = complex_mul(dp, z) + p;
+            p = complex_mul(p, z) + unpack2(newton.coefficients[i]);
+        }
 
-    let smooth_iteration = f32(iteration) - log2(max(1.0, log2(f32(length))));
-    return smooth_iteration / f32(max_iteration);
+        let step = constant - complex_div(p, dp) * real(newton.relaxation);
+        z += step;
+        iteration += 1;
+        step_length = step.x * step.x + step.y * step.y;
+        if step_length < tolerance {
+            break;
+        }
+    }
+
+    if step_length >= tolerance {
+        return vec2<f32>(-1.0, f32(iteration));
+    }
+
+    var root = 0u;
+    var root_distance = real(1e30);
+    for (var i = 0u; i < newton.degree; i++) {
+        let offset = z - unpack2(newton.roots[i]);
+        let distance = offset.x * offset.x + offset.y * offset.y;
+        if distance < root_distance {
+            root = i;
+            root_distance = distance;
+        }
+    }
+
+    // Convergence is quadratic, so the log of the last step roughly doubles every iteration
+    let overshoot = log(f32(max(step_length, real(1e-30)))) / log(f32(tolerance));
+    return vec2<f32>(f32(root), f32(iteration) - log2(overshoot));
+}
+
+// A bright colour going once round the colour wheel as hue goes from 0 to 1.
+fn hue_colour(hue: f32) -> vec3<f32> {
+    return 0.5 + 0.5 * cos(6.28318 * (hue + vec3<f32>(0.0, 0.33, 0.67)));
+}
+
+fn get_basin_colour(root: i32, iterations: f32) -> vec3<f32> {
+    if root < 0 {
+        return vec3<f32>(0.0, 0.0, 0.0);
+    }
+    let colour = hue_colour(f32(root) / f32(newton.degree));
+    return colour * pow(0.92, max(iterations, 0.0));
+}
+
+// Drives the logistic map x <- r * x * (1 - x) with r switching between a and b as the
+// sequence dictates, returning the Lyapunov exponent averaged over max_iteration steps.
+fn lyapunov_exponent(ab: vec2<f32>, max_iteration: i32) -> f32 {
+    var x = 0.5;
+    var letter = 0u;
+    var total = 0.0;
+    for (var i = 0; i < i32(lyapunov.warmup) + max_iteration; i++) {
+        var r = ab.x;
+        if ((lyapunov.sequence >> letter) & 1u) != 0u {
+            r = ab.y;
+        }
+        letter = (letter + 1u) % lyapunov.sequence_length;
+
+        x = r * x * (1.0 - x);
+        if i >= i32(lyapunov.warmup) {
+            total += log(max(abs(r * (1.0 - 2.0 * x)), 1e-30));
+        }
+    }
+    return total / f32(max_iteration);
+}
+
+// Stable regions, where the exponent is negative, shade from the stable colour to black.
+// Chaotic regions shade from black to the chaotic colour.
+fn get_lyapunov_colour(exponent: f32) -> vec3<f32> {
+    if exponent < 0.0 {
+        return lyapunov.stable_colour * (1.0 - exp(exponent));
+    }
+    return lyapunov.chaotic_colour * (1.0 - exp(-exponent * 2.0));
+}
+
+// Raises z to the power in spherical coordinates, given its length r.
+fn bulb_pow(z: vec3<f32>, r: f32, power: f32) -> vec3<f32> {
+    let theta = acos(clamp(z.z / max(r, 1e-6), -1.0, 1.0)) * power;
+    let phi = atan2(z.y, z.x) * power;
+    return pow(r, power) * vec3<f32>(sin(theta) * cos(phi), sin(theta) * sin(phi), cos(theta));
+}
+
+// Distance estimate for the Mandelbulb, iterating z <- z^power + p. The second component is an
+// orbit trap used for colouring.
+fn mandelbulb_distance(p: vec3<f32>) -> vec2<f32> {
+    let power = raymarch.bulb_power;
+    var z = p;
+    var dr = 1.0;
+    var r = length(z);
+    var trap = r;
+    for (var i = 0; i < 16; i++) {
+        if r > 2.0 {
+            break;
+        }
+        dr = pow(r, power - 1.0) * power * dr + 1.0;
+        z = bulb_pow(z, r, power) + p;
+        r = length(z);
+        trap = min(trap, r);
+    }
+    return vec2<f32>(0.5 * log(max(r, 1e-6)) * r / dr, trap);
+}
+
+// Distance estimate for the Mandelbox and other box fold formulas, running the chain of steps
+// once per iteration. dr tracks how much the steps have stretched space.
+fn mandelbox_distance(p: vec3<f32>) -> vec2<f32> {
+    let min_radius2 = raymarch.min_radius * raymarch.min_radius;
+    let fixed_radius2 = raymarch.fixed_radius * raymarch.fixed_radius;
+    var z = p;
+    var dr = 1.0;
+    var trap = dot(z, z);
+    for (var i = 0; i < 12; i++) {
+        for (var step = 0u; step < raymarch.chain_length; step++) {
+            switch (raymarch.chain >> (2u * step)) & 3u {
+                case 0u: {
+                    z = clamp(z, vec3<f32>(-raymarch.fold_limit), vec3<f32>(raymarch.fold_limit)) * 2.0 - z;
+                }
+                case 1u: {
+                    let r2 = dot(z, z);
+                    var factor = 1.0;
+                    if r2 < min_radius2 {
+                        factor = fixed_radius2 / min_radius2;
+                    } else if r2 < fixed_radius2 {
+                        factor = fixed_radius2 / r2;
+                    }
+                    z *= factor;
+                    dr *= factor;
+                }
+                case 2u: {
+                    z = z * raymarch.box_scale + p;
+                    dr = dr * abs(raymarch.box_scale) + 1.0;
+                }
+                default: {
+                    let r = length(z);
+                    dr = pow(r, raymarch.bulb_power - 1.0) * raymarch.bulb_power * dr + 1.0;
+                    z = bulb_pow(z, r, raymarch.bulb_power) + p;
+                }
+            }
+        }
+        let r2 = dot(z, z);
+        trap = min(trap, r2);
+        if r2 > 1e4 {
+            break;
+        }
+    }
+    return vec2<f32>(length(z) / abs(dr), sqrt(trap));
+}
+
+fn scene_distance(p: vec3<f32>) -> vec2<f32> {
+    if fractal.mode == 5u {
+        return mandelbox_distance(p);
+    }
+    return mandelbulb_distance(p);
+}
+
+fn scene_normal(p: vec3<f32>, epsilon: f32) -> vec3<f32> {
+    let e = vec2<f32>(epsilon, 0.0);
+    return normalize(vec3<f32>(
+        scene_distance(p + e.xyy).x - scene_distance(p - e.xyy).x,
+        scene_distance(p + e.yxy).x - scene_distance(p - e.yxy).x,
+        scene_distance(p + e.yyx).x - scene_distance(p - e.yyx).x,
+    ));
+}
+
+// Marches towards the light, darkening by how closely the ray grazes the surface on the way.
+fn soft_shadow(origin: vec3<f32>, direction: vec3<f32>, sharpness: f32) -> f32 {
+    var light = 1.0;
+    var t = 0.01;
+    for (var i = 0; i < 64; i++) {
+        let distance = scene_distance(origin + direction * t).x;
+        if distance < 1e-4 {
+            return 0.0;
+        }
+        light = min(light, sharpness * distance / t);
+        t += clamp(distance, 0.005, 0.2);
+        if t > 5.0 {
+            break;
+        }
+    }
+    return clamp(light, 0.0, 1.0);
 }
 
-fn get_colour(iterations: f32) -> vec3<f32> {
-    return vec3<f32>(
-        iterations * 0.5,
-        iterations * 0.6,
-        iterations * 0.7,
+// Samples the distance at a few steps along the normal, occluded points being closer to
+// other surfaces than the step taken.
+fn ambient_occlusion(p: vec3<f32>, normal: vec3<f32>) -> f32 {
+    var occlusion = 0.0;
+    var weight = 1.0;
+    for (var i = 1; i <= 5; i++) {
+        let step = 0.02 * f32(i);
+        occlusion += weight * (step - scene_distance(p + normal * step).x);
+        weight *= 0.6;
+    }
+    return clamp(1.0 - 4.0 * occlusion, 0.0, 1.0);
+}
+
+fn render_3d(tex_coords: vec2<f32>) -> vec3<f32> {
+    let direction = normalize(
+        camera_3d.forward * camera_3d.focal_length
+        + camera_3d.right * tex_coords.x
+        - camera_3d.up * tex_coords.y
     );
-    /*return vec3<f32>(
-        pow(cos(sqrt(iterations)*1.0 + 0.0), 2.0),
-        pow(cos(sqrt(iterations)*1.0 + 120.0), 2.0),
-        pow(cos(sqrt(iterations)*1.0 + 240.0), 2.0),
-    );*/
+    let background = mix(vec3<f32>(0.05, 0.05, 0.1), vec3<f32>(0.3, 0.4, 0.6), 0.5 - 0.5 * tex_coords.y);
+
+    var t = 0.0;
+    var hit = false;
+    var trap = 0.0;
+    for (var i = 0; i < 256; i++) {
+        let result = scene_distance(camera_3d.position + direction * t);
+        // Scale the hit threshold with distance so detail matches the pixel size
+        if result.x < 0.0005 * t {
+            hit = true;
+            trap = result.y;
+            break;
+        }
+        t += result.x;
+        if t > 50.0 {
+            break;
+        }
+    }
+    if !hit {
+        return background;
+    }
+
+    let p = camera_3d.position + direction * t;
+    let normal = scene_normal(p, max(0.0005 * t, 1e-5));
+    let light = normalize(vec3<f32>(0.6, 0.8, -0.4));
+    let surface = p + normal * 0.002;
+    let diffuse = max(dot(normal, light), 0.0) * soft_shadow(surface, light, 16.0);
+    let occlusion = ambient_occlusion(p, normal);
+    let albedo = 0.5 + 0.5 * cos(6.28318 * (trap + vec3<f32>(0.0, 0.1, 0.2)));
+    return albedo * (0.85 * diffuse + 0.25 * occlusion);
+}
+
+// Tallies the pixels that hit the limit or escaped in its last quarter, which are the ones a
+// higher limit would change. Pixels the interior checks caught wouldn't.
+fn count_iterations(result: Orbit) {
+    if fractal.adaptive_iterations == 0u {
+        return;
+    }
+    if result.limited || result.iterations > 0.75 {
+        count_pixel(result.limited);
+    }
+}
+
+// Interpolates between the palette's texels by hand, since the colourings call this from
+// non-uniform control flow, where the texture can't be sampled with implicit derivatives. The
+// texels are read from an sRGB texture, so they are blended in linear light.
+fn sample_palette(t: f32) -> vec3<f32> {
+    let last = textureDimensions(palette_texture) - 1u;
+    let x = t * f32(last);
+    let i = min(u32(x), last);
+    let a = textureLoad(palette_texture, i, 0).rgb;
+    let b = textureLoad(palette_texture, min(i + 1u, last), 0).rgb;
+    return mix(a, b, fract(x));
+}
+
+// Maps a colouring's value onto the palette, scaled and offset by `Palette`, and starting the
+// gradient over past its end if it repeats. Negative values, for pixels inside the set, are
+// black.
+fn get_colour(value: f32) -> vec3<f32> {
+    if value < 0.0 {
+        return vec3<f32>(0.0);
+    }
+    var t = value * palette.scale + palette.offset;
+    if palette.repeat != 0u {
+        t = fract(t);
+    } else {
+        t = clamp(t, 0.0, 1.0);
+    }
+    return sample_palette(t);
+}
+
+// Colours a pixel inside the set by the mode in fractal.interior, matching `Interior`. Pixels
+// whose cycle wasn't detected are left black by the period and multiplier colourings.
+fn get_interior_colour(orbit: Orbit) -> vec3<f32> {
+    switch fractal.interior {
+        // Period, with the golden ratio spreading neighbouring periods round the colour wheel
+        case 1u: {
+            if orbit.period == 0u {
+                return vec3<f32>(0.0);
+            }
+            return 0.8 * hue_colour(fract(f32(orbit.period) * 0.618034));
+        }
+        // Multiplier, with its argument as the hue, fading to black at the nucleus
+        case 2u: {
+            if orbit.period == 0u {
+                return vec3<f32>(0.0);
+            }
+            let multiplier = orbit.multiplier;
+            let hue = atan2(multiplier.y, multiplier.x) / 6.28318;
+            return hue_colour(hue) * min(length(multiplier), 1.0);
+        }
+        // Final |z|
+        case 3u: {
+            return hue_colour(0.5 * length(orbit.z));
+        }
+        // Average orbit, with its argument as the hue and its distance from 0 as the brightness
+        case 4u: {
+            let average = orbit.average;
+            let hue = atan2(average.y, average.x) / 6.28318;
+            return hue_colour(hue) * (0.3 + 0.7 * clamp(0.5 * length(average), 0.0, 1.0));
+        }
+        default: {
+            return get_colour(-1.0);
+        }
+    }
+}
+
+// Colours a pixel by how close its orbit came to the trap. Image traps show the image under the
+// closest point instead, and leave orbits that missed it to the other colourings.
+fn get_trap_colour(orbit: Orbit) -> vec3<f32> {
+    let hit = orbit.trap;
+    if trap.shape == 4u {
+        if hit.distance < 1.0 {
+            return textureSampleLevel(trap_image, trap_sampler, trap_image_coordinates(hit.point), 0.0).rgb;
+        }
+        if orbit.iterations < 0.0 {
+            return get_interior_colour(orbit);
+        }
+        return get_colour(orbit.iterations);
+    }
+    return get_colour(exp(-4.0 * hit.distance));
+}
+
+// Draws the boundary as an anti-aliased line fractal.line_width pixels wide, half on each side,
+// over white outside the set and grey inside. Interior pixels without a distance estimate are
+// filled black.
+fn get_distance_colour(orbit: Orbit, pixel_size: f32) -> vec3<f32> {
+    let inside = orbit.iterations < 0.0;
+    if inside && orbit.distance < 0.0 {
+        return vec3<f32>(0.0);
+    }
+    let background = select(vec3<f32>(1.0), vec3<f32>(0.7), inside);
+    let coverage = clamp(0.5 * fractal.line_width + 0.5 - orbit.distance / pixel_size, 0.0, 1.0);
+    return mix(background, vec3<f32>(0.0), coverage);
+}
+
+// The colour of a pixel in linear light, for whichever mode is being drawn.
+fn pixel_colour(in: FragmentInput) -> vec3<f32> {
+    // The size of a pixel in tex_coords, taken here while control flow is still uniform
+    let pixel_size = abs(dpdy(in.tex_coords.y));
+    let offset = vec2<real>(in.tex_coords) * unpack(camera.scale);
+    let pixel = offset + unpack2(camera.pos);
+    let reference_offset = offset + unpack2(camera.offset);
+    let zero = vec2<real>(real(0.0), real(0.0));
+
+    if fractal.mode == 1u {
+        var z0 = pixel;
+        var constant = zero;
+        if fractal.julia != 0u {
+            constant = unpack2(fractal.julia_constant);
+        } else if newton.nova != 0u {
+            // Nova orbits start from a critical point of the relaxed Newton map
+            z0 = unpack2(newton.nova_start);
+            constant = pixel;
+        }
+        let basin = newton_iterations(z0, constant, 200);
+        return get_basin_colour(i32(basin.x), basin.y);
+    }
+
+    if fractal.mode == 4u || fractal.mode == 5u {
+        return render_3d(in.tex_coords);
+    }
+
+    if fractal.mode == 3u {
+        return get_buddhabrot_colour(in.position.xy);
+    }
+
+    if fractal.mode == 2u {
+        let exponent = lyapunov_exponent(vec2<f32>(pixel), 200);
+        return get_lyapunov_colour(exponent);
+    }
+
+    let max_iteration = i32(fractal.max_iterations);
+    var result: Orbit;
+    if fractal.perturbation != 0u {
+        if fractal.julia != 0u {
+            result = compute_iterations_perturbed(reference_offset, zero, unpack2(fractal.julia_constant), max_iteration);
+        } else {
+            result = compute_iterations_perturbed(zero, reference_offset, pixel, max_iteration);
+        }
+    } else if DOUBLE_SINGLE && fractal.power == 2.0 && fractal.formula != 6u {
+        result = ds_compute_iterations(in.tex_coords, max_iteration);
+    } else if fractal.julia != 0u {
+        result = compute_iterations(pixel, unpack2(fractal.julia_constant), max_iteration);
+    } else {
+        result = compute_iterations(zero, pixel, max_iteration);
+    }
+    count_iterations(result);
+
+    if fractal.colouring == 1u {
+        return get_distance_colour(result, pixel_size);
+    }
+    if fractal.colouring == 2u {
+        return get_trap_colour(result);
+    }
+    if fractal.colouring >= 3u && result.iterations >= 0.0 {
+        return get_colour(result.statistic);
+    }
+    if result.iterations < 0.0 {
+        return get_interior_colour(result);
+    }
+    return get_colour(result.iterations);
 }
 
 @fragment
 fn fs_main(in: FragmentInput) -> @location(0) vec4<f32> {
-    let result = compute_iterations(
-        vec2<f64>(f64(0.0), f64(0.0)),
-        vec2<f64>(in.tex_coords) * f64(exp(-camera.zoom)) + camera.pos,
-        200
-    );
-
-    return vec4<f32>(get_colour(result), 1.0);
+    return output_colour(pixel_colour(in));
 }
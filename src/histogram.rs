@@ -0,0 +1,271 @@
+use winit::dpi::PhysicalSize;
+
+use crate::camera::CameraUniform;
+
+/// Each workgroup takes 64 samples.
+const WORKGROUPS_PER_FRAME: u32 = 256;
+/// Every histogram starts with the largest counts, which the tone mapping divides by. It is
+/// padded to keep the pixels aligned.
+const HISTOGRAM_HEADER: u64 = 4;
+
+/// Prepends the helpers shared by the histogram compute shaders to `source`.
+pub fn shader_source(source: &str) -> String {
+    format!("{}\n{}", include_str!("histogram.wgsl"), source)
+}
+
+fn create_histogram_buffer(
+    device: &wgpu::Device,
+    size: PhysicalSize<u32>,
+    channels: u64,
+    label: &str,
+) -> wgpu::Buffer {
+    let length = HISTOGRAM_HEADER + size.width as u64 * size.height as u64 * channels;
+    device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some(label),
+        size: length * std::mem::size_of::<u32>() as u64,
+        usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    })
+}
+
+/// Binds `buffers` in order from binding 0.
+fn create_bind_group(
+    device: &wgpu::Device,
+    layout: &wgpu::BindGroupLayout,
+    buffers: &[&wgpu::Buffer],
+    label: &str,
+) -> wgpu::BindGroup {
+    let entries: Vec<_> = buffers
+        .iter()
+        .enumerate()
+        .map(|(binding, buffer)| wgpu::BindGroupEntry {
+            binding: binding as u32,
+            resource: buffer.as_entire_binding(),
+        })
+        .collect();
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        layout,
+        entries: &entries,
+        label: Some(label),
+    })
+}
+
+/// The uniform then the histogram, which the compute shader writes and follows with the batch
+/// counter, and the fragment shader only reads.
+fn create_bind_group_layout(
+    device: &wgpu::Device,
+    compute: bool,
+    label: &str,
+) -> wgpu::BindGroupLayout {
+    let visibility = if compute {
+        wgpu::ShaderStages::COMPUTE
+    } else {
+        wgpu::ShaderStages::FRAGMENT
+    };
+    let buffer_entry = |binding, ty| wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility,
+        ty: wgpu::BindingType::Buffer {
+            ty,
+            has_dynamic_offset: false,
+            min_binding_size: None,
+        },
+        count: None,
+    };
+    let mut entries = vec![
+        buffer_entry(0, wgpu::BufferBindingType::Uniform),
+        buffer_entry(
+            1,
+            wgpu::BufferBindingType::Storage {
+                read_only: !compute,
+            },
+        ),
+    ];
+    if compute {
+        entries.push(buffer_entry(2, wgpu::BufferBindingType::Uniform));
+    }
+    device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        entries: &entries,
+        label: Some(label),
+    })
+}
+
+/// A compute pass that accumulates samples into a histogram of the window's pixels, which a
+/// fragment shader then tone maps. The histogram keeps accumulating across frames until the
+/// camera or the uniform changes.
+pub struct HistogramPass<U> {
+    name: &'static str,
+    /// The number of words each pixel takes in the histogram.
+    channels: u64,
+    uniform: U,
+    uniform_buffer: wgpu::Buffer,
+    histogram_buffer: wgpu::Buffer,
+    /// Seeds the random numbers, so it only needs to differ between batches.
+    batch: u32,
+    batch_buffer: wgpu::Buffer,
+    /// Missing when the adapter can't run compute shaders, and the mode isn't offered.
+    compute_pipeline: Option<wgpu::ComputePipeline>,
+    compute_bind_group_layout: wgpu::BindGroupLayout,
+    compute_bind_group: wgpu::BindGroup,
+    pub render_bind_group_layout: wgpu::BindGroupLayout,
+    pub render_bind_group: wgpu::BindGroup,
+    accumulated_camera: Option<CameraUniform>,
+}
+
+impl<U: bytemuck::Pod + PartialEq> HistogramPass<U> {
+    /// `shader` is the compute shader's full source, with `cs_main` taking the pass's uniform
+    /// and histogram at group 0 and `bind_group_layouts` from group 1 on.
+    pub fn new(
+        device: &wgpu::Device,
+        name: &'static str,
+        shader: String,
+        channels: u64,
+        bind_group_layouts: &[&wgpu::BindGroupLayout],
+        size: PhysicalSize<u32>,
+        compute_shaders: bool,
+    ) -> Self {
+        let uniform = U::zeroed();
+        let uniform_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some(&format!("{}_buffer", name)),
+            size: std::mem::size_of::<U>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let histogram_buffer = create_histogram_buffer(
+            device,
+            size,
+            channels,
+            &format!("{}_histogram_buffer", name),
+        );
+        let batch_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some(&format!("{}_batch_buffer", name)),
+            size: std::mem::size_of::<u32>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let compute_bind_group_layout =
+            create_bind_group_layout(device, true, &format!("{}_compute_bind_group_layout", name));
+        let compute_bind_group = create_bind_group(
+            device,
+            &compute_bind_group_layout,
+            &[&uniform_buffer, &histogram_buffer, &batch_buffer],
+            &format!("{}_compute_bind_group", name),
+        );
+        let render_bind_group_layout =
+            create_bind_group_layout(device, false, &format!("{}_render_bind_group_layout", name));
+        let render_bind_group = create_bind_group(
+            device,
+            &render_bind_group_layout,
+            &[&uniform_buffer, &histogram_buffer],
+            &format!("{}_render_bind_group", name),
+        );
+
+        let compute_pipeline = compute_shaders.then(|| {
+            let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+                label: Some(&format!("{}_shader", name)),
+                source: wgpu::ShaderSource::Wgsl(shader.into()),
+            });
+            let layouts: Vec<_> = std::iter::once(&compute_bind_group_layout)
+                .chain(bind_group_layouts.iter().copied())
+                .collect();
+            let compute_pipeline_layout =
+                device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                    label: Some(&format!("{}_pipeline_layout", name)),
+                    bind_group_layouts: &layouts,
+                    push_constant_ranges: &[],
+                });
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some(&format!("{}_compute_pipeline", name)),
+                layout: Some(&compute_pipeline_layout),
+                module: &shader,
+                entry_point: Some("cs_main"),
+                compilation_options: Default::default(),
+                cache: None,
+            })
+        });
+
+        Self {
+            name,
+            channels,
+            uniform,
+            uniform_buffer,
+            histogram_buffer,
+            batch: 0,
+            batch_buffer,
+            compute_pipeline,
+            compute_bind_group_layout,
+            compute_bind_group,
+            render_bind_group_layout,
+            render_bind_group,
+            accumulated_camera: None,
+        }
+    }
+
+    pub fn resize(&mut self, device: &wgpu::Device, size: PhysicalSize<u32>) {
+        self.histogram_buffer = create_histogram_buffer(
+            device,
+            size,
+            self.channels,
+            &format!("{}_histogram_buffer", self.name),
+        );
+        self.compute_bind_group = create_bind_group(
+            device,
+            &self.compute_bind_group_layout,
+            &[
+                &self.uniform_buffer,
+                &self.histogram_buffer,
+                &self.batch_buffer,
+            ],
+            &format!("{}_compute_bind_group", self.name),
+        );
+        self.render_bind_group = create_bind_group(
+            device,
+            &self.render_bind_group_layout,
+            &[&self.uniform_buffer, &self.histogram_buffer],
+            &format!("{}_render_bind_group", self.name),
+        );
+        self.accumulated_camera = None;
+    }
+
+    /// Lets `update` change the uniform, starting the histogram over if it did.
+    pub fn update(&mut self, queue: &wgpu::Queue, update: impl FnOnce(&mut U)) {
+        let previous = self.uniform;
+        update(&mut self.uniform);
+        if self.uniform != previous {
+            self.accumulated_camera = None;
+            queue.write_buffer(&self.uniform_buffer, 0, bytemuck::bytes_of(&self.uniform));
+        }
+    }
+
+    /// Adds another batch of samples to the histogram, clearing it first if the camera has
+    /// moved since the last batch. `bind_groups` match the layouts given to `new`.
+    pub fn compute(
+        &mut self,
+        encoder: &mut wgpu::CommandEncoder,
+        queue: &wgpu::Queue,
+        camera_uniform: &CameraUniform,
+        bind_groups: &[&wgpu::BindGroup],
+    ) {
+        let Some(compute_pipeline) = &self.compute_pipeline else {
+            return;
+        };
+        if self.accumulated_camera != Some(*camera_uniform) {
+            encoder.clear_buffer(&self.histogram_buffer, 0, None);
+            self.accumulated_camera = Some(*camera_uniform);
+        }
+        self.batch = self.batch.wrapping_add(1);
+        queue.write_buffer(&self.batch_buffer, 0, bytemuck::bytes_of(&self.batch));
+
+        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some(&format!("{}_compute_pass", self.name)),
+            timestamp_writes: None,
+        });
+        compute_pass.set_pipeline(compute_pipeline);
+        compute_pass.set_bind_group(0, &self.compute_bind_group, &[]);
+        for (index, bind_group) in bind_groups.iter().enumerate() {
+            compute_pass.set_bind_group(index as u32 + 1, *bind_group, &[]);
+        }
+        compute_pass.dispatch_workgroups(WORKGROUPS_PER_FRAME, 1, 1);
+    }
+}
@@ -0,0 +1,32 @@
+// Shared by the compute shaders that accumulate samples into a histogram, which follow it. Each
+// binds its own uniform and histogram at group 0.
+
+struct CameraUniform {
+    pos: wide2,
+    zoom: f32,
+    aspect: f32,
+    scale: wide,
+    offset: wide2,
+};
+@group(1)
+@binding(0)
+var<uniform> camera: CameraUniform;
+
+// Counts the batches dispatched, to seed each one differently
+@group(0)
+@binding(2)
+var<uniform> batch: u32;
+
+const HISTOGRAM_HEADER: u32 = 4u;
+
+fn hash(value: u32) -> u32 {
+    // PCG
+    let state = value * 747796405u + 2891336453u;
+    let word = ((state >> ((state >> 28u) + 4u)) ^ state) * 277803737u;
+    return (word >> 22u) ^ word;
+}
+
+fn random(seed: ptr<function, u32>) -> f32 {
+    *seed = hash(*seed);
+    return f32(*seed) / 4294967295.0;
+}
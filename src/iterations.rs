@@ -0,0 +1,327 @@
+use std::sync::mpsc;
+
+/// The limit at zoom 0, where the whole set is in view.
+const BASE_ITERATIONS: f32 = 200.0;
+/// Detail near the boundary takes longer to escape the deeper the zoom, roughly linearly in
+/// the zoom's logarithm.
+const ITERATIONS_PER_ZOOM: f32 = 60.0;
+pub const MIN_ITERATIONS: u32 = 50;
+pub const MAX_ITERATIONS: u32 = 100_000;
+
+/// More than this fraction of pixels escaping late, in the last quarter of the limit, means the
+/// limit is cutting off detail.
+const RAISE_FRACTION: f64 = 0.001;
+const RAISE_FACTOR: f32 = 1.5;
+const LOWER_FACTOR: f32 = 0.8;
+/// Readings in a row without a pixel near the limit before it is lowered.
+const QUIET_READINGS: u32 = 10;
+/// Zooming further than this from where a limit was found to cut off detail forgets it.
+const FLOOR_ZOOM_RANGE: f32 = 1.0;
+
+/// How many iterations the escape-time modes run before treating a pixel as inside the set.
+#[derive(Debug)]
+pub struct IterationLimit {
+    /// The limit for the current frame.
+    pub limit: u32,
+    /// Set from the console or the hotkeys to stop the limit following the zoom.
+    pub override_limit: Option<u32>,
+    /// Adjusts the limit from how many pixels hit it in the previous frame instead of from the
+    /// zoom.
+    pub adaptive: bool,
+    /// The highest adaptive limit found to cut off detail, and the zoom it was found at. The
+    /// limit is only lowered while it stays above it, which leaves a dead band where it can't
+    /// hunt between a limit that cuts off detail and one that doesn't.
+    floor: Option<(u32, f32)>,
+    quiet_readings: u32,
+    zoom: f32,
+}
+
+impl IterationLimit {
+    pub fn new() -> Self {
+        Self {
+            limit: BASE_ITERATIONS as u32,
+            override_limit: None,
+            adaptive: false,
+            floor: None,
+            quiet_readings: 0,
+            zoom: 0.0,
+        }
+    }
+
+    /// Follows the zoom unless the limit has been overridden or is adapting.
+    pub fn update(&mut self, zoom: f32) {
+        self.zoom = zoom;
+        if self
+            .floor
+            .is_some_and(|(_, floor_zoom)| (zoom - floor_zoom).abs() > FLOOR_ZOOM_RANGE)
+        {
+            self.floor = None;
+        }
+        if let Some(limit) = self.override_limit {
+            self.limit = limit;
+        } else if !self.adaptive {
+            self.limit = auto_limit(zoom);
+        }
+    }
+
+    /// Overrides the limit with a multiple of the current one.
+    pub fn scale(&mut self, factor: f32) {
+        let limit = scaled(self.limit, factor);
+        self.override_limit = Some(limit);
+        self.limit = limit;
+    }
+
+    /// Raises the limit while pixels are escaping just short of it, and lowers it once none
+    /// have come near it for a while, so it settles on a limit that shows all the detail.
+    /// `limit` is the one the counts were rendered with, since they arrive a frame or two late.
+    pub fn adapt(&mut self, limit: u32, counts: IterationCounts, pixels: u32) {
+        if !self.adaptive || self.override_limit.is_some() || pixels == 0 || limit != self.limit {
+            return;
+        }
+        if counts.late as f64 > RAISE_FRACTION * pixels as f64 {
+            let floor = self.floor.map_or(limit, |(floor, _)| floor.max(limit));
+            self.floor = Some((floor, self.zoom));
+            self.limit = scaled(limit, RAISE_FACTOR);
+            self.quiet_readings = 0;
+        } else if counts.limited == 0 && counts.late == 0 {
+            self.quiet_readings += 1;
+            let lowered = scaled(limit, LOWER_FACTOR);
+            if self.quiet_readings >= QUIET_READINGS
+                && self.floor.is_none_or(|(floor, _)| lowered > floor)
+            {
+                self.limit = lowered;
+                self.quiet_readings = 0;
+            }
+        } else {
+            self.quiet_readings = 0;
+        }
+    }
+}
+
+fn auto_limit(zoom: f32) -> u32 {
+    (BASE_ITERATIONS + ITERATIONS_PER_ZOOM * zoom.max(0.0)) as u32
+}
+
+fn scaled(limit: u32, factor: f32) -> u32 {
+    ((limit as f32 * factor) as u32).clamp(MIN_ITERATIONS, MAX_ITERATIONS)
+}
+
+/// Tallies written by `count_iterations` in the fragment shader.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct IterationCounts {
+    /// Pixels that reached the limit without escaping or being caught by the interior checks.
+    pub limited: u32,
+    /// Pixels that escaped in the last quarter of the limit.
+    pub late: u32,
+}
+
+#[derive(Debug)]
+enum Readback {
+    Idle,
+    /// The counts have been copied into the readback buffer in a frame that hasn't been
+    /// submitted yet. Each state keeps the iteration limit the frame was rendered with.
+    Copied(u32),
+    Mapping(u32, mpsc::Receiver<Result<(), wgpu::BufferAsyncError>>),
+}
+
+/// Reads the fragment shader's [`IterationCounts`] back to the CPU. The buffer is mapped
+/// asynchronously, so the counts arrive a frame or two late, and frames rendered while a read
+/// is in flight are not copied.
+#[derive(Debug)]
+pub struct IterationCounter {
+    pub buffer: wgpu::Buffer,
+    readback_buffer: wgpu::Buffer,
+    readback: Readback,
+}
+
+impl IterationCounter {
+    pub fn new(device: &wgpu::Device) -> Self {
+        let size = std::mem::size_of::<IterationCounts>() as wgpu::BufferAddress;
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("iteration_count_buffer"),
+            size,
+            usage: wgpu::BufferUsages::STORAGE
+                | wgpu::BufferUsages::COPY_SRC
+                | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let readback_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("iteration_count_readback_buffer"),
+            size,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        Self {
+            buffer,
+            readback_buffer,
+            readback: Readback::Idle,
+        }
+    }
+
+    /// Zeroes the counts, before the pass that writes them.
+    pub fn clear(&self, encoder: &mut wgpu::CommandEncoder) {
+        encoder.clear_buffer(&self.buffer, 0, None);
+    }
+
+    /// Copies the counts out after the pass that writes them with `limit`, if no read is in
+    /// flight.
+    pub fn copy(&mut self, encoder: &mut wgpu::CommandEncoder, limit: u32) {
+        if let Readback::Idle = self.readback {
+            let size = self.buffer.size();
+            encoder.copy_buffer_to_buffer(&self.buffer, 0, &self.readback_buffer, 0, size);
+            self.readback = Readback::Copied(limit);
+        }
+    }
+
+    /// Starts mapping the copy, once the frame that made it has been submitted.
+    pub fn submitted(&mut self) {
+        if let Readback::Copied(limit) = self.readback {
+            let (sender, receiver) = mpsc::channel();
+            self.readback_buffer
+                .slice(..)
+                .map_async(wgpu::MapMode::Read, move |result| {
+                    let _ = sender.send(result);
+                });
+            self.readback = Readback::Mapping(limit, receiver);
+        }
+    }
+
+    /// Returns the counts once a read has finished, with the limit they were rendered with.
+    pub fn poll(&mut self, device: &wgpu::Device) -> Option<(u32, IterationCounts)> {
+        let Readback::Mapping(limit, receiver) = &self.readback else {
+            return None;
+        };
+        let limit = *limit;
+        device.poll(wgpu::Maintain::Poll);
+        let result = receiver.try_recv().ok()?;
+        self.readback = Readback::Idle;
+        if let Err(e) = result {
+            eprintln!("failed to read the iteration counts: {:?}", e);
+            return None;
+        }
+        let counts =
+            bytemuck::pod_read_unaligned(&self.readback_buffer.slice(..).get_mapped_range());
+        self.readback_buffer.unmap();
+        Some((limit, counts))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PIXELS: u32 = 10_000;
+
+    fn adaptive() -> IterationLimit {
+        let mut limit = IterationLimit::new();
+        limit.adaptive = true;
+        limit.update(0.0);
+        limit
+    }
+
+    fn counts(limited: u32, late: u32) -> IterationCounts {
+        IterationCounts { limited, late }
+    }
+
+    /// The counts for a view whose pixels escape evenly spread over 0 to 1000 iterations.
+    fn view_counts(limit: u32) -> IterationCounts {
+        let escapes = (0..PIXELS).map(|pixel| pixel / 10);
+        let limited = escapes.clone().filter(|&escape| escape >= limit).count();
+        let late = escapes
+            .filter(|&escape| escape < limit && escape as f32 >= 0.75 * limit as f32)
+            .count();
+        counts(limited as u32, late as u32)
+    }
+
+    #[test]
+    fn the_default_follows_the_zoom() {
+        assert_eq!(auto_limit(0.0), 200);
+        assert_eq!(auto_limit(-3.0), 200);
+        assert_eq!(auto_limit(10.0), 800);
+        let mut limit = IterationLimit::new();
+        limit.update(5.0);
+        assert_eq!(limit.limit, 500);
+        limit.scale(2.0);
+        limit.update(10.0);
+        assert_eq!(limit.limit, 1000);
+        limit.override_limit = None;
+        limit.update(10.0);
+        assert_eq!(limit.limit, 800);
+    }
+
+    #[test]
+    fn scaling_is_clamped() {
+        assert_eq!(scaled(100, 0.1), MIN_ITERATIONS);
+        assert_eq!(scaled(MAX_ITERATIONS, 1.5), MAX_ITERATIONS);
+    }
+
+    #[test]
+    fn late_escapes_raise_the_limit() {
+        let mut limit = adaptive();
+        limit.adapt(200, counts(0, 10), PIXELS);
+        assert_eq!(limit.limit, 200);
+        limit.adapt(200, counts(0, 11), PIXELS);
+        assert_eq!(limit.limit, 300);
+        // Counts from frames rendered before the change are stale
+        limit.adapt(200, counts(0, 1000), PIXELS);
+        assert_eq!(limit.limit, 300);
+    }
+
+    #[test]
+    fn the_limit_is_lowered_after_quiet_readings() {
+        let mut limit = adaptive();
+        for _ in 1..QUIET_READINGS {
+            limit.adapt(200, counts(0, 0), PIXELS);
+        }
+        // A pixel near the limit starts the wait over
+        limit.adapt(200, counts(1, 0), PIXELS);
+        for _ in 1..QUIET_READINGS {
+            limit.adapt(200, counts(0, 0), PIXELS);
+        }
+        assert_eq!(limit.limit, 200);
+        limit.adapt(200, counts(0, 0), PIXELS);
+        assert_eq!(limit.limit, 160);
+    }
+
+    #[test]
+    fn the_limit_settles_instead_of_hunting() {
+        let mut limit = adaptive();
+        let mut history = Vec::new();
+        for _ in 0..200 {
+            let current = limit.limit;
+            limit.adapt(current, view_counts(current), PIXELS);
+            limit.update(0.0);
+            history.push(limit.limit);
+        }
+        let settled = history[history.len() - 1];
+        assert!(
+            history[100..].iter().all(|&limit| limit == settled),
+            "{:?}",
+            history
+        );
+        // High enough that nothing escapes near it
+        assert_eq!(view_counts(settled).late, 0);
+        assert!(settled < 2000, "{}", settled);
+    }
+
+    #[test]
+    fn zooming_away_forgets_the_floor() {
+        let mut limit = adaptive();
+        limit.adapt(200, counts(0, 100), PIXELS);
+        limit.adapt(300, counts(0, 100), PIXELS);
+        let quiet = |limit: &mut IterationLimit| {
+            for _ in 0..QUIET_READINGS {
+                limit.adapt(limit.limit, counts(0, 0), PIXELS);
+            }
+        };
+        quiet(&mut limit);
+        assert_eq!(limit.limit, 360);
+        // 288 would be under 300, which cut off detail here
+        quiet(&mut limit);
+        assert_eq!(limit.limit, 360);
+        limit.update(1.5);
+        quiet(&mut limit);
+        assert_eq!(limit.limit, 288);
+    }
+}
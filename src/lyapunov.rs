@@ -0,0 +1,142 @@
+use anyhow::{anyhow, Result};
+
+/// The sequence is packed into the bits of a u32 for the shader.
+pub const MAX_SEQUENCE_LENGTH: usize = 32;
+
+/// Settings for the Markus-Lyapunov fractal, where each pixel drives the logistic map with
+/// r = a or r = b as picked by the letters of the sequence.
+#[derive(Debug)]
+pub struct Lyapunov {
+    sequence: String,
+    warmup: u32,
+    /// Stable regions, where the exponent is negative, shade from this colour to black.
+    stable_colour: [f32; 3],
+    /// Chaotic regions shade from black to this colour.
+    chaotic_colour: [f32; 3],
+}
+
+impl Lyapunov {
+    pub fn new() -> Self {
+        Self {
+            sequence: "AB".to_string(),
+            warmup: 50,
+            stable_colour: [1.0, 0.8, 0.2],
+            chaotic_colour: [0.1, 0.3, 1.0],
+        }
+    }
+
+    pub fn set_sequence(&mut self, sequence: &str) -> Result<()> {
+        let sequence = sequence.trim().to_uppercase();
+        if sequence.is_empty()
+            || sequence.len() > MAX_SEQUENCE_LENGTH
+            || sequence.chars().any(|c| c != 'A' && c != 'B')
+        {
+            return Err(anyhow!(
+                "expected a sequence of 1 to {} As and Bs",
+                MAX_SEQUENCE_LENGTH
+            ));
+        }
+        self.sequence = sequence;
+        Ok(())
+    }
+
+    /// Sets the colour of the stable or chaotic regions from `stable r g b` or `chaotic r g b`,
+    /// with each channel from 0 to 1.
+    pub fn set_colour(&mut self, arguments: &str) -> Result<()> {
+        let arguments = arguments.trim();
+        let (region, value) = arguments.split_once(' ').unwrap_or((arguments, ""));
+        let colour = match region {
+            "stable" => &mut self.stable_colour,
+            "chaotic" => &mut self.chaotic_colour,
+            _ => return Err(anyhow!("expected stable or chaotic")),
+        };
+        *colour = parse_colour(value)?;
+        Ok(())
+    }
+}
+
+fn parse_colour(text: &str) -> Result<[f32; 3]> {
+    let channels = text
+        .split_whitespace()
+        .map(|channel| {
+            channel
+                .parse::<f32>()
+                .ok()
+                .filter(|channel| (0.0..=1.0).contains(channel))
+                .ok_or_else(|| anyhow!("invalid colour channel {:?}", channel))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    channels
+        .try_into()
+        .map_err(|_| anyhow!("expected a colour as red, green and blue from 0 to 1"))
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct LyapunovUniform {
+    /// Bit i is set when letter i of the sequence is B.
+    pub sequence: u32,
+    pub sequence_length: u32,
+    pub warmup: u32,
+    pub _padding: u32,
+    pub stable_colour: [f32; 3],
+    pub _stable_padding: f32,
+    pub chaotic_colour: [f32; 3],
+    pub _chaotic_padding: f32,
+}
+
+impl LyapunovUniform {
+    pub fn new() -> Self {
+        Self {
+            sequence: 0,
+            sequence_length: 1,
+            warmup: 0,
+            _padding: 0,
+            stable_colour: [0.0; 3],
+            _stable_padding: 0.0,
+            chaotic_colour: [0.0; 3],
+            _chaotic_padding: 0.0,
+        }
+    }
+
+    pub fn update(&mut self, lyapunov: &Lyapunov) {
+        self.sequence = lyapunov
+            .sequence
+            .chars()
+            .enumerate()
+            .filter(|&(_, c)| c == 'B')
+            .fold(0, |bits, (i, _)| bits | 1 << i);
+        self.sequence_length = lyapunov.sequence.len() as u32;
+        self.warmup = lyapunov.warmup;
+        self.stable_colour = lyapunov.stable_colour;
+        self.chaotic_colour = lyapunov.chaotic_colour;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn region_colours_can_be_set() {
+        let mut lyapunov = Lyapunov::new();
+        lyapunov.set_colour("stable 0 0.5 1").unwrap();
+        lyapunov.set_colour(" chaotic 1 1 1 ").unwrap();
+        assert_eq!(lyapunov.stable_colour, [0.0, 0.5, 1.0]);
+        assert_eq!(lyapunov.chaotic_colour, [1.0; 3]);
+        let mut uniform = LyapunovUniform::new();
+        uniform.update(&lyapunov);
+        assert_eq!(uniform.stable_colour, [0.0, 0.5, 1.0]);
+    }
+
+    #[test]
+    fn invalid_colours_are_rejected() {
+        let mut lyapunov = Lyapunov::new();
+        assert!(lyapunov.set_colour("stable 0 0.5").is_err());
+        assert!(lyapunov.set_colour("stable 0 0.5 1 1").is_err());
+        assert!(lyapunov.set_colour("stable 0 2 1").is_err());
+        assert!(lyapunov.set_colour("stable red").is_err());
+        assert!(lyapunov.set_colour("periodic 0 0 0").is_err());
+        assert_eq!(lyapunov.stable_colour, [1.0, 0.8, 0.2]);
+    }
+}
@@ -1,10 +1,27 @@
+mod big;
+mod buddhabrot;
+mod camera;
+mod complex;
+mod console;
+mod external_ray;
+mod flame;
+mod fractal;
+mod histogram;
+mod iterations;
+mod lyapunov;
+mod newton;
+mod nucleus;
+mod palette;
+mod perturbation;
+mod precision;
+mod raymarch;
 mod state;
+mod trap;
+mod user_formula;
+
 use std::time::Instant;
 
 use state::*;
-
-mod camera;
-
 use winit::{
     application::ApplicationHandler,
     event::*,
@@ -14,9 +31,11 @@ use winit::{
 };
 
 enum App {
-    Uninitialised,
+    Uninitialised {
+        location: Option<String>,
+    },
     Initialised {
-        state: State,
+        state: Box<State>,
         last_render_time: Instant,
         focused: bool,
     },
@@ -27,7 +46,22 @@ impl ApplicationHandler for App {
         let window = event_loop
             .create_window(Window::default_attributes())
             .unwrap();
-        let state = pollster::block_on(State::new(window));
+        let mut state = match pollster::block_on(State::new(window)) {
+            Ok(state) => Box::new(state),
+            Err(e) => {
+                eprintln!("{:?}", e);
+                event_loop.exit();
+                return;
+            }
+        };
+        if let App::Uninitialised {
+            location: Some(location),
+        } = self
+        {
+            if let Err(e) = state.set_location(location) {
+                eprintln!("{:?}", e);
+            }
+        }
         *self = App::Initialised {
             state,
             last_render_time: Instant::now(),
@@ -93,6 +127,12 @@ impl ApplicationHandler for App {
 fn main() {
     env_logger::init();
     let event_loop = EventLoop::new().expect("failed to create event loop");
-    let mut app = App::Uninitialised;
-    event_loop.run_app(&mut app).expect("failure while running event loop");
+    // A location to start at can be given as "<re> <im> [zoom]", as printed by pressing L
+    let location = std::env::args().skip(1).collect::<Vec<_>>().join(" ");
+    let mut app = App::Uninitialised {
+        location: (!location.is_empty()).then_some(location),
+    };
+    event_loop
+        .run_app(&mut app)
+        .expect("failure while running event loop");
 }
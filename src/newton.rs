@@ -0,0 +1,349 @@
+use anyhow::{anyhow, Result};
+
+use crate::complex::Complex;
+
+pub const MAX_DEGREE: usize = 8;
+const ROOT_ITERATIONS: usize = 500;
+
+/// A polynomial stored as its coefficients from the constant term up, along with its roots
+/// which are needed to colour each pixel by the basin it converges to.
+#[derive(Debug, Clone)]
+pub struct Polynomial {
+    coefficients: Vec<Complex>,
+    roots: Vec<Complex>,
+}
+
+impl Polynomial {
+    pub fn from_roots(roots: Vec<Complex>) -> Result<Self> {
+        if roots.is_empty() || roots.len() > MAX_DEGREE {
+            return Err(anyhow!("expected between 1 and {} roots", MAX_DEGREE));
+        }
+
+        // Multiply out (z - r0)(z - r1)...
+        let mut coefficients = vec![Complex::ONE];
+        for &root in &roots {
+            let mut next = vec![Complex::ZERO; coefficients.len() + 1];
+            for (i, &coefficient) in coefficients.iter().enumerate() {
+                next[i + 1] = next[i + 1] + coefficient;
+                next[i] = next[i] - coefficient * root;
+            }
+            coefficients = next;
+        }
+        Ok(Self {
+            coefficients,
+            roots,
+        })
+    }
+
+    pub fn from_coefficients(mut coefficients: Vec<Complex>) -> Result<Self> {
+        while coefficients.last() == Some(&Complex::ZERO) {
+            coefficients.pop();
+        }
+        if coefficients.len() < 2 || coefficients.len() > MAX_DEGREE + 1 {
+            return Err(anyhow!(
+                "expected a polynomial of degree 1 to {}",
+                MAX_DEGREE
+            ));
+        }
+
+        let roots = find_roots(&coefficients);
+        Ok(Self {
+            coefficients,
+            roots,
+        })
+    }
+
+    pub fn roots(&self) -> &[Complex] {
+        &self.roots
+    }
+
+    pub fn degree(&self) -> usize {
+        self.coefficients.len() - 1
+    }
+
+    fn derivative(coefficients: &[Complex]) -> Vec<Complex> {
+        coefficients
+            .iter()
+            .enumerate()
+            .skip(1)
+            .map(|(power, &coefficient)| coefficient * power as f64)
+            .collect()
+    }
+
+    /// The critical points of the relaxed Newton map z - R·p(z)/p'(z), other than the poles,
+    /// which are the zeros of (1 - R)·p'² + R·p·p''. With R = 1 these are the roots and the
+    /// zeros of p''.
+    pub fn critical_points(&self, relaxation: f64) -> Vec<Complex> {
+        let first = Self::derivative(&self.coefficients);
+        let second = Self::derivative(&first);
+        let mut numerator = vec![Complex::ZERO; 2 * self.degree() - 1];
+        for (i, &a) in first.iter().enumerate() {
+            for (j, &b) in first.iter().enumerate() {
+                numerator[i + j] = numerator[i + j] + a * b * (1.0 - relaxation);
+            }
+        }
+        for (i, &a) in self.coefficients.iter().enumerate() {
+            for (j, &b) in second.iter().enumerate() {
+                numerator[i + j] = numerator[i + j] + a * b * relaxation;
+            }
+        }
+        while numerator.last() == Some(&Complex::ZERO) {
+            numerator.pop();
+        }
+        if numerator.len() < 2 {
+            return Vec::new();
+        }
+        find_roots(&numerator)
+    }
+}
+
+/// Finds every root at once with the Durand-Kerner method.
+fn find_roots(coefficients: &[Complex]) -> Vec<Complex> {
+    let leading = *coefficients.last().unwrap();
+    let monic = coefficients
+        .iter()
+        .map(|&coefficient| coefficient / leading)
+        .collect::<Vec<_>>();
+    let evaluate = |z: Complex| {
+        monic
+            .iter()
+            .rev()
+            .fold(Complex::ZERO, |result, &coefficient| {
+                result * z + coefficient
+            })
+    };
+
+    let degree = coefficients.len() - 1;
+    let seed = Complex::new(0.4, 0.9);
+    let mut roots = (0..degree)
+        .scan(Complex::ONE, |power, _| {
+            *power = *power * seed;
+            Some(*power)
+        })
+        .collect::<Vec<_>>();
+    for _ in 0..ROOT_ITERATIONS {
+        for i in 0..degree {
+            let denominator = (0..degree)
+                .filter(|&j| j != i)
+                .fold(Complex::ONE, |result, j| result * (roots[i] - roots[j]));
+            roots[i] = roots[i] - evaluate(roots[i]) / denominator;
+        }
+    }
+    roots
+}
+
+/// Settings for Newton's method, z ← z - R·p(z)/p'(z) + c. With `nova` set, c is taken from the
+/// pixel and every orbit starts from a critical point of the map, which gives the Nova fractal.
+#[derive(Debug)]
+pub struct Newton {
+    polynomial: Polynomial,
+    relaxation: f32,
+    pub nova: bool,
+    /// Kept from the last change to the polynomial or relaxation, since finding it solves for
+    /// the critical points.
+    nova_start: Complex,
+}
+
+impl Newton {
+    pub fn new() -> Self {
+        // z³ - 1
+        let polynomial = Polynomial::from_coefficients(vec![
+            Complex::new(-1.0, 0.0),
+            Complex::ZERO,
+            Complex::ZERO,
+            Complex::ONE,
+        ])
+        .unwrap();
+        let relaxation = 1.0;
+        Self {
+            nova_start: find_nova_start(&polynomial, relaxation),
+            polynomial,
+            relaxation,
+            nova: false,
+        }
+    }
+
+    pub fn polynomial(&self) -> &Polynomial {
+        &self.polynomial
+    }
+
+    pub fn set_polynomial(&mut self, polynomial: Polynomial) {
+        self.nova_start = find_nova_start(&polynomial, self.relaxation);
+        self.polynomial = polynomial;
+    }
+
+    pub fn relaxation(&self) -> f32 {
+        self.relaxation
+    }
+
+    pub fn set_relaxation(&mut self, relaxation: f32) {
+        self.relaxation = relaxation;
+        self.nova_start = find_nova_start(&self.polynomial, relaxation);
+    }
+
+    pub fn nova_start(&self) -> Complex {
+        self.nova_start
+    }
+}
+
+/// Where Nova orbits start: the critical point closest to the first root, which for z³ - 1
+/// with R = 1 is the classic starting point z = 1.
+fn find_nova_start(polynomial: &Polynomial, relaxation: f32) -> Complex {
+    let root = polynomial.roots[0];
+    polynomial
+        .critical_points(relaxation as f64)
+        .into_iter()
+        .min_by(|a, b| (*a - root).norm_sqr().total_cmp(&(*b - root).norm_sqr()))
+        .unwrap_or(root)
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct NewtonUniform {
+    pub coefficients: [[f64; 2]; MAX_DEGREE + 1],
+    pub roots: [[f64; 2]; MAX_DEGREE],
+    pub nova_start: [f64; 2],
+    pub degree: u32,
+    pub relaxation: f32,
+    pub nova: u32,
+    pub _padding: u32,
+}
+
+impl NewtonUniform {
+    pub fn new() -> Self {
+        Self {
+            coefficients: [[0.0; 2]; MAX_DEGREE + 1],
+            roots: [[0.0; 2]; MAX_DEGREE],
+            nova_start: [0.0; 2],
+            degree: 0,
+            relaxation: 1.0,
+            nova: 0,
+            _padding: 0,
+        }
+    }
+
+    pub fn update(&mut self, newton: &Newton) {
+        let polynomial = newton.polynomial();
+        self.coefficients = [[0.0; 2]; MAX_DEGREE + 1];
+        for (uniform, &coefficient) in self.coefficients.iter_mut().zip(&polynomial.coefficients) {
+            *uniform = coefficient.into();
+        }
+        self.roots = [[0.0; 2]; MAX_DEGREE];
+        for (uniform, &root) in self.roots.iter_mut().zip(&polynomial.roots) {
+            *uniform = root.into();
+        }
+        self.degree = polynomial.degree() as u32;
+        self.relaxation = newton.relaxation();
+        self.nova = newton.nova as u32;
+        if newton.nova {
+            self.nova_start = newton.nova_start().into();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: Complex, expected: Complex) {
+        assert!(
+            (actual - expected).norm_sqr() < 1e-18,
+            "{} != {}",
+            actual,
+            expected
+        );
+    }
+
+    /// Pairs each expected root with the nearest found one.
+    fn assert_roots(actual: &[Complex], expected: &[Complex]) {
+        assert_eq!(actual.len(), expected.len());
+        for &root in expected {
+            let nearest = actual
+                .iter()
+                .copied()
+                .min_by(|a, b| (*a - root).norm_sqr().total_cmp(&(*b - root).norm_sqr()))
+                .unwrap();
+            assert_close(nearest, root);
+        }
+    }
+
+    #[test]
+    fn roots_are_multiplied_out() {
+        // (z - 1)(z - i) = z² - (1 + i)z + i
+        let polynomial =
+            Polynomial::from_roots(vec![Complex::ONE, Complex::new(0.0, 1.0)]).unwrap();
+        assert_eq!(
+            polynomial.coefficients,
+            [
+                Complex::new(0.0, 1.0),
+                Complex::new(-1.0, -1.0),
+                Complex::ONE
+            ]
+        );
+        assert_eq!(polynomial.degree(), 2);
+        assert!(Polynomial::from_roots(Vec::new()).is_err());
+        assert!(Polynomial::from_roots(vec![Complex::ONE; MAX_DEGREE + 1]).is_err());
+    }
+
+    #[test]
+    fn coefficients_are_solved_for_their_roots() {
+        // 2z³ - 2, with a zero leading coefficient that is dropped
+        let coefficients = vec![
+            Complex::new(-2.0, 0.0),
+            Complex::ZERO,
+            Complex::ZERO,
+            Complex::new(2.0, 0.0),
+            Complex::ZERO,
+        ];
+        let polynomial = Polynomial::from_coefficients(coefficients).unwrap();
+        assert_eq!(polynomial.degree(), 3);
+        let half_root_three = 3f64.sqrt() / 2.0;
+        assert_roots(
+            polynomial.roots(),
+            &[
+                Complex::ONE,
+                Complex::new(-0.5, half_root_three),
+                Complex::new(-0.5, -half_root_three),
+            ],
+        );
+        assert!(Polynomial::from_coefficients(vec![Complex::ONE]).is_err());
+        assert!(Polynomial::from_coefficients(vec![Complex::ONE; MAX_DEGREE + 2]).is_err());
+    }
+
+    #[test]
+    fn critical_points_of_the_relaxed_map() {
+        // For z² - 1, (1 - R)·4z² + R·2(z² - 1) = 0 gives z² = 2R / (4 - 2R)
+        let polynomial =
+            Polynomial::from_roots(vec![Complex::ONE, Complex::new(-1.0, 0.0)]).unwrap();
+        let point = (2.0 * 0.5 / (4.0 - 2.0 * 0.5f64)).sqrt();
+        assert_roots(
+            &polynomial.critical_points(0.5),
+            &[Complex::new(point, 0.0), Complex::new(-point, 0.0)],
+        );
+        // Without relaxation only the roots are left
+        assert_roots(
+            &polynomial.critical_points(1.0),
+            &[Complex::ONE, Complex::new(-1.0, 0.0)],
+        );
+    }
+
+    #[test]
+    fn nova_start_follows_the_polynomial_and_relaxation() {
+        let mut newton = Newton::new();
+        let root = newton.polynomial().roots()[0];
+        assert_close(newton.nova_start(), root);
+
+        newton.set_relaxation(0.5);
+        let expected = find_nova_start(newton.polynomial(), 0.5);
+        assert_close(newton.nova_start(), expected);
+        assert!((newton.nova_start() - root).norm_sqr() > 1e-6);
+
+        let polynomial =
+            Polynomial::from_roots(vec![Complex::new(2.0, 0.0), Complex::new(-2.0, 0.0)]).unwrap();
+        newton.set_polynomial(polynomial);
+        assert_close(
+            newton.nova_start(),
+            find_nova_start(newton.polynomial(), 0.5),
+        );
+    }
+}
@@ -0,0 +1,263 @@
+use std::sync::mpsc::{self, TryRecvError};
+use std::thread;
+
+use anyhow::{anyhow, Result};
+use dashu_float::ops::{EstimatedLog2, SquareRoot};
+use dashu_float::FBig;
+
+use crate::big::{self, BigPoint};
+use crate::complex::Complex;
+use crate::perturbation::precision_for_zoom;
+
+/// Orbits further than this from 0 have escaped, which ends the search for the period.
+const ESCAPE_RADIUS: f64 = 1e3;
+pub const MAX_NEWTON_STEPS: usize = 64;
+/// Newton's method has converged once its steps are this many bits short of the precision.
+const NEWTON_TOLERANCE_BITS: usize = 16;
+/// Derivatives only need to be roughly right, but deep minibrots take them beyond the exponent
+/// range of f64, so they are carried in binary floats of this precision.
+pub const DERIVATIVE_BITS: usize = 64;
+/// The view's half height as a multiple of the minibrot's size estimate. The whole set has
+/// size 1, and fits with a small margin.
+const VIEW_SCALE: f64 = 1.5;
+
+/// The centre of a minibrot, where its critical orbit is periodic: f^p(0) = 0.
+#[derive(Debug)]
+pub struct Nucleus {
+    pub position: BigPoint,
+    pub period: usize,
+    /// Roughly the minibrot's radius, in [`DERIVATIVE_BITS`] of precision.
+    pub size: FBig,
+}
+
+impl Nucleus {
+    /// The zoom that fits the minibrot in view.
+    pub fn zoom(&self) -> f32 {
+        view_zoom_log2(self.size.log2_est() as f64)
+    }
+}
+
+/// The zoom that fits a feature of the given size in view.
+pub fn view_zoom(size: f64) -> f32 {
+    view_zoom_log2(size.log2())
+}
+
+/// [`view_zoom`] from log2 of the size, which also covers sizes too small for f64.
+fn view_zoom_log2(size_log2: f64) -> f32 {
+    -(size_log2 * std::f64::consts::LN_2 + VIEW_SCALE.ln()) as f32
+}
+
+/// A [`locate`] running on a worker thread, since deep minibrots can take seconds to find.
+#[derive(Debug)]
+pub struct Search {
+    receiver: mpsc::Receiver<Result<Nucleus>>,
+}
+
+impl Search {
+    pub fn start(c: BigPoint, zoom: f32, max_iterations: u32) -> Self {
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            // Nobody is waiting for the result if the window has closed
+            let _ = sender.send(locate(&c, zoom, max_iterations));
+        });
+        Self { receiver }
+    }
+
+    /// The result, once the search has finished.
+    pub fn poll(&self) -> Option<Result<Nucleus>> {
+        match self.receiver.try_recv() {
+            Ok(result) => Some(result),
+            Err(TryRecvError::Empty) => None,
+            Err(TryRecvError::Disconnected) => Some(Err(anyhow!("the nucleus search failed"))),
+        }
+    }
+}
+
+/// A complex number in binary floating point, for orbits iterated in full precision.
+pub type BigComplex = (FBig, FBig);
+
+pub fn step(z: &BigComplex, c: &BigComplex) -> BigComplex {
+    let product = &z.0 * &z.1;
+    (z.0.sqr() - z.1.sqr() + &c.0, &product + &product + &c.1)
+}
+
+pub fn to_complex(z: &BigComplex) -> Complex {
+    Complex::new(z.0.to_f64().value(), z.1.to_f64().value())
+}
+
+pub fn zero(bits: usize) -> BigComplex {
+    (
+        big::binary_from_f64(0.0, bits),
+        big::binary_from_f64(0.0, bits),
+    )
+}
+
+/// 1 in [`DERIVATIVE_BITS`], to start derivatives from.
+pub fn one() -> BigComplex {
+    (
+        big::binary_from_f64(1.0, DERIVATIVE_BITS),
+        big::binary_from_f64(0.0, DERIVATIVE_BITS),
+    )
+}
+
+/// `z` rounded to [`DERIVATIVE_BITS`], for calculating derivatives with.
+pub fn rough(z: &BigComplex) -> BigComplex {
+    (
+        z.0.clone().with_precision(DERIVATIVE_BITS).value(),
+        z.1.clone().with_precision(DERIVATIVE_BITS).value(),
+    )
+}
+
+pub fn add(a: &BigComplex, b: &BigComplex) -> BigComplex {
+    (&a.0 + &b.0, &a.1 + &b.1)
+}
+
+pub fn sub(a: &BigComplex, b: &BigComplex) -> BigComplex {
+    (&a.0 - &b.0, &a.1 - &b.1)
+}
+
+pub fn double(z: &BigComplex) -> BigComplex {
+    add(z, z)
+}
+
+pub fn mul(a: &BigComplex, b: &BigComplex) -> BigComplex {
+    (&a.0 * &b.0 - &a.1 * &b.1, &a.0 * &b.1 + &a.1 * &b.0)
+}
+
+/// `a / b`, or `None` if b is 0. Both should be [`rough`].
+pub fn div(a: &BigComplex, b: &BigComplex) -> Option<BigComplex> {
+    let norm = b.0.sqr() + b.1.sqr();
+    if norm.repr().is_zero() {
+        return None;
+    }
+    let product = mul(a, &(b.0.clone(), -b.1.clone()));
+    Some((product.0 / &norm, product.1 / &norm))
+}
+
+/// The derivative of the next step of the orbit with respect to c, 2 z d + 1.
+pub fn derivative_step(z: &BigComplex, derivative: &BigComplex) -> BigComplex {
+    add(&double(&mul(&rough(z), derivative)), &one())
+}
+
+/// Roughly log2 |z|, which unlike f64 doesn't underflow. It is -inf for 0.
+fn log2_abs(z: &BigComplex) -> f64 {
+    let z = rough(z);
+    (z.0.sqr() + z.1.sqr()).log2_est() as f64 / 2.0
+}
+
+/// Whether a step of Newton's method is small enough to have converged, within
+/// [`NEWTON_TOLERANCE_BITS`] of the precision.
+pub fn converged(delta: &BigComplex, bits: usize) -> bool {
+    log2_abs(delta) <= -((bits - NEWTON_TOLERANCE_BITS) as f64)
+}
+
+/// Finds the nucleus of the minibrot whose atom domain contains `c`, viewed at `zoom`. The
+/// period is taken from the domain, then Newton's method is run on f^p(0) = 0 with the
+/// precision raised until it resolves the minibrot.
+pub fn locate(c: &BigPoint, zoom: f32, max_iterations: u32) -> Result<Nucleus> {
+    let mut bits = precision_for_zoom(zoom);
+    let mut c = (big::to_binary(&c.x, bits), big::to_binary(&c.y, bits));
+    let mut period = atom_domain_period(&c, bits, max_iterations);
+    loop {
+        c = newton(c, period, bits)?;
+        period = lowest_period(&c, period, bits);
+        let size = size_estimate(&c, period, bits)
+            .ok_or_else(|| anyhow!("couldn't estimate the size of the minibrot"))?;
+        let zoom = view_zoom_log2(size.log2_est() as f64);
+        if precision_for_zoom(zoom) <= bits {
+            let digits = big::digits_for_zoom(zoom);
+            return Ok(Nucleus {
+                position: BigPoint {
+                    x: big::from_binary(&c.0, digits),
+                    y: big::from_binary(&c.1, digits),
+                },
+                period,
+                size,
+            });
+        }
+        bits = precision_for_zoom(zoom);
+        c = (
+            c.0.with_precision(bits).value(),
+            c.1.with_precision(bits).value(),
+        );
+    }
+}
+
+/// The iteration at which the orbit of c comes closest to 0, before escaping or running out
+/// of iterations. c is in that period's atom domain, which surrounds the minibrot.
+fn atom_domain_period(c: &BigComplex, bits: usize, max_iterations: u32) -> usize {
+    let mut z = zero(bits);
+    let mut closest = f64::INFINITY;
+    let mut period = 1;
+    for iteration in 1..=max_iterations as usize {
+        z = step(&z, c);
+        let distance = log2_abs(&z);
+        if distance < closest {
+            closest = distance;
+            period = iteration;
+        }
+        if distance > ESCAPE_RADIUS.log2() {
+            break;
+        }
+    }
+    period
+}
+
+/// Newton's method on f^p(0) = 0, with the derivative with respect to c carried in
+/// [`DERIVATIVE_BITS`].
+pub fn newton(mut c: BigComplex, period: usize, bits: usize) -> Result<BigComplex> {
+    for _ in 0..MAX_NEWTON_STEPS {
+        let mut z = zero(bits);
+        let mut derivative = zero(DERIVATIVE_BITS);
+        for _ in 0..period {
+            derivative = derivative_step(&z, &derivative);
+            z = step(&z, &c);
+        }
+        let Some(delta) = div(&rough(&z), &derivative) else {
+            break;
+        };
+        c = sub(&c, &delta);
+        if converged(&delta, bits) {
+            return Ok(c);
+        }
+    }
+    Err(anyhow!("couldn't find the nucleus of period {}", period))
+}
+
+/// Points inside a minibrot come closest to 0 at multiples of its period, which Newton's method
+/// also converges on. This finds the smallest divisor of `period` that c is already a nucleus
+/// for, to within the tolerance of [`newton`].
+fn lowest_period(c: &BigComplex, period: usize, bits: usize) -> usize {
+    let mut z = zero(bits);
+    let mut derivative = zero(DERIVATIVE_BITS);
+    for iteration in 1..period {
+        derivative = derivative_step(&z, &derivative);
+        z = step(&z, c);
+        if period.is_multiple_of(iteration)
+            && div(&rough(&z), &derivative).is_some_and(|delta| converged(&delta, bits))
+        {
+            return iteration;
+        }
+    }
+    period
+}
+
+/// Estimates the size of the minibrot from the derivatives along its nucleus' orbit, as
+/// 1 / |b l^2| with l the derivative of z_p with respect to z_1 and b the sum of 1 / l over the
+/// orbit. `None` if a derivative is 0.
+fn size_estimate(c: &BigComplex, period: usize, bits: usize) -> Option<FBig> {
+    let mut z = zero(bits);
+    let mut l = one();
+    let mut b = one();
+    for _ in 1..period {
+        z = step(&z, c);
+        l = double(&mul(&rough(&z), &l));
+        b = add(&b, &div(&one(), &l)?);
+    }
+    let product = mul(&b, &mul(&l, &l));
+    let norm = product.0.sqr() + product.1.sqr();
+    if norm.repr().is_zero() {
+        return None;
+    }
+    Some(FBig::ONE / norm.sqrt())
+}
@@ -0,0 +1,15 @@
+// Appended to each shader that draws to the window by `output_shader_source`, which also
+// declares ENCODE_SRGB. The fragment shaders work in linear light and return their colours
+// through `output_colour`, which encodes them when the window's view isn't sRGB.
+fn linear_to_srgb(colour: vec3<f32>) -> vec3<f32> {
+    let low = colour * 12.92;
+    let high = 1.055 * pow(colour, vec3<f32>(1.0 / 2.4)) - 0.055;
+    return select(high, low, colour <= vec3<f32>(0.0031308));
+}
+
+fn output_colour(colour: vec3<f32>) -> vec4<f32> {
+    if ENCODE_SRGB {
+        return vec4<f32>(linear_to_srgb(clamp(colour, vec3<f32>(0.0), vec3<f32>(1.0))), 1.0);
+    }
+    return vec4<f32>(colour, 1.0);
+}
@@ -0,0 +1,727 @@
+use std::path::Path;
+
+use anyhow::{anyhow, Context, Result};
+
+/// Texels in the palette texture.
+const PALETTE_SIZE: u32 = 1024;
+/// Points each GIMP gradient segment is sampled at, to follow its blending curve.
+const SEGMENT_SAMPLES: usize = 16;
+/// Ultra Fractal gradients have this many positions, and wrap round.
+const UGR_POSITIONS: f32 = 400.0;
+
+/// A colour at a position along a gradient, from 0 to 1. Colours are sRGB encoded, from 0 to 1,
+/// as they are written in gradient files.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Stop {
+    pub position: f32,
+    pub colour: [f32; 3],
+}
+
+impl Stop {
+    fn new(position: f32, colour: [f32; 3]) -> Self {
+        Self { position, colour }
+    }
+
+    fn from_bytes(position: f32, [r, g, b]: [u8; 3]) -> Self {
+        Self::new(position, [r, g, b].map(|channel| channel as f32 / 255.0))
+    }
+}
+
+/// The colour space stops are interpolated in. Blending sRGB encoded colours darkens and greys
+/// the midpoints, which linear light avoids, and OKLab also keeps the lightness even.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Interpolation {
+    Oklab,
+    Linear,
+    Srgb,
+}
+
+impl Interpolation {
+    pub fn parse(name: &str) -> Result<Self> {
+        match name.trim() {
+            "oklab" => Ok(Interpolation::Oklab),
+            "linear" => Ok(Interpolation::Linear),
+            "srgb" => Ok(Interpolation::Srgb),
+            _ => Err(anyhow!(
+                "unknown interpolation {:?}, expected oklab, linear or srgb",
+                name
+            )),
+        }
+    }
+
+    /// Blends two sRGB encoded colours in this space.
+    fn mix(self, a: [f32; 3], b: [f32; 3], fraction: f32) -> [f32; 3] {
+        match self {
+            Interpolation::Oklab => {
+                let (a, b) = (oklab(a.map(srgb_to_linear)), oklab(b.map(srgb_to_linear)));
+                oklab_to_linear(mix(a, b, fraction)).map(linear_to_srgb)
+            }
+            Interpolation::Linear => {
+                let (a, b) = (a.map(srgb_to_linear), b.map(srgb_to_linear));
+                mix(a, b, fraction).map(linear_to_srgb)
+            }
+            Interpolation::Srgb => mix(a, b, fraction),
+        }
+    }
+}
+
+/// Colours interpolated between stops.
+#[derive(Debug, Clone)]
+pub struct Gradient {
+    pub name: String,
+    stops: Vec<Stop>,
+    /// Interpolates from the last stop round to the first, for gradients that are meant to be
+    /// repeated.
+    wrap: bool,
+}
+
+impl Gradient {
+    pub fn new(name: &str, mut stops: Vec<Stop>, wrap: bool) -> Result<Self> {
+        if stops.is_empty() {
+            return Err(anyhow!("the gradient {:?} has no colours", name));
+        }
+        stops.sort_by(|a, b| a.position.total_cmp(&b.position));
+        Ok(Self {
+            name: name.to_string(),
+            stops,
+            wrap,
+        })
+    }
+
+    /// Imports a Fractint `.map`, GIMP `.ggr` or Ultra Fractal `.ugr` file, by its extension.
+    pub fn load(path: &str) -> Result<Self> {
+        let text = std::fs::read_to_string(path).with_context(|| format!("reading {}", path))?;
+        let name = Path::new(path)
+            .file_stem()
+            .map_or(path.into(), |stem| stem.to_string_lossy());
+        let extension = Path::new(path)
+            .extension()
+            .map(|extension| extension.to_string_lossy().to_lowercase());
+        let gradient = match extension.as_deref() {
+            Some("map") => Gradient::new(&name, parse_map(&text)?, false),
+            Some("ggr") => parse_ggr(&name, &text),
+            Some("ugr") => parse_ugr(&name, &text),
+            _ => Err(anyhow!(
+                "expected a .map, .ggr or .ugr file, got {:?}",
+                path
+            )),
+        };
+        gradient.with_context(|| format!("parsing {}", path))
+    }
+
+    /// The colour at `t`, which wraps round for wrapping gradients and is clamped otherwise.
+    pub fn sample(&self, t: f32, interpolation: Interpolation) -> [f32; 3] {
+        let t = if self.wrap {
+            t.rem_euclid(1.0)
+        } else {
+            t.clamp(0.0, 1.0)
+        };
+        let (first, last) = (self.stops[0], self.stops[self.stops.len() - 1]);
+        // Wrapping gradients continue through copies of the end stops a turn away
+        let (before, after) = if self.wrap {
+            (
+                Stop::new(last.position - 1.0, last.colour),
+                Stop::new(first.position + 1.0, first.colour),
+            )
+        } else {
+            (Stop::new(0.0, first.colour), Stop::new(1.0, last.colour))
+        };
+        let stops = std::iter::once(before)
+            .chain(self.stops.iter().copied())
+            .chain(std::iter::once(after))
+            .collect::<Vec<_>>();
+        let i = stops
+            .windows(2)
+            .position(|pair| t < pair[1].position)
+            .unwrap_or(stops.len() - 2);
+        let (a, b) = (stops[i], stops[i + 1]);
+        let width = b.position - a.position;
+        let fraction = if width > 0.0 {
+            ((t - a.position) / width).clamp(0.0, 1.0)
+        } else {
+            1.0
+        };
+        interpolation.mix(a.colour, b.colour, fraction)
+    }
+
+    /// The gradient sampled across the palette texture, sRGB encoded. The last texel is at
+    /// t = 1, so that wrapping gradients join up with the first.
+    fn texels(&self, interpolation: Interpolation) -> Vec<[u8; 4]> {
+        (0..PALETTE_SIZE)
+            .map(|i| {
+                let [r, g, b] = self
+                    .sample(i as f32 / (PALETTE_SIZE - 1) as f32, interpolation)
+                    .map(|channel| (channel.clamp(0.0, 1.0) * 255.0).round() as u8);
+                [r, g, b, 255]
+            })
+            .collect()
+    }
+}
+
+fn mix(a: [f32; 3], b: [f32; 3], fraction: f32) -> [f32; 3] {
+    [0, 1, 2].map(|i| a[i] + (b[i] - a[i]) * fraction)
+}
+
+fn srgb_to_linear(channel: f32) -> f32 {
+    if channel <= 0.04045 {
+        channel / 12.92
+    } else {
+        ((channel + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn linear_to_srgb(channel: f32) -> f32 {
+    if channel <= 0.0031308 {
+        channel * 12.92
+    } else {
+        1.055 * channel.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+/// Converts linear sRGB to OKLab, as defined by Björn Ottosson.
+fn oklab([r, g, b]: [f32; 3]) -> [f32; 3] {
+    let l = (0.4122215 * r + 0.5363325 * g + 0.051446 * b).cbrt();
+    let m = (0.2119035 * r + 0.6806996 * g + 0.107397 * b).cbrt();
+    let s = (0.0883025 * r + 0.2817188 * g + 0.6299787 * b).cbrt();
+    [
+        0.2104543 * l + 0.7936178 * m - 0.004072 * s,
+        1.9779985 * l - 2.4285922 * m + 0.4505937 * s,
+        0.025904 * l + 0.7827718 * m - 0.8086758 * s,
+    ]
+}
+
+fn oklab_to_linear([lightness, a, b]: [f32; 3]) -> [f32; 3] {
+    let l = (lightness + 0.3963378 * a + 0.2158038 * b).powi(3);
+    let m = (lightness - 0.1055613 * a - 0.0638542 * b).powi(3);
+    let s = (lightness - 0.0894842 * a - 1.2914855 * b).powi(3);
+    [
+        4.0767417 * l - 3.3077116 * m + 0.2309699 * s,
+        -1.268438 * l + 2.6097574 * m - 0.3413194 * s,
+        -0.0041961 * l - 0.7034186 * m + 1.7076147 * s,
+    ]
+}
+
+/// The gradients the hotkey cycles through, starting with the original blue-grey ramp.
+pub fn library() -> Vec<Gradient> {
+    let gradient = |name: &str, stops: &[(f32, [u8; 3])], wrap: bool| {
+        let stops = stops
+            .iter()
+            .map(|&(position, colour)| Stop::from_bytes(position, colour))
+            .collect();
+        Gradient::new(name, stops, wrap).unwrap()
+    };
+    // The cosine palette fractalbox used to have, with its phases in radians
+    let cosine = (0..=32)
+        .map(|i| {
+            let t = i as f32 / 32.0;
+            let colour = [0.0f32, 120.0, 240.0].map(|phase| (t.sqrt() + phase).cos().powi(2));
+            Stop::new(t, colour)
+        })
+        .collect();
+    vec![
+        Gradient::new(
+            "default",
+            vec![
+                Stop::new(0.0, [0.0, 0.0, 0.0]),
+                Stop::new(1.0, [0.5, 0.6, 0.7]),
+            ],
+            false,
+        )
+        .unwrap(),
+        gradient(
+            "ultra",
+            &[
+                (0.0, [0, 7, 100]),
+                (0.16, [32, 107, 203]),
+                (0.42, [237, 255, 255]),
+                (0.6425, [255, 170, 0]),
+                (0.8575, [0, 2, 0]),
+            ],
+            true,
+        ),
+        gradient(
+            "fire",
+            &[
+                (0.0, [0, 0, 0]),
+                (0.3, [160, 20, 0]),
+                (0.6, [255, 140, 0]),
+                (0.85, [255, 230, 60]),
+                (1.0, [255, 255, 255]),
+            ],
+            false,
+        ),
+        gradient(
+            "ocean",
+            &[
+                (0.0, [2, 10, 40]),
+                (0.45, [0, 110, 140]),
+                (0.8, [120, 220, 210]),
+                (1.0, [250, 255, 250]),
+            ],
+            false,
+        ),
+        gradient(
+            "rainbow",
+            &[
+                (0.0, [255, 0, 0]),
+                (1.0 / 6.0, [255, 255, 0]),
+                (2.0 / 6.0, [0, 255, 0]),
+                (3.0 / 6.0, [0, 255, 255]),
+                (4.0 / 6.0, [0, 0, 255]),
+                (5.0 / 6.0, [255, 0, 255]),
+            ],
+            true,
+        ),
+        gradient("grey", &[(0.0, [0, 0, 0]), (1.0, [255, 255, 255])], false),
+        Gradient::new("cosine", cosine, false).unwrap(),
+    ]
+}
+
+/// Fractint maps list a colour per line as red, green and blue from 0 to 255, optionally
+/// followed by a comment. The colours are spread evenly along the gradient.
+fn parse_map(text: &str) -> Result<Vec<Stop>> {
+    let colours = text
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(number, line)| {
+            let channels = line
+                .split_whitespace()
+                .take(3)
+                .map(str::parse)
+                .collect::<Result<Vec<u8>, _>>();
+            match channels.as_deref() {
+                Ok(&[r, g, b]) => Ok([r, g, b]),
+                _ => Err(anyhow!("expected a colour on line {}", number + 1)),
+            }
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let last = colours.len().saturating_sub(1).max(1) as f32;
+    Ok(colours
+        .into_iter()
+        .enumerate()
+        .map(|(i, colour)| Stop::from_bytes(i as f32 / last, colour))
+        .collect())
+}
+
+/// GIMP gradients are made of segments, each blending between a colour at either end along a
+/// curve that passes halfway at its midpoint. The segments are sampled into stops.
+fn parse_ggr(name: &str, text: &str) -> Result<Gradient> {
+    let mut lines = text.lines();
+    if lines.next().map(str::trim) != Some("GIMP Gradient") {
+        return Err(anyhow!("missing the \"GIMP Gradient\" header"));
+    }
+    let mut name = name.to_string();
+    let mut line = lines.next().unwrap_or_default();
+    if let Some(title) = line.strip_prefix("Name:") {
+        name = title.trim().to_string();
+        line = lines.next().unwrap_or_default();
+    }
+    let count = line
+        .trim()
+        .parse::<usize>()
+        .map_err(|_| anyhow!("expected the number of segments, got {:?}", line))?;
+
+    let mut stops = Vec::new();
+    for number in 0..count {
+        let line = lines
+            .next()
+            .ok_or_else(|| anyhow!("expected {} segments, found {}", count, number))?;
+        let fields = line
+            .split_whitespace()
+            .map(str::parse::<f32>)
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|_| anyhow!("invalid segment {:?}", line))?;
+        if fields.len() < 13 {
+            return Err(anyhow!("expected at least 13 fields in segment {:?}", line));
+        }
+        let (left, middle, right) = (fields[0], fields[1], fields[2]);
+        let left_colour = [fields[3], fields[4], fields[5]];
+        let right_colour = [fields[7], fields[8], fields[9]];
+        let (blending, colouring) = (fields[11] as u32, fields[12] as u32);
+        let width = right - left;
+        let middle = if width > 0.0 {
+            (middle - left) / width
+        } else {
+            0.5
+        };
+        for sample in 0..=SEGMENT_SAMPLES {
+            let p = sample as f32 / SEGMENT_SAMPLES as f32;
+            let factor = ggr_blend(blending, p, middle);
+            let colour = match colouring {
+                1 | 2 => hsv_mix(left_colour, right_colour, factor, colouring == 2),
+                _ => mix(left_colour, right_colour, factor),
+            };
+            stops.push(Stop::new(left + p * width, colour));
+        }
+    }
+    Gradient::new(&name, stops, false)
+}
+
+/// How far along a GIMP segment's colours a point `p` of the way across it is, for the
+/// segment's blending type, with `middle` where it passes halfway.
+fn ggr_blend(blending: u32, p: f32, middle: f32) -> f32 {
+    const EPSILON: f32 = 1e-6;
+    let linear = if p <= middle {
+        if middle < EPSILON {
+            0.0
+        } else {
+            0.5 * p / middle
+        }
+    } else if 1.0 - middle < EPSILON {
+        1.0
+    } else {
+        0.5 + 0.5 * (p - middle) / (1.0 - middle)
+    };
+    match blending {
+        1 => p.powf(0.5f32.ln() / middle.max(EPSILON).ln()),
+        2 => 0.5 * ((std::f32::consts::PI * (linear - 0.5)).sin() + 1.0),
+        3 => (1.0 - (linear - 1.0).powi(2)).sqrt(),
+        4 => 1.0 - (1.0 - linear.powi(2)).sqrt(),
+        5 => (p >= middle) as u32 as f32,
+        _ => linear,
+    }
+}
+
+/// Blends through HSV, with the hue turning anticlockwise or clockwise as GIMP's colour types
+/// ask.
+fn hsv_mix(a: [f32; 3], b: [f32; 3], fraction: f32, clockwise: bool) -> [f32; 3] {
+    let (a, b) = (rgb_to_hsv(a), rgb_to_hsv(b));
+    let mut turn = (b[0] - a[0]).rem_euclid(1.0);
+    if clockwise && turn > 0.0 {
+        turn -= 1.0;
+    }
+    let hue = (a[0] + turn * fraction).rem_euclid(1.0);
+    let [_, saturation, value] = mix(a, b, fraction);
+    hsv_to_rgb([hue, saturation, value])
+}
+
+fn rgb_to_hsv([r, g, b]: [f32; 3]) -> [f32; 3] {
+    let max = r.max(g).max(b);
+    let range = max - r.min(g).min(b);
+    let hue = if range == 0.0 {
+        0.0
+    } else if max == r {
+        ((g - b) / range).rem_euclid(6.0)
+    } else if max == g {
+        (b - r) / range + 2.0
+    } else {
+        (r - g) / range + 4.0
+    };
+    let saturation = if max == 0.0 { 0.0 } else { range / max };
+    [hue / 6.0, saturation, max]
+}
+
+fn hsv_to_rgb([hue, saturation, value]: [f32; 3]) -> [f32; 3] {
+    [5.0, 3.0, 1.0].map(|n| {
+        let k = (n + hue * 6.0) % 6.0;
+        value - value * saturation * k.min(4.0 - k).clamp(0.0, 1.0)
+    })
+}
+
+/// Ultra Fractal gradient files hold named entries whose `gradient:` section lists colours as
+/// `index=<0 to 399> color=<0xBBGGRR in decimal>`. The first entry is imported.
+fn parse_ugr(name: &str, text: &str) -> Result<Gradient> {
+    let section = text
+        .split_once("gradient:")
+        .ok_or_else(|| anyhow!("no gradient section"))?
+        .1;
+    let section = section
+        .split(['}'])
+        .next()
+        .unwrap_or_default()
+        .split("opacity:")
+        .next()
+        .unwrap_or_default();
+    let name = section
+        .split_once("title=\"")
+        .and_then(|(_, rest)| rest.split_once('"'))
+        .map_or(name, |(title, _)| title);
+
+    let mut stops = Vec::new();
+    let mut index = None;
+    for token in section.split_whitespace() {
+        if let Some(value) = token.strip_prefix("index=") {
+            let value = value
+                .parse::<i32>()
+                .map_err(|_| anyhow!("invalid index {:?}", value))?;
+            index = Some(value);
+        } else if let Some(value) = token.strip_prefix("color=") {
+            let colour = value
+                .parse::<u32>()
+                .map_err(|_| anyhow!("invalid colour {:?}", value))?;
+            let index = index
+                .take()
+                .ok_or_else(|| anyhow!("colour {} has no index", value))?;
+            let [r, g, b, _] = colour.to_le_bytes();
+            stops.push(Stop::from_bytes(index as f32 / UGR_POSITIONS, [r, g, b]));
+        }
+    }
+    Gradient::new(name, stops, true)
+}
+
+/// Appends `output.wgsl` to a shader that draws to a view of the window with `format`, along
+/// with whether its `output_colour` has to encode colours because the format isn't sRGB.
+pub fn output_shader_source(source: &str, format: wgpu::TextureFormat) -> String {
+    format!(
+        "{}\nconst ENCODE_SRGB: bool = {};\n\n{}",
+        source,
+        !format.is_srgb(),
+        include_str!("output.wgsl")
+    )
+}
+
+/// The gradient and how the colourings' values are mapped onto it, by `get_colour`.
+#[derive(Debug)]
+pub struct Palette {
+    pub gradient: Gradient,
+    /// The gradient's place in [`library`], which the hotkey cycles from.
+    library_index: usize,
+    /// Added to the value after scaling.
+    pub offset: f32,
+    /// How many times the gradient is stretched across the values from 0 to 1.
+    pub scale: f32,
+    /// Starts the gradient over past its end instead of holding its last colour.
+    pub repeat: bool,
+    pub interpolation: Interpolation,
+}
+
+impl Palette {
+    pub fn new() -> Self {
+        Self {
+            gradient: library().remove(0),
+            library_index: 0,
+            offset: 0.0,
+            scale: 1.0,
+            repeat: false,
+            interpolation: Interpolation::Oklab,
+        }
+    }
+
+    /// Switches to the next gradient in the library.
+    pub fn next(&mut self) {
+        let library = library();
+        self.library_index = (self.library_index + 1) % library.len();
+        self.gradient = library[self.library_index].clone();
+    }
+
+    /// Switches to a gradient in the library by name.
+    pub fn select(&mut self, name: &str) -> Result<()> {
+        let library = library();
+        let Some(index) = library.iter().position(|gradient| gradient.name == name) else {
+            let names = library
+                .iter()
+                .map(|gradient| gradient.name.as_str())
+                .collect::<Vec<_>>();
+            return Err(anyhow!(
+                "unknown palette {:?}, expected one of {}",
+                name,
+                names.join(", ")
+            ));
+        };
+        self.library_index = index;
+        self.gradient = library[index].clone();
+        Ok(())
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct PaletteUniform {
+    pub offset: f32,
+    pub scale: f32,
+    pub repeat: u32,
+    pub _padding: u32,
+}
+
+impl PaletteUniform {
+    pub fn new() -> Self {
+        Self {
+            offset: 0.0,
+            scale: 1.0,
+            repeat: 0,
+            _padding: 0,
+        }
+    }
+
+    pub fn update(&mut self, palette: &Palette) {
+        self.offset = palette.offset;
+        self.scale = palette.scale;
+        self.repeat = palette.repeat as u32;
+    }
+}
+
+/// The gradient sampled into a 1D texture, which `sample_palette` interpolates. The texture is
+/// sRGB, so the shader reads and blends its texels in linear light.
+#[derive(Debug)]
+pub struct PaletteTexture {
+    texture: wgpu::Texture,
+    pub view: wgpu::TextureView,
+}
+
+impl PaletteTexture {
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue, palette: &Palette) -> Self {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("palette_texture"),
+            size: wgpu::Extent3d {
+                width: PALETTE_SIZE,
+                height: 1,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D1,
+            format: wgpu::TextureFormat::Rgba8UnormSrgb,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let palette_texture = Self { texture, view };
+        palette_texture.write(queue, palette);
+        palette_texture
+    }
+
+    /// Uploads the palette's gradient, after it or its interpolation changes.
+    pub fn write(&self, queue: &wgpu::Queue, palette: &Palette) {
+        queue.write_texture(
+            wgpu::ImageCopyTexture {
+                texture: &self.texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            bytemuck::cast_slice(&palette.gradient.texels(palette.interpolation)),
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(4 * PALETTE_SIZE),
+                rows_per_image: Some(1),
+            },
+            self.texture.size(),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_colour(actual: [f32; 3], expected: [f32; 3]) {
+        assert!(
+            actual
+                .iter()
+                .zip(expected)
+                .all(|(a, e)| (a - e).abs() < 1e-4),
+            "{:?} != {:?}",
+            actual,
+            expected
+        );
+    }
+
+    /// A single segment GIMP gradient from `left` to `right`, with the given blending and
+    /// colour types.
+    fn ggr(middle: f32, left: [f32; 3], right: [f32; 3], blending: u32, colouring: u32) -> String {
+        format!(
+            "GIMP Gradient\nName: Test\n1\n0 {} 1 {} {} {} 1 {} {} {} 1 {} {}\n",
+            middle, left[0], left[1], left[2], right[0], right[1], right[2], blending, colouring
+        )
+    }
+
+    #[test]
+    fn map_colours_are_spread_evenly_and_comments_ignored() {
+        let stops = parse_map("255 0 0 red\n\n0 255 0\t; green\n  0 0 255\n").unwrap();
+        assert_eq!(stops.len(), 3);
+        assert_eq!(
+            stops.iter().map(|stop| stop.position).collect::<Vec<_>>(),
+            [0.0, 0.5, 1.0]
+        );
+        assert_colour(stops[1].colour, [0.0, 1.0, 0.0]);
+        assert_colour(stops[2].colour, [0.0, 0.0, 1.0]);
+    }
+
+    #[test]
+    fn map_errors_name_the_line() {
+        let error = parse_map("0 0 0\n255 255\n").unwrap_err();
+        assert_eq!(error.to_string(), "expected a colour on line 2");
+        assert!(parse_map("0 0 256\n").is_err());
+    }
+
+    #[test]
+    fn ggr_blending_types() {
+        for blending in 0..=2 {
+            assert!(
+                (ggr_blend(blending, 0.3, 0.3) - 0.5).abs() < 1e-6,
+                "{}",
+                blending
+            );
+        }
+        for blending in 0..=4 {
+            assert_eq!(ggr_blend(blending, 0.0, 0.3), 0.0, "{}", blending);
+            assert!(
+                (ggr_blend(blending, 1.0, 0.3) - 1.0).abs() < 1e-6,
+                "{}",
+                blending
+            );
+        }
+        // Linear is piecewise, curved follows a power, and sine eases in
+        assert!((ggr_blend(0, 0.15, 0.3) - 0.25).abs() < 1e-6);
+        assert!((ggr_blend(1, 0.09, 0.3) - 0.25).abs() < 1e-6);
+        assert!(ggr_blend(2, 0.15, 0.3) < 0.25);
+        // Spheres rise quickly or slowly
+        assert!(ggr_blend(3, 0.15, 0.3) > 0.25);
+        assert!(ggr_blend(4, 0.15, 0.3) < 0.25);
+        // Step jumps at the middle
+        assert_eq!(ggr_blend(5, 0.29, 0.3), 0.0);
+        assert_eq!(ggr_blend(5, 0.3, 0.3), 1.0);
+    }
+
+    #[test]
+    fn ggr_segments_blend_rgb() {
+        let gradient =
+            parse_ggr("file", &ggr(0.5, [1.0, 0.0, 0.0], [0.0, 0.0, 1.0], 0, 0)).unwrap();
+        assert_eq!(gradient.name, "Test");
+        assert_colour(
+            gradient.sample(0.25, Interpolation::Srgb),
+            [0.75, 0.0, 0.25],
+        );
+        assert_colour(gradient.sample(1.0, Interpolation::Srgb), [0.0, 0.0, 1.0]);
+    }
+
+    #[test]
+    fn ggr_hsv_colour_types_turn_either_way() {
+        let (red, blue) = ([1.0, 0.0, 0.0], [0.0, 0.0, 1.0]);
+        let anticlockwise = parse_ggr("", &ggr(0.5, red, blue, 0, 1)).unwrap();
+        assert_colour(
+            anticlockwise.sample(0.5, Interpolation::Srgb),
+            [0.0, 1.0, 0.0],
+        );
+        let clockwise = parse_ggr("", &ggr(0.5, red, blue, 0, 2)).unwrap();
+        assert_colour(clockwise.sample(0.5, Interpolation::Srgb), [1.0, 0.0, 1.0]);
+    }
+
+    #[test]
+    fn ggr_errors() {
+        assert!(parse_ggr("", "GIMP Palette\n1\n").is_err());
+        assert!(parse_ggr("", "GIMP Gradient\n2\n0 0.5 1 0 0 0 1 1 1 1 1 0 0\n").is_err());
+        assert!(parse_ggr("", "GIMP Gradient\n1\n0 0.5 1 0 0 0 1 1 1 1 1\n").is_err());
+    }
+
+    #[test]
+    fn ugr_colours_are_bgr_and_wrap() {
+        let text = "first {\ngradient:\n  title=\"Fire\" smooth=no\n  index=0 color=255\n  \
+                    index=200 color=16711680\nopacity:\n  index=0 opacity=255\n}\n\
+                    second {\ngradient:\n  index=0 color=65280\n}\n";
+        let gradient = parse_ugr("file", text).unwrap();
+        assert_eq!(gradient.name, "Fire");
+        assert_colour(gradient.sample(0.0, Interpolation::Srgb), [1.0, 0.0, 0.0]);
+        assert_colour(gradient.sample(0.5, Interpolation::Srgb), [0.0, 0.0, 1.0]);
+        assert_colour(gradient.sample(0.75, Interpolation::Srgb), [0.5, 0.0, 0.5]);
+        assert_colour(gradient.sample(1.25, Interpolation::Srgb), [0.5, 0.0, 0.5]);
+    }
+
+    #[test]
+    fn ugr_errors() {
+        assert!(parse_ugr("", "first {\n}\n").is_err());
+        assert!(parse_ugr("", "first {\ngradient:\n color=255\n}\n").is_err());
+        assert!(parse_ugr("", "first {\ngradient:\n index=0 color=red\n}\n").is_err());
+    }
+}
@@ -0,0 +1,255 @@
+use std::sync::mpsc;
+use std::thread;
+
+use cgmath::{MetricSpace, Point2};
+
+use crate::big::{self, BigPoint};
+use crate::camera::Camera;
+use crate::iterations::MAX_ITERATIONS;
+
+/// Beyond this zoom, neighbouring pixels are no longer distinguishable in f64 and the shader
+/// switches to iterating deltas against a reference orbit. Only the Mandelbrot formula has a
+/// delta iteration, so the other formulas keep rendering directly.
+pub const PERTURBATION_ZOOM: f32 = 25.0;
+pub const MAX_REFERENCE_LENGTH: usize = MAX_ITERATIONS as usize + 1;
+const REFERENCE_BAILOUT: f64 = 5.0;
+/// The orbit's precision in bits is rounded up to a multiple of this.
+const PRECISION_STEP: usize = 64;
+
+#[derive(Debug, Clone, PartialEq)]
+struct ReferenceKey {
+    centre: BigPoint,
+    julia_constant: Option<Point2<f64>>,
+    precision: usize,
+    /// The number of points computed unless the orbit escapes first, at least one more than
+    /// the iteration limit.
+    length: usize,
+}
+
+impl ReferenceKey {
+    /// Whether an orbit computed for this key can be used for a view that wants `wanted`, and
+    /// reaches `radius` from its centre.
+    fn covers(&self, wanted: &ReferenceKey, radius: f64) -> bool {
+        let distance = wanted
+            .centre
+            .offset_from(&self.centre)
+            .distance(Point2::new(0.0, 0.0));
+        self.julia_constant == wanted.julia_constant
+            && self.precision >= wanted.precision
+            && self.length >= wanted.length
+            && distance < radius
+    }
+}
+
+/// A high precision orbit computed on the CPU, which the shader iterates low precision pixel
+/// deltas against.
+#[derive(Debug)]
+pub struct ReferenceOrbit {
+    key: Option<ReferenceKey>,
+    pub points: Vec<[f64; 2]>,
+    /// The orbit being computed on a worker thread, since deep orbits take long enough to stall
+    /// the window. The current one is rendered with until it is done.
+    pending: Option<(ReferenceKey, mpsc::Receiver<Vec<[f64; 2]>>)>,
+}
+
+impl ReferenceOrbit {
+    pub fn new() -> Self {
+        Self {
+            key: None,
+            points: vec![[0.0; 2]],
+            pending: None,
+        }
+    }
+
+    pub fn centre(&self) -> Option<&BigPoint> {
+        self.key.as_ref().map(|key| &key.centre)
+    }
+
+    /// Starts recomputing the orbit around the camera's centre if neither the current one nor
+    /// the one being computed can be used for the view, and returns whether a new one has
+    /// arrived whose points need to be uploaded. An orbit is kept while panning as long as its
+    /// centre stays close to the screen, and lowering the iteration limit keeps it too.
+    pub fn update(
+        &mut self,
+        camera: &Camera,
+        julia_constant: Option<Point2<f64>>,
+        max_iterations: u32,
+    ) -> bool {
+        let mut arrived = false;
+        if let Some((key, receiver)) = &self.pending {
+            if let Ok(points) = receiver.try_recv() {
+                self.key = Some(key.clone());
+                self.points = points;
+                self.pending = None;
+                arrived = true;
+            }
+        }
+
+        // Both are rounded up, so that zooming in, which raises them a little every frame,
+        // only recomputes the orbit once it has outgrown them
+        let wanted = ReferenceKey {
+            centre: camera.position().clone(),
+            julia_constant,
+            precision: precision_for_zoom(camera.zoom()).next_multiple_of(PRECISION_STEP),
+            length: (max_iterations as usize + 1)
+                .next_power_of_two()
+                .min(MAX_REFERENCE_LENGTH),
+        };
+        let radius = 2.0 * (-camera.zoom() as f64).exp() * camera.aspect.max(1.0) as f64;
+        let pending = self.pending.as_ref().map(|(key, _)| key);
+        if self
+            .key
+            .iter()
+            .chain(pending)
+            .any(|key| key.covers(&wanted, radius))
+        {
+            return arrived;
+        }
+
+        let (sender, receiver) = mpsc::channel();
+        let key = wanted.clone();
+        thread::spawn(move || {
+            // Nobody is waiting for the orbit if a newer one has replaced it
+            let _ = sender.send(compute_orbit(&key));
+        });
+        self.pending = Some((wanted, receiver));
+        arrived
+    }
+}
+
+/// The number of mantissa bits needed to resolve pixels at the given zoom, with some headroom
+/// for the error that accumulates while iterating.
+pub fn precision_for_zoom(zoom: f32) -> usize {
+    (zoom.max(0.0) / std::f32::consts::LN_2) as usize + 64
+}
+
+fn compute_orbit(key: &ReferenceKey) -> Vec<[f64; 2]> {
+    let centre_x = big::to_binary(&key.centre.x, key.precision);
+    let centre_y = big::to_binary(&key.centre.y, key.precision);
+    let (mut zr, mut zi, cr, ci) = match key.julia_constant {
+        Some(constant) => (
+            centre_x,
+            centre_y,
+            big::binary_from_f64(constant.x, key.precision),
+            big::binary_from_f64(constant.y, key.precision),
+        ),
+        None => (
+            big::binary_from_f64(0.0, key.precision),
+            big::binary_from_f64(0.0, key.precision),
+            centre_x,
+            centre_y,
+        ),
+    };
+
+    let mut points = vec![[zr.to_f64().value(), zi.to_f64().value()]];
+    while points.len() < key.length {
+        let product = &zr * &zi;
+        zr = zr.sqr() - zi.sqr() + &cr;
+        zi = &product + &product + &ci;
+
+        let point = [zr.to_f64().value(), zi.to_f64().value()];
+        points.push(point);
+        if point[0] * point[0] + point[1] * point[1] > REFERENCE_BAILOUT {
+            break;
+        }
+    }
+    points
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::*;
+
+    fn key(centre: (f64, f64), julia_constant: Option<(f64, f64)>, length: usize) -> ReferenceKey {
+        ReferenceKey {
+            centre: BigPoint::from_f64(centre.into(), 20),
+            julia_constant: julia_constant.map(Point2::from),
+            precision: 128,
+            length,
+        }
+    }
+
+    /// Updates the orbit until the one it starts computing has arrived.
+    fn wait_for_orbit(orbit: &mut ReferenceOrbit, camera: &Camera, max_iterations: u32) {
+        let start = Instant::now();
+        while !orbit.update(camera, None, max_iterations) {
+            assert!(orbit.pending.is_some(), "no orbit is being computed");
+            assert!(
+                start.elapsed() < Duration::from_secs(10),
+                "the orbit never arrived"
+            );
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    #[test]
+    fn orbits_stop_after_the_first_point_past_the_bailout() {
+        // 0, 1, 2, 5 for c = 1
+        let points = compute_orbit(&key((1.0, 0.0), None, 64));
+        assert_eq!(points, [[0.0, 0.0], [1.0, 0.0], [2.0, 0.0], [5.0, 0.0]]);
+    }
+
+    #[test]
+    fn bounded_orbits_run_to_the_length() {
+        let points = compute_orbit(&key((-1.0, 0.0), None, 64));
+        assert_eq!(points.len(), 64);
+        assert_eq!(points[63], [-1.0, 0.0]);
+    }
+
+    #[test]
+    fn julia_orbits_start_from_the_centre() {
+        let points = compute_orbit(&key((0.5, 0.0), Some((0.0, 1.0)), 3));
+        assert_eq!(points, [[0.5, 0.0], [0.25, 1.0], [-0.9375, 1.5]]);
+    }
+
+    #[test]
+    fn length_and_precision_are_rounded_up() {
+        let mut orbit = ReferenceOrbit::new();
+        let camera = Camera::new((-1.0, 0.0), 30.0, 1.0);
+        wait_for_orbit(&mut orbit, &camera, 100);
+        let key = orbit.key.as_ref().unwrap();
+        assert_eq!((key.length, key.precision), (128, 128));
+        assert_eq!(orbit.points.len(), 128);
+        assert_eq!(orbit.centre(), Some(camera.position()));
+    }
+
+    #[test]
+    fn orbits_are_reused_while_they_cover_the_view() {
+        let mut orbit = ReferenceOrbit::new();
+        let mut camera = Camera::new((-1.0, 0.0), 1.0, 1.0);
+        wait_for_orbit(&mut orbit, &camera, 100);
+        let reused = |orbit: &mut ReferenceOrbit, camera: &Camera, max_iterations| {
+            let arrived = orbit.update(camera, None, max_iterations);
+            let reused = !arrived && orbit.pending.is_none();
+            orbit.pending = None;
+            reused
+        };
+
+        assert!(reused(&mut orbit, &camera, 100));
+        assert!(reused(&mut orbit, &camera, 50));
+        assert!(!reused(&mut orbit, &camera, 200));
+
+        // The view reaches 2 e^-1 from its centre, about 0.74
+        camera.set_location("-0.5 0").unwrap();
+        assert!(reused(&mut orbit, &camera, 100));
+        camera.set_location("0 0").unwrap();
+        assert!(!reused(&mut orbit, &camera, 100));
+
+        camera.set_location("-1 0 50").unwrap();
+        assert!(!reused(&mut orbit, &camera, 100));
+    }
+
+    #[test]
+    fn the_old_orbit_is_kept_until_the_new_one_arrives() {
+        let mut orbit = ReferenceOrbit::new();
+        let mut camera = Camera::new((-1.0, 0.0), 1.0, 1.0);
+        wait_for_orbit(&mut orbit, &camera, 100);
+        camera.set_location("0.25 0").unwrap();
+        orbit.update(&camera, None, 100);
+        assert_eq!(orbit.points[1], [-1.0, 0.0]);
+        wait_for_orbit(&mut orbit, &camera, 100);
+        assert_eq!(orbit.points[1], [0.25, 0.0]);
+    }
+}
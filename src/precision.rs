@@ -0,0 +1,99 @@
+use std::fmt;
+
+use anyhow::{anyhow, Result};
+
+/// Bits kept spare beyond those needed to tell pixels apart, for the rounding errors that build
+/// up over the iterations.
+const GUARD_BITS: f64 = 4.0;
+/// Pixels smaller than this run out of exponent range in the f32 based precisions.
+const MIN_F32_PIXEL_SIZE: f64 = 1e-30;
+/// The same for f64, leaving room below for the differences between neighbouring pixels.
+const MIN_F64_PIXEL_SIZE: f64 = 1e-300;
+
+/// How the shaders represent real numbers. They are written against the aliases `real`, for
+/// arithmetic, and `wide`/`wide2`, for the f64s in the uniforms, which are read with `unpack`.
+/// Each precision prepends a preamble defining them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Precision {
+    /// Plain f32, enough for shallow zooms.
+    F32,
+    /// f32 everywhere except the escape-time iteration, which uses pairs of f32s for about 48
+    /// bits of precision.
+    DoubleSingle,
+    /// Needs [`wgpu::Features::SHADER_F64`].
+    F64,
+}
+
+impl Precision {
+    pub const ALL: [Precision; 3] = [Precision::F32, Precision::DoubleSingle, Precision::F64];
+
+    pub fn parse(name: &str) -> Result<Self> {
+        match name.trim() {
+            "f32" => Ok(Precision::F32),
+            "ds" | "double-single" => Ok(Precision::DoubleSingle),
+            "f64" => Ok(Precision::F64),
+            _ => Err(anyhow!(
+                "unknown precision {:?}, expected f32, ds or f64",
+                name
+            )),
+        }
+    }
+
+    /// The best precision the device supports.
+    pub fn best(features: wgpu::Features) -> Self {
+        if features.contains(wgpu::Features::SHADER_F64) {
+            Precision::F64
+        } else {
+            Precision::DoubleSingle
+        }
+    }
+
+    /// The smallest pixel the best precision the device supports can draw. Perturbation holds
+    /// its deltas in the same precision, so it can't go further either.
+    pub fn min_pixel_size(features: wgpu::Features) -> f64 {
+        match Precision::best(features) {
+            Precision::F64 => MIN_F64_PIXEL_SIZE,
+            Precision::F32 | Precision::DoubleSingle => MIN_F32_PIXEL_SIZE,
+        }
+    }
+
+    pub fn is_supported(self, features: wgpu::Features) -> bool {
+        self != Precision::F64 || features.contains(wgpu::Features::SHADER_F64)
+    }
+
+    /// Picks the cheapest precision that can still tell apart pixels of `pixel_size` at
+    /// coordinates up to `extent`. That is f32 for shallow zooms, then the best the device
+    /// supports.
+    pub fn for_view(extent: f64, pixel_size: f64, features: wgpu::Features) -> Self {
+        let needed_bits = (extent / pixel_size).log2() + GUARD_BITS;
+        if needed_bits <= 24.0 && pixel_size >= MIN_F32_PIXEL_SIZE {
+            Precision::F32
+        } else {
+            Precision::best(features)
+        }
+    }
+
+    /// The shader with the definitions for this precision prepended.
+    pub fn shader_source(self, source: &str) -> String {
+        let preamble = match self {
+            Precision::F64 => include_str!("precision_f64.wgsl"),
+            Precision::F32 | Precision::DoubleSingle => include_str!("precision_f32.wgsl"),
+        };
+        format!(
+            "{}\nconst DOUBLE_SINGLE: bool = {};\n\n{}",
+            preamble,
+            self == Precision::DoubleSingle,
+            source
+        )
+    }
+}
+
+impl fmt::Display for Precision {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Precision::F32 => write!(f, "f32"),
+            Precision::DoubleSingle => write!(f, "double-single"),
+            Precision::F64 => write!(f, "f64"),
+        }
+    }
+}
@@ -0,0 +1,40 @@
+// Real numbers are f32. The uniforms still hold f64s, which are read as their raw bits and
+// converted by hand since the adapter may not support f64 at all.
+alias real = f32;
+alias wide = vec2<u32>;
+alias wide2 = vec4<u32>;
+// The gap between 1 and the next real.
+const REAL_EPSILON: f32 = 1.1920929e-7;
+
+fn wide_x(bits: wide2) -> wide {
+    return bits.xy;
+}
+
+fn wide_y(bits: wide2) -> wide {
+    return bits.zw;
+}
+
+// The f64 as a double-single, the high part holding the top 24 bits of the significand and the
+// low part the next 24. Values outside the range of f32 flush to zero or infinity.
+fn unpack_double_single(bits: wide) -> vec2<f32> {
+    let biased_exponent = i32((bits.y >> 20u) & 0x7ffu);
+    if biased_exponent == 0 {
+        return vec2<f32>(0.0, 0.0);
+    }
+    let exponent = biased_exponent - 1023;
+    let high_bits = ((bits.y & 0xfffffu) << 3u) | (bits.x >> 29u) | 0x800000u;
+    let low_bits = bits.x & 0x1fffffffu;
+    var value = vec2<f32>(ldexp(f32(high_bits), exponent - 23), ldexp(f32(low_bits), exponent - 52));
+    if (bits.y >> 31u) != 0u {
+        value = -value;
+    }
+    return value;
+}
+
+fn unpack(bits: wide) -> real {
+    return unpack_double_single(bits).x;
+}
+
+fn unpack2(bits: wide2) -> vec2<real> {
+    return vec2<real>(unpack(bits.xy), unpack(bits.zw));
+}
@@ -0,0 +1,27 @@
+// Real numbers are f64, which the uniforms already hold.
+alias real = f64;
+alias wide = f64;
+alias wide2 = vec2<f64>;
+// The gap between 1 and the next real.
+const REAL_EPSILON: f32 = 2.220446e-16;
+
+fn wide_x(value: wide2) -> wide {
+    return value.x;
+}
+
+fn wide_y(value: wide2) -> wide {
+    return value.y;
+}
+
+fn unpack(value: wide) -> real {
+    return value;
+}
+
+fn unpack2(value: wide2) -> vec2<real> {
+    return value;
+}
+
+fn unpack_double_single(value: wide) -> vec2<f32> {
+    let high = f32(value);
+    return vec2<f32>(high, f32(value - f64(high)));
+}
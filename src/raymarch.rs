@@ -0,0 +1,153 @@
+use anyhow::{anyhow, Result};
+
+/// Each step of a chain takes two bits of a u32 in the shader.
+pub const MAX_CHAIN_LENGTH: usize = 16;
+
+/// Settings for the ray marched Mandelbulb. The power is also used by the bulb steps of box
+/// fold chains.
+#[derive(Debug)]
+pub struct Mandelbulb {
+    pub power: f32,
+}
+
+impl Mandelbulb {
+    pub fn new() -> Self {
+        Self { power: 8.0 }
+    }
+}
+
+/// One step of a box fold formula, matching the cases in `mandelbox_distance`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FoldStep {
+    /// Reflects each coordinate beyond the fold limit back inside it.
+    Box,
+    /// Inverts points inside the fixed radius, with a linear scale inside the minimum radius.
+    Sphere,
+    /// z ← scale·z + c
+    Scale,
+    /// z ← z^power + c, as in the Mandelbulb.
+    Bulb,
+}
+
+impl FoldStep {
+    fn parse(name: &str) -> Result<Self> {
+        match name {
+            "box" => Ok(FoldStep::Box),
+            "sphere" => Ok(FoldStep::Sphere),
+            "scale" => Ok(FoldStep::Scale),
+            "bulb" => Ok(FoldStep::Bulb),
+            _ => Err(anyhow!(
+                "unknown fold step {:?}, expected box, sphere, scale or bulb",
+                name
+            )),
+        }
+    }
+}
+
+/// Settings for the Mandelbox and other formulas built from a chain of folds, which is run
+/// once per iteration. The default chain is the Mandelbox itself, which gives the Amazing Box
+/// with a scale of -1.5.
+#[derive(Debug)]
+pub struct Mandelbox {
+    pub scale: f32,
+    pub fold_limit: f32,
+    pub min_radius: f32,
+    pub fixed_radius: f32,
+    chain: Vec<FoldStep>,
+}
+
+impl Mandelbox {
+    pub fn new() -> Self {
+        Self {
+            scale: 2.0,
+            fold_limit: 1.0,
+            min_radius: 0.5,
+            fixed_radius: 1.0,
+            chain: vec![FoldStep::Box, FoldStep::Sphere, FoldStep::Scale],
+        }
+    }
+
+    /// Sets the chain from step names, or one of the presets `mandelbox` and `hybrid`, which
+    /// follows each Mandelbox iteration with a bulb step.
+    pub fn set_chain(&mut self, arguments: &str) -> Result<()> {
+        let chain = match arguments.trim() {
+            "mandelbox" => "box sphere scale",
+            "hybrid" => "box sphere scale bulb",
+            chain => chain,
+        };
+        let chain = chain
+            .split_whitespace()
+            .map(FoldStep::parse)
+            .collect::<Result<Vec<_>>>()?;
+        if chain.is_empty() || chain.len() > MAX_CHAIN_LENGTH {
+            return Err(anyhow!(
+                "expected a chain of 1 to {} steps",
+                MAX_CHAIN_LENGTH
+            ));
+        }
+        self.chain = chain;
+        Ok(())
+    }
+
+    /// Sets the minimum and fixed sphere fold radii, written as `"<min> <max>"`.
+    pub fn set_radii(&mut self, arguments: &str) -> Result<()> {
+        let radii = arguments
+            .split_whitespace()
+            .map(str::parse)
+            .collect::<Result<Vec<f32>, _>>()
+            .map_err(|_| anyhow!("invalid radii {:?}", arguments))?;
+        match radii[..] {
+            [min, max] if 0.0 < min && min <= max => {
+                self.min_radius = min;
+                self.fixed_radius = max;
+                Ok(())
+            }
+            _ => Err(anyhow!(
+                "expected a minimum and maximum radius with 0 < min <= max"
+            )),
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct RaymarchUniform {
+    pub bulb_power: f32,
+    pub box_scale: f32,
+    pub fold_limit: f32,
+    pub min_radius: f32,
+    pub fixed_radius: f32,
+    /// Step i of the chain is stored in bits 2i and 2i + 1.
+    pub chain: u32,
+    pub chain_length: u32,
+    pub _padding: u32,
+}
+
+impl RaymarchUniform {
+    pub fn new() -> Self {
+        Self {
+            bulb_power: 8.0,
+            box_scale: 2.0,
+            fold_limit: 1.0,
+            min_radius: 0.5,
+            fixed_radius: 1.0,
+            chain: 0,
+            chain_length: 0,
+            _padding: 0,
+        }
+    }
+
+    pub fn update(&mut self, mandelbulb: &Mandelbulb, mandelbox: &Mandelbox) {
+        self.bulb_power = mandelbulb.power;
+        self.box_scale = mandelbox.scale;
+        self.fold_limit = mandelbox.fold_limit;
+        self.min_radius = mandelbox.min_radius;
+        self.fixed_radius = mandelbox.fixed_radius;
+        self.chain = mandelbox
+            .chain
+            .iter()
+            .enumerate()
+            .fold(0, |bits, (i, &step)| bits | (step as u32) << (2 * i));
+        self.chain_length = mandelbox.chain.len() as u32;
+    }
+}
@@ -1,11 +1,29 @@
 use std::sync::Arc;
 
-use crate::camera::{Camera, CameraController, CameraUniform};
-use anyhow::Result;
-use wgpu::{util::DeviceExt, TextureFormat};
+use crate::big;
+use crate::buddhabrot::{self, Buddhabrot, BuddhabrotPass};
+use crate::camera::{Camera, Camera3d, Camera3dUniform, CameraController, CameraUniform};
+use crate::complex::Complex;
+use crate::console::Console;
+use crate::external_ray::{ExternalAngle, ExternalRay, RayOverlay};
+use crate::flame::{self, Flame, FlamePass};
+use crate::fractal::{Colouring, Formula, Fractal, FractalUniform, Interior, Mode, Norm};
+use crate::iterations::{IterationCounter, MAX_ITERATIONS, MIN_ITERATIONS};
+use crate::lyapunov::{Lyapunov, LyapunovUniform};
+use crate::newton::{Newton, NewtonUniform, Polynomial};
+use crate::nucleus;
+use crate::palette::{self, Gradient, Interpolation, Palette, PaletteTexture, PaletteUniform};
+use crate::perturbation::{ReferenceOrbit, MAX_REFERENCE_LENGTH, PERTURBATION_ZOOM};
+use crate::precision::Precision;
+use crate::raymarch::{Mandelbox, Mandelbulb, RaymarchUniform};
+use crate::trap::{OrbitTrap, TrapImage, TrapShape, TrapUniform};
+use crate::user_formula::UserFormula;
+use anyhow::{anyhow, Context, Result};
+use wgpu::util::DeviceExt;
 use winit::{
+    dpi::PhysicalPosition,
     event::{ElementState, KeyEvent, WindowEvent},
-    keyboard::PhysicalKey,
+    keyboard::{KeyCode, ModifiersState, PhysicalKey},
     window::Window,
 };
 
@@ -16,6 +34,136 @@ const FULLSCREEN_VERTICES: &[[f32; 3]] = &[
     [1.0, -1.0, 0.0],
 ];
 const FULLSCREEN_INDICES: &[u16] = &[0, 2, 1, 1, 2, 3];
+/// The reference orbit, the iteration counts and a histogram.
+const FRAGMENT_STORAGE_BUFFERS: u32 = 3;
+
+fn fragment_buffer_entry(binding: u32, ty: wgpu::BufferBindingType) -> wgpu::BindGroupLayoutEntry {
+    wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility: wgpu::ShaderStages::FRAGMENT,
+        ty: wgpu::BindingType::Buffer {
+            ty,
+            has_dynamic_offset: false,
+            min_binding_size: None,
+        },
+        count: None,
+    }
+}
+
+/// Creates a pipeline that draws the full-screen quad with the given shader's `vs_main` and
+/// `fs_main`.
+fn create_fullscreen_pipeline(
+    device: &wgpu::Device,
+    label: &str,
+    layout: &wgpu::PipelineLayout,
+    shader: &wgpu::ShaderModule,
+    format: wgpu::TextureFormat,
+) -> wgpu::RenderPipeline {
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some(label),
+        layout: Some(layout),
+        vertex: wgpu::VertexState {
+            module: shader,
+            entry_point: Some("vs_main"),
+            buffers: &[wgpu::VertexBufferLayout {
+                array_stride: (std::mem::size_of::<f32>() * 3) as wgpu::BufferAddress,
+                step_mode: wgpu::VertexStepMode::Vertex,
+                attributes: &[wgpu::VertexAttribute {
+                    offset: 0,
+                    shader_location: 0,
+                    format: wgpu::VertexFormat::Float32x3,
+                }],
+            }],
+            compilation_options: Default::default(),
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: shader,
+            entry_point: Some("fs_main"),
+            targets: &[Some(wgpu::ColorTargetState {
+                // Final view
+                format,
+                blend: Some(wgpu::BlendState::REPLACE),
+                write_mask: wgpu::ColorWrites::ALL,
+            })],
+            compilation_options: Default::default(),
+        }),
+        primitive: wgpu::PrimitiveState {
+            topology: wgpu::PrimitiveTopology::TriangleList,
+            strip_index_format: None,
+            front_face: wgpu::FrontFace::Ccw,
+            cull_mode: None,
+            polygon_mode: wgpu::PolygonMode::Fill,
+            unclipped_depth: false,
+            conservative: false,
+        },
+        depth_stencil: None,
+        multisample: wgpu::MultisampleState {
+            count: 1,
+            mask: !0,
+            alpha_to_coverage_enabled: false,
+        },
+        multiview: None,
+        cache: None,
+    })
+}
+
+/// Creates the fullscreen pipeline at every precision the device supports.
+fn create_fullscreen_pipelines(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    formula: &UserFormula,
+    format: wgpu::TextureFormat,
+    fragment_storage: bool,
+) -> Vec<(Precision, wgpu::RenderPipeline)> {
+    let storage = if fragment_storage {
+        include_str!("fragment_storage.wgsl")
+    } else {
+        include_str!("fragment_storage_none.wgsl")
+    };
+    Precision::ALL
+        .into_iter()
+        .filter(|precision| precision.is_supported(device.features()))
+        .map(|precision| {
+            let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+                label: Some("fullscreen_shader"),
+                source: wgpu::ShaderSource::Wgsl(
+                    palette::output_shader_source(
+                        &format!("{}\n{}", formula.shader_source(precision), storage),
+                        format,
+                    )
+                    .into(),
+                ),
+            });
+            let pipeline = create_fullscreen_pipeline(
+                device,
+                "Fullscreen Render Pipeline",
+                layout,
+                &shader,
+                format,
+            );
+            (precision, pipeline)
+        })
+        .collect()
+}
+
+fn parse_number(arguments: &str, name: &str) -> Result<f32> {
+    arguments
+        .trim()
+        .parse()
+        .map_err(|_| anyhow!("invalid {} {:?}", name, arguments))
+}
+
+fn parse_switch(arguments: &str, name: &str) -> Result<bool> {
+    match arguments.trim() {
+        "on" => Ok(true),
+        "off" => Ok(false),
+        _ => Err(anyhow!("expected {} on or off", name)),
+    }
+}
+
+fn parse_complex_list(arguments: &str) -> Result<Vec<Complex>> {
+    arguments.split_whitespace().map(str::parse).collect()
+}
 
 pub struct State {
     surface: wgpu::Surface<'static>,
@@ -23,24 +171,84 @@ pub struct State {
     pub device: wgpu::Device,
     queue: wgpu::Queue,
     pub config: wgpu::SurfaceConfiguration,
+    /// The format the window is drawn through, which is sRGB unless neither the surface nor
+    /// a view of it can be.
+    view_format: wgpu::TextureFormat,
     pub size: winit::dpi::PhysicalSize<u32>,
     camera: Camera,
     camera_uniform: CameraUniform,
     camera_buffer: wgpu::Buffer,
+    camera_3d: Camera3d,
+    camera_3d_uniform: Camera3dUniform,
+    camera_3d_buffer: wgpu::Buffer,
     camera_bind_group: wgpu::BindGroup,
     camera_controller: CameraController,
     mouse_pressed: bool,
-    fullscreen_pipeline: wgpu::RenderPipeline,
+    cursor_position: PhysicalPosition<f64>,
+    modifiers: ModifiersState,
+    fractal: Fractal,
+    fractal_uniform: FractalUniform,
+    fractal_buffer: wgpu::Buffer,
+    iteration_counter: IterationCounter,
+    newton: Newton,
+    newton_uniform: NewtonUniform,
+    newton_buffer: wgpu::Buffer,
+    lyapunov: Lyapunov,
+    lyapunov_uniform: LyapunovUniform,
+    lyapunov_buffer: wgpu::Buffer,
+    mandelbulb: Mandelbulb,
+    mandelbox: Mandelbox,
+    raymarch_uniform: RaymarchUniform,
+    raymarch_buffer: wgpu::Buffer,
+    trap: OrbitTrap,
+    trap_uniform: TrapUniform,
+    trap_buffer: wgpu::Buffer,
+    trap_image: TrapImage,
+    palette: Palette,
+    palette_uniform: PaletteUniform,
+    palette_buffer: wgpu::Buffer,
+    palette_texture: PaletteTexture,
+    buddhabrot: Buddhabrot,
+    /// The histogram passes are missing without fragment storage.
+    buddhabrot_pass: Option<BuddhabrotPass>,
+    flame: Flame,
+    flame_pass: Option<FlamePass>,
+    flame_pipeline: Option<wgpu::RenderPipeline>,
+    /// The ray typed into the console, drawn over the Mandelbrot set.
+    external_ray: Option<ExternalRay>,
+    ray_overlay: RayOverlay,
+    /// The minibrot being looked for after a right click or the `nucleus` command.
+    nucleus_search: Option<nucleus::Search>,
+    console: Console,
+    /// Feedback from the last hotkey or command, shown after the status in the title.
+    message: Option<String>,
+    title: String,
+    reference_orbit: ReferenceOrbit,
+    reference_orbit_buffer: wgpu::Buffer,
+    fullscreen_pipeline_layout: wgpu::PipelineLayout,
+    /// A variant for each precision the device supports, so the precision can follow the zoom.
+    fullscreen_pipelines: Vec<(Precision, wgpu::RenderPipeline)>,
+    user_formula: UserFormula,
+    /// The precision being rendered with, shown in the title.
+    precision: Precision,
+    /// Set to stop the precision from being picked automatically.
+    precision_override: Option<Precision>,
     fullscreen_bind_group: wgpu::BindGroup,
     fullscreen_vertex_buffer: wgpu::Buffer,
     fullscreen_index_buffer: wgpu::Buffer,
     frame_count: f32,
     frame_count_buffer: wgpu::Buffer,
     utils_bind_group: wgpu::BindGroup,
+    /// Whether fragment shaders can bind storage buffers, which perturbation, adaptive
+    /// iteration limits and drawing histograms need.
+    fragment_storage: bool,
+    /// Whether the adapter runs compute shaders, which the Buddhabrot and flame modes need
+    /// along with fragment storage.
+    compute_shaders: bool,
 }
 
 impl State {
-    pub async fn new(window: Window) -> Self {
+    pub async fn new(window: Window) -> Result<Self> {
         let window = Arc::new(window);
         let size = window.inner_size();
 
@@ -50,7 +258,7 @@ impl State {
         });
         let surface = instance
             .create_surface(window.clone())
-            .expect("failed to create surface");
+            .context("creating the window's surface")?;
         let adapter = instance
             .request_adapter(&wgpu::RequestAdapterOptions {
                 power_preference: wgpu::PowerPreference::HighPerformance,
@@ -58,29 +266,61 @@ impl State {
                 force_fallback_adapter: false,
             })
             .await
-            .unwrap();
+            .ok_or_else(|| anyhow!("no graphics adapter can draw to the window"))?;
+
+        // The fragment shaders read the reference orbit and histograms from storage buffers.
+        // Adapters without them, such as WebGL2-class ones, lose perturbation, adaptive
+        // iteration limits and the Buddhabrot and flame modes, which also need compute shaders.
+        let downlevel = adapter.get_downlevel_capabilities();
+        let fragment_storage = downlevel
+            .flags
+            .contains(wgpu::DownlevelFlags::FRAGMENT_WRITABLE_STORAGE)
+            && adapter.limits().max_storage_buffers_per_shader_stage >= FRAGMENT_STORAGE_BUFFERS;
+        let compute_shaders = fragment_storage
+            && downlevel
+                .flags
+                .contains(wgpu::DownlevelFlags::COMPUTE_SHADERS);
 
+        // f64 is only requested when the adapter has it, otherwise the shaders fall back to
+        // double-single arithmetic
+        let features = adapter.features() & wgpu::Features::SHADER_F64;
+        let precision = Precision::best(features);
         let (device, queue) = adapter
             .request_device(
                 &wgpu::DeviceDescriptor {
-                    required_features: wgpu::Features::SHADER_F64,
-                    required_limits: wgpu::Limits::default(),
+                    required_features: features,
+                    required_limits: adapter.limits(),
                     memory_hints: wgpu::MemoryHints::Performance,
                     label: None,
                 },
                 None,
             )
             .await
-            .unwrap();
+            .context("requesting a device")?;
 
         let surface_caps = surface.get_capabilities(&adapter);
-        let surface_format = surface_caps
-            .formats
-            .iter()
-            .copied()
-            .filter(TextureFormat::is_srgb)
-            .next()
+        // Colours are worked out in linear light and encoded to sRGB on the way out, by the
+        // surface's view if it can be sRGB and by the shaders otherwise. An 8-bit format is
+        // picked where there is one, since float formats may not be encoded at all.
+        let eight_bit = |format: &wgpu::TextureFormat| {
+            matches!(
+                format.remove_srgb_suffix(),
+                wgpu::TextureFormat::Rgba8Unorm | wgpu::TextureFormat::Bgra8Unorm
+            )
+        };
+        let formats = || surface_caps.formats.iter().copied().filter(eight_bit);
+        let surface_format = formats()
+            .find(wgpu::TextureFormat::is_srgb)
+            .or_else(|| formats().next())
             .unwrap_or(surface_caps.formats[0]);
+        let view_format = if downlevel
+            .flags
+            .contains(wgpu::DownlevelFlags::SURFACE_VIEW_FORMATS)
+        {
+            surface_format.add_srgb_suffix()
+        } else {
+            surface_format
+        };
         let config = wgpu::SurfaceConfiguration {
             usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
             format: surface_format,
@@ -88,28 +328,195 @@ impl State {
             height: size.height,
             present_mode: wgpu::PresentMode::Fifo,
             alpha_mode: surface_caps.alpha_modes[0],
-            view_formats: vec![],
+            view_formats: if view_format == surface_format {
+                vec![]
+            } else {
+                vec![view_format]
+            },
             desired_maximum_frame_latency: 2,
         };
         surface.configure(&device, &config);
 
         println!("Output config: {:#?}", config);
 
+        let fractal = Fractal::new();
+        let mut fractal_uniform = FractalUniform::new();
+        fractal_uniform.update(&fractal, false, None);
+
+        let fractal_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("fractal_buffer"),
+            contents: bytemuck::cast_slice(&[fractal_uniform]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let iteration_counter = IterationCounter::new(&device);
+
+        let reference_orbit = ReferenceOrbit::new();
+        let reference_orbit_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("reference_orbit_buffer"),
+            size: (MAX_REFERENCE_LENGTH * std::mem::size_of::<[f64; 2]>()) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let newton = Newton::new();
+        let mut newton_uniform = NewtonUniform::new();
+        newton_uniform.update(&newton);
+
+        let newton_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("newton_buffer"),
+            contents: bytemuck::cast_slice(&[newton_uniform]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let lyapunov = Lyapunov::new();
+        let mut lyapunov_uniform = LyapunovUniform::new();
+        lyapunov_uniform.update(&lyapunov);
+
+        let lyapunov_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("lyapunov_buffer"),
+            contents: bytemuck::cast_slice(&[lyapunov_uniform]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let mandelbulb = Mandelbulb::new();
+        let mandelbox = Mandelbox::new();
+        let mut raymarch_uniform = RaymarchUniform::new();
+        raymarch_uniform.update(&mandelbulb, &mandelbox);
+
+        let raymarch_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("raymarch_buffer"),
+            contents: bytemuck::cast_slice(&[raymarch_uniform]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let trap = OrbitTrap::new();
+        let mut trap_uniform = TrapUniform::new();
+        trap_uniform.update(&trap);
+
+        let trap_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("trap_buffer"),
+            contents: bytemuck::cast_slice(&[trap_uniform]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let trap_image = TrapImage::new(&device, &queue);
+
+        let palette = Palette::new();
+        let mut palette_uniform = PaletteUniform::new();
+        palette_uniform.update(&palette);
+
+        let palette_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("palette_buffer"),
+            contents: bytemuck::cast_slice(&[palette_uniform]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let palette_texture = PaletteTexture::new(&device, &queue, &palette);
+
+        // The storage buffers are left out where fragment shaders can't bind them
+        let fragment_binding = |binding: u32| fragment_storage || !matches!(binding, 1 | 5);
+        let fullscreen_bind_group_layout_entries = [
+            fragment_buffer_entry(0, wgpu::BufferBindingType::Uniform),
+            fragment_buffer_entry(1, wgpu::BufferBindingType::Storage { read_only: true }),
+            fragment_buffer_entry(2, wgpu::BufferBindingType::Uniform),
+            fragment_buffer_entry(3, wgpu::BufferBindingType::Uniform),
+            fragment_buffer_entry(4, wgpu::BufferBindingType::Uniform),
+            fragment_buffer_entry(5, wgpu::BufferBindingType::Storage { read_only: false }),
+            fragment_buffer_entry(6, wgpu::BufferBindingType::Uniform),
+            wgpu::BindGroupLayoutEntry {
+                binding: 7,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Texture {
+                    sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    multisampled: false,
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 8,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                count: None,
+            },
+            fragment_buffer_entry(9, wgpu::BufferBindingType::Uniform),
+            wgpu::BindGroupLayoutEntry {
+                binding: 10,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Texture {
+                    sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    view_dimension: wgpu::TextureViewDimension::D1,
+                    multisampled: false,
+                },
+                count: None,
+            },
+        ]
+        .into_iter()
+        .filter(|entry| fragment_binding(entry.binding))
+        .collect::<Vec<_>>();
         let fullscreen_bind_group_layout =
             device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-                entries: &[],
+                entries: &fullscreen_bind_group_layout_entries,
                 label: Some("fullscreen_bind_group_layout"),
             });
 
+        let fullscreen_bind_group_entries = [
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: fractal_buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: reference_orbit_buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: newton_buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 3,
+                resource: lyapunov_buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 4,
+                resource: raymarch_buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 5,
+                resource: iteration_counter.buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 6,
+                resource: trap_buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 7,
+                resource: wgpu::BindingResource::TextureView(&trap_image.view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 8,
+                resource: wgpu::BindingResource::Sampler(&trap_image.sampler),
+            },
+            wgpu::BindGroupEntry {
+                binding: 9,
+                resource: palette_buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 10,
+                resource: wgpu::BindingResource::TextureView(&palette_texture.view),
+            },
+        ]
+        .into_iter()
+        .filter(|entry| fragment_binding(entry.binding))
+        .collect::<Vec<_>>();
         let fullscreen_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
             layout: &fullscreen_bind_group_layout,
-            entries: &[],
+            entries: &fullscreen_bind_group_entries,
             label: Some("fullscreen_bind_group"),
         });
 
         let camera = Camera::new((0.0, 0.0), 0.0, 1.0);
         let mut camera_uniform = CameraUniform::new();
-        camera_uniform.update(&camera);
+        camera_uniform.update(&camera, None);
 
         let camera_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             label: Some("camera_buffer"),
@@ -117,27 +524,56 @@ impl State {
             usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
         });
 
+        let camera_3d = Camera3d::new(Mode::Mandelbulb.home_3d(), 0.0, 0.0);
+        let mut camera_3d_uniform = Camera3dUniform::new();
+        camera_3d_uniform.update(&camera_3d);
+
+        let camera_3d_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("camera_3d_buffer"),
+            contents: bytemuck::cast_slice(&[camera_3d_uniform]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
         let camera_bind_group_layout =
             device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-                entries: &[wgpu::BindGroupLayoutEntry {
-                    binding: 0,
-                    visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
-                    ty: wgpu::BindingType::Buffer {
-                        ty: wgpu::BufferBindingType::Uniform,
-                        has_dynamic_offset: false,
-                        min_binding_size: None,
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::VERTEX_FRAGMENT
+                            | wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
                     },
-                    count: None,
-                }],
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                ],
                 label: Some("camera_bind_group_layout"),
             });
 
         let camera_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
             layout: &camera_bind_group_layout,
-            entries: &[wgpu::BindGroupEntry {
-                binding: 0,
-                resource: camera_buffer.as_entire_binding(),
-            }],
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: camera_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: camera_3d_buffer.as_entire_binding(),
+                },
+            ],
             label: Some("camera_bind_group"),
         });
 
@@ -153,7 +589,7 @@ impl State {
             device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
                 entries: &[wgpu::BindGroupLayoutEntry {
                     binding: 0,
-                    visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+                    visibility: wgpu::ShaderStages::VERTEX_FRAGMENT | wgpu::ShaderStages::COMPUTE,
                     ty: wgpu::BindingType::Buffer {
                         ty: wgpu::BufferBindingType::Uniform,
                         has_dynamic_offset: false,
@@ -173,68 +609,76 @@ impl State {
             label: Some("utils_bind_group"),
         });
 
-        let fullscreen_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
-            label: Some("fullscreen_shader"),
-            source: wgpu::ShaderSource::Wgsl(include_str!("fullscreen.wgsl").into()),
+        let buddhabrot_pass = fragment_storage.then(|| {
+            buddhabrot::create_pass(
+                &device,
+                &camera_bind_group_layout,
+                size,
+                precision,
+                compute_shaders,
+            )
+        });
+
+        let flame_pass = fragment_storage.then(|| {
+            flame::create_pass(
+                &device,
+                &camera_bind_group_layout,
+                size,
+                precision,
+                compute_shaders,
+            )
         });
 
+        let user_formula = UserFormula::new();
+
         let fullscreen_pipeline_layout =
             device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
                 label: Some("Fullscreen Pipeline Layout"),
                 bind_group_layouts: &[
-                    &fullscreen_bind_group_layout,
-                    &camera_bind_group_layout,
-                    &utils_bind_group_layout,
-                ],
+                    Some(&fullscreen_bind_group_layout),
+                    Some(&camera_bind_group_layout),
+                    Some(&utils_bind_group_layout),
+                    buddhabrot_pass
+                        .as_ref()
+                        .map(|pass| &pass.render_bind_group_layout),
+                ]
+                .into_iter()
+                .flatten()
+                .collect::<Vec<_>>(),
                 push_constant_ranges: &[],
             });
 
-        let fullscreen_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-            label: Some("Fullscreen Render Pipeline"),
-            layout: Some(&fullscreen_pipeline_layout),
-            vertex: wgpu::VertexState {
-                module: &fullscreen_shader,
-                entry_point: Some("vs_main"),
-                buffers: &[wgpu::VertexBufferLayout {
-                    array_stride: (std::mem::size_of::<f32>() * 3) as wgpu::BufferAddress,
-                    step_mode: wgpu::VertexStepMode::Vertex,
-                    attributes: &[wgpu::VertexAttribute {
-                        offset: 0,
-                        shader_location: 0,
-                        format: wgpu::VertexFormat::Float32x3,
-                    }],
-                }],
-                compilation_options: Default::default(),
-            },
-            fragment: Some(wgpu::FragmentState {
-                module: &fullscreen_shader,
-                entry_point: Some("fs_main"),
-                targets: &[Some(wgpu::ColorTargetState {
-                    // Final view
-                    format: config.format,
-                    blend: Some(wgpu::BlendState::REPLACE),
-                    write_mask: wgpu::ColorWrites::ALL,
-                })],
-                compilation_options: Default::default(),
-            }),
-            primitive: wgpu::PrimitiveState {
-                topology: wgpu::PrimitiveTopology::TriangleList,
-                strip_index_format: None,
-                front_face: wgpu::FrontFace::Ccw,
-                cull_mode: None,
-                polygon_mode: wgpu::PolygonMode::Fill,
-                unclipped_depth: false,
-                conservative: false,
-            },
-            depth_stencil: None,
-            multisample: wgpu::MultisampleState {
-                count: 1,
-                mask: !0,
-                alpha_to_coverage_enabled: false,
-            },
-            multiview: None,
-            cache: None,
+        let fullscreen_pipelines = create_fullscreen_pipelines(
+            &device,
+            &fullscreen_pipeline_layout,
+            &user_formula,
+            view_format,
+            fragment_storage,
+        );
+
+        let flame_pipeline = flame_pass.as_ref().map(|flame_pass| {
+            let flame_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+                label: Some("flame_render_shader"),
+                source: wgpu::ShaderSource::Wgsl(
+                    palette::output_shader_source(include_str!("flame_render.wgsl"), view_format)
+                        .into(),
+                ),
+            });
+            let flame_pipeline_layout =
+                device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                    label: Some("Flame Render Pipeline Layout"),
+                    bind_group_layouts: &[&flame_pass.render_bind_group_layout],
+                    push_constant_ranges: &[],
+                });
+            create_fullscreen_pipeline(
+                &device,
+                "Flame Render Pipeline",
+                &flame_pipeline_layout,
+                &flame_shader,
+                view_format,
+            )
         });
+        let ray_overlay = RayOverlay::new(&device, view_format);
 
         let fullscreen_vertex_buffer =
             device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
@@ -250,27 +694,74 @@ impl State {
                 usage: wgpu::BufferUsages::INDEX,
             });
 
-        return Self {
+        Ok(Self {
             surface,
             device,
             window,
             queue,
             config,
+            view_format,
             size,
             camera,
             camera_uniform,
             camera_buffer,
+            camera_3d,
+            camera_3d_uniform,
+            camera_3d_buffer,
             camera_bind_group,
             camera_controller,
             mouse_pressed: false,
-            fullscreen_pipeline,
+            cursor_position: PhysicalPosition::new(0.0, 0.0),
+            modifiers: ModifiersState::empty(),
+            fractal,
+            fractal_uniform,
+            fractal_buffer,
+            iteration_counter,
+            newton,
+            newton_uniform,
+            newton_buffer,
+            lyapunov,
+            lyapunov_uniform,
+            lyapunov_buffer,
+            mandelbulb,
+            mandelbox,
+            raymarch_uniform,
+            raymarch_buffer,
+            trap,
+            trap_uniform,
+            trap_buffer,
+            trap_image,
+            palette,
+            palette_uniform,
+            palette_buffer,
+            palette_texture,
+            buddhabrot: Buddhabrot::new(),
+            buddhabrot_pass,
+            flame: Flame::new(),
+            flame_pass,
+            flame_pipeline,
+            external_ray: None,
+            ray_overlay,
+            nucleus_search: None,
+            console: Console::new(),
+            message: None,
+            title: String::new(),
+            reference_orbit,
+            reference_orbit_buffer,
+            fullscreen_pipeline_layout,
+            fullscreen_pipelines,
+            user_formula,
+            precision,
+            precision_override: None,
             fullscreen_bind_group,
             fullscreen_vertex_buffer,
             fullscreen_index_buffer,
             frame_count: 0.0,
             frame_count_buffer,
             utils_bind_group,
-        };
+            fragment_storage,
+            compute_shaders,
+        })
     }
 
     pub fn resize(&mut self, new_size: winit::dpi::PhysicalSize<u32>) {
@@ -280,10 +771,30 @@ impl State {
             self.config.height = new_size.height;
             self.camera.aspect = new_size.width as f32 / new_size.height as f32;
             self.surface.configure(&self.device, &self.config);
+            if let Some(pass) = &mut self.buddhabrot_pass {
+                pass.resize(&self.device, new_size);
+            }
+            if let Some(pass) = &mut self.flame_pass {
+                pass.resize(&self.device, new_size);
+            }
         }
     }
 
     pub fn input(&mut self, event: &WindowEvent) -> bool {
+        if let WindowEvent::KeyboardInput { event, .. } = event {
+            if self.console.is_open() {
+                if let Some(command) = self.console.process_key(event) {
+                    // Commands may leave a message of their own
+                    self.message = None;
+                    if let Err(e) = self.run_command(&command) {
+                        eprintln!("{:?}", e);
+                        self.message = Some(e.to_string());
+                    }
+                }
+                return true;
+            }
+        }
+
         match event {
             WindowEvent::MouseInput {
                 button: winit::event::MouseButton::Left,
@@ -294,6 +805,36 @@ impl State {
                 true
             }
 
+            WindowEvent::MouseInput {
+                button: winit::event::MouseButton::Right,
+                state: ElementState::Pressed,
+                ..
+            } => {
+                self.message = self
+                    .fly_to_nucleus(self.cursor_position)
+                    .err()
+                    .map(|e| e.to_string());
+                true
+            }
+
+            WindowEvent::CursorMoved { position, .. } => {
+                self.cursor_position = *position;
+                if self.picking_julia_constant() {
+                    self.fractal.julia_constant =
+                        self.camera.screen_to_world(self.cursor_position, self.size);
+                }
+                false
+            }
+
+            WindowEvent::ModifiersChanged(modifiers) => {
+                self.modifiers = modifiers.state();
+                if self.picking_julia_constant() {
+                    self.fractal.julia_constant =
+                        self.camera.screen_to_world(self.cursor_position, self.size);
+                }
+                false
+            }
+
             WindowEvent::MouseWheel { delta, .. } => {
                 self.camera_controller.process_mouse(*delta);
                 true
@@ -307,15 +848,636 @@ impl State {
                         ..
                     },
                 ..
-            } => self.camera_controller.process_keyboard(*key, *state),
+            } => self.process_keyboard(*key, *state),
 
             _ => false,
         }
     }
 
+    fn process_keyboard(&mut self, key: KeyCode, state: ElementState) -> bool {
+        match key {
+            KeyCode::Enter => {
+                if state == ElementState::Pressed {
+                    self.console.open();
+                }
+                true
+            }
+            KeyCode::KeyM => {
+                if state == ElementState::Pressed {
+                    let previous = self.fractal.mode;
+                    self.fractal.mode = previous.next();
+                    while self.fractal.mode.uses_compute() && !self.compute_shaders {
+                        self.fractal.mode = self.fractal.mode.next();
+                    }
+                    if self.fractal.mode.home_2d() != previous.home_2d() {
+                        self.camera.jump(self.fractal.mode.home_2d(), 0.0);
+                    }
+                    if self.fractal.mode.is_3d() {
+                        self.camera_3d = Camera3d::new(self.fractal.mode.home_3d(), 0.0, 0.0);
+                    }
+                    self.message = Some(format!("mode: {:?}", self.fractal.mode));
+                }
+                true
+            }
+            KeyCode::KeyB => {
+                if state == ElementState::Pressed {
+                    self.buddhabrot.anti = !self.buddhabrot.anti;
+                }
+                true
+            }
+            KeyCode::KeyN => {
+                if state == ElementState::Pressed {
+                    self.newton.nova = !self.newton.nova;
+                }
+                true
+            }
+            KeyCode::KeyJ => {
+                if state == ElementState::Pressed {
+                    self.fractal.julia = !self.fractal.julia;
+                }
+                true
+            }
+            KeyCode::KeyF => {
+                if state == ElementState::Pressed {
+                    self.fractal.formula = self.fractal.formula.next();
+                    self.message = Some(format!("formula: {:?}", self.fractal.formula));
+                }
+                true
+            }
+            KeyCode::KeyE => {
+                if state == ElementState::Pressed {
+                    self.fractal.norm = self.fractal.norm.next();
+                    self.message = Some(format!("norm: {:?}", self.fractal.norm));
+                }
+                true
+            }
+            KeyCode::KeyC => {
+                if state == ElementState::Pressed {
+                    self.fractal.cardioid_test = !self.fractal.cardioid_test;
+                    self.message = Some(format!("cardioid test: {}", self.fractal.cardioid_test));
+                }
+                true
+            }
+            KeyCode::KeyV => {
+                if state == ElementState::Pressed {
+                    self.fractal.periodicity = !self.fractal.periodicity;
+                    self.message = Some(format!(
+                        "periodicity checking: {}",
+                        self.fractal.periodicity
+                    ));
+                }
+                true
+            }
+            KeyCode::KeyD => {
+                if state == ElementState::Pressed {
+                    self.fractal.colouring = match self.fractal.colouring {
+                        Colouring::Distance => Colouring::Iterations,
+                        _ => Colouring::Distance,
+                    };
+                    self.message = Some(format!("colouring: {:?}", self.fractal.colouring));
+                }
+                true
+            }
+            KeyCode::KeyS => {
+                if state == ElementState::Pressed {
+                    self.fractal.colouring = self.fractal.colouring.next_statistic();
+                    self.message = Some(format!("colouring: {:?}", self.fractal.colouring));
+                }
+                true
+            }
+            KeyCode::KeyG => {
+                if state == ElementState::Pressed {
+                    self.palette.next();
+                    self.palette_texture.write(&self.queue, &self.palette);
+                    self.message = Some(format!("palette: {}", self.palette.gradient.name));
+                }
+                true
+            }
+            KeyCode::KeyT => {
+                if state == ElementState::Pressed {
+                    if self.fractal.colouring == Colouring::Trap {
+                        self.trap.shape = self.trap.shape.next();
+                    }
+                    self.fractal.colouring = Colouring::Trap;
+                    self.message = Some(format!("trap: {:?}", self.trap.shape));
+                }
+                true
+            }
+            KeyCode::KeyH => {
+                if state == ElementState::Pressed {
+                    self.fractal.interior = self.fractal.interior.next();
+                    self.message = Some(format!("interior: {:?}", self.fractal.interior));
+                }
+                true
+            }
+            KeyCode::BracketLeft | KeyCode::BracketRight => {
+                if state == ElementState::Pressed {
+                    // Whole steps keep the exponent on the fast integer path, shift allows
+                    // fractional exponents
+                    let step = if self.modifiers.shift_key() { 0.1 } else { 1.0 };
+                    let step = if key == KeyCode::BracketLeft {
+                        -step
+                    } else {
+                        step
+                    };
+                    match self.fractal.mode {
+                        // The Mandelbox scale is tweaked in tenths, or hundredths with shift
+                        Mode::Mandelbox => {
+                            let box_scale = &mut self.mandelbox.scale;
+                            *box_scale = ((*box_scale + step * 0.1) * 100.0).round() / 100.0;
+                            self.message = Some(format!("scale: {}", box_scale));
+                        }
+                        mode => {
+                            let power = if mode == Mode::Mandelbulb {
+                                &mut self.mandelbulb.power
+                            } else {
+                                self.fractal.animate_power = false;
+                                &mut self.fractal.power
+                            };
+                            *power = (((*power + step) * 10.0).round() / 10.0).max(1.1);
+                            self.message = Some(format!("power: {}", power));
+                        }
+                    }
+                }
+                true
+            }
+            KeyCode::PageUp | KeyCode::PageDown => {
+                if state == ElementState::Pressed {
+                    let factor = if key == KeyCode::PageUp {
+                        1.5
+                    } else {
+                        1.0 / 1.5
+                    };
+                    self.fractal.iterations.scale(factor);
+                    self.message = Some(format!("iterations: {}", self.fractal.iterations.limit));
+                }
+                true
+            }
+            KeyCode::KeyA => {
+                if state == ElementState::Pressed {
+                    let iterations = &mut self.fractal.iterations;
+                    iterations.adaptive = !iterations.adaptive;
+                    iterations.override_limit = None;
+                }
+                true
+            }
+            KeyCode::KeyP => {
+                if state == ElementState::Pressed {
+                    self.fractal.animate_power = !self.fractal.animate_power;
+                }
+                true
+            }
+            KeyCode::KeyL => {
+                if state == ElementState::Pressed {
+                    self.message = Some(format!("location: {}", self.camera.location()));
+                }
+                true
+            }
+            _ => self.camera_controller.process_keyboard(key, state),
+        }
+    }
+
+    /// Runs a line typed into the console, made of a command name followed by its arguments.
+    fn run_command(&mut self, line: &str) -> Result<()> {
+        let line = line.trim();
+        let (command, arguments) = line.split_once(' ').unwrap_or((line, ""));
+        match command {
+            "" => {}
+            "goto" => self.set_location(arguments)?,
+            "nucleus" => {
+                let centre = PhysicalPosition::new(
+                    self.size.width as f64 / 2.0,
+                    self.size.height as f64 / 2.0,
+                );
+                self.fly_to_nucleus(centre)?
+            }
+            "ray" => self.trace_ray(arguments)?,
+            "roots" => {
+                self.newton
+                    .set_polynomial(Polynomial::from_roots(parse_complex_list(arguments)?)?);
+            }
+            "coefficients" => {
+                self.newton
+                    .set_polynomial(Polynomial::from_coefficients(parse_complex_list(
+                        arguments,
+                    )?)?);
+                let roots = self.newton.polynomial().roots();
+                let roots = roots.iter().map(Complex::to_string).collect::<Vec<_>>();
+                self.message = Some(format!("roots: {}", roots.join(" ")));
+            }
+            "relaxation" => self
+                .newton
+                .set_relaxation(parse_number(arguments, "relaxation")?),
+            "sequence" => self.lyapunov.set_sequence(arguments)?,
+            "lyapunov" => self.lyapunov.set_colour(arguments)?,
+            "limits" => self.buddhabrot.set_limits(arguments)?,
+            "bailout" => self.fractal.set_bailout(arguments)?,
+            "norm" => self.fractal.norm = Norm::parse(arguments)?,
+            "cardioid" => self.fractal.cardioid_test = parse_switch(arguments, "cardioid")?,
+            "periodicity" => self.fractal.periodicity = parse_switch(arguments, "periodicity")?,
+            "colouring" => self.fractal.colouring = Colouring::parse(arguments)?,
+            "interior" => self.fractal.interior = Interior::parse(arguments)?,
+            "trap" => self.set_trap(arguments)?,
+            "palette" => self.set_palette(arguments)?,
+            "density" => self.fractal.stripe_density = parse_number(arguments, "stripe density")?,
+            "width" => {
+                let width = parse_number(arguments, "line width")?;
+                if width <= 0.0 {
+                    return Err(anyhow!("expected a positive line width"));
+                }
+                self.fractal.line_width = width;
+            }
+            "iterations" => {
+                let iterations = &mut self.fractal.iterations;
+                match arguments.trim() {
+                    "auto" => {
+                        iterations.override_limit = None;
+                        iterations.adaptive = false;
+                    }
+                    "adaptive" if !self.fragment_storage => {
+                        return Err(anyhow!(
+                            "adaptive iteration limits need storage buffers in fragment shaders"
+                        ))
+                    }
+                    "adaptive" => {
+                        iterations.override_limit = None;
+                        iterations.adaptive = true;
+                    }
+                    limit => {
+                        let limit = limit
+                            .parse::<u32>()
+                            .map_err(|_| anyhow!("invalid iteration limit {:?}", limit))?;
+                        if !(MIN_ITERATIONS..=MAX_ITERATIONS).contains(&limit) {
+                            return Err(anyhow!(
+                                "expected auto, adaptive or a limit from {} to {}",
+                                MIN_ITERATIONS,
+                                MAX_ITERATIONS
+                            ));
+                        }
+                        iterations.override_limit = Some(limit);
+                    }
+                }
+            }
+            "precision" if arguments.trim() == "auto" => self.precision_override = None,
+            "precision" => {
+                let precision = Precision::parse(arguments)?;
+                if !precision.is_supported(self.device.features()) {
+                    return Err(anyhow!("this adapter doesn't support {}", precision));
+                }
+                self.precision_override = Some(precision);
+            }
+            "formula" => {
+                let formula = UserFormula::compile(arguments)?;
+                self.rebuild_fullscreen_pipelines(&formula)?;
+                self.message = Some(format!("formula: {}", formula.source));
+                self.user_formula = formula;
+                self.fractal.formula = Formula::Custom;
+            }
+            "flame" => {
+                let (flame, skipped) = Flame::load(arguments.trim())?;
+                self.flame = flame;
+                if let Some((position, zoom)) = self.flame.view {
+                    self.camera.jump(position, zoom);
+                }
+                if !skipped.is_empty() {
+                    self.message = Some(format!(
+                        "skipped unsupported attributes: {}",
+                        skipped.join(" ")
+                    ));
+                }
+            }
+            "power" => self.mandelbulb.power = parse_number(arguments, "power")?,
+            "scale" => self.mandelbox.scale = parse_number(arguments, "scale")?,
+            "fold" => self.mandelbox.fold_limit = parse_number(arguments, "fold limit")?,
+            "radii" => self.mandelbox.set_radii(arguments)?,
+            "chain" => self.mandelbox.set_chain(arguments)?,
+            _ => return Err(anyhow!("unknown command {:?}", command)),
+        }
+        Ok(())
+    }
+
+    /// Rebuilds the fullscreen pipelines with a new user formula. If the shader doesn't compile
+    /// the error is returned and the current pipelines are kept.
+    fn rebuild_fullscreen_pipelines(&mut self, formula: &UserFormula) -> Result<()> {
+        self.device.push_error_scope(wgpu::ErrorFilter::Validation);
+        let pipelines = create_fullscreen_pipelines(
+            &self.device,
+            &self.fullscreen_pipeline_layout,
+            formula,
+            self.view_format,
+            self.fragment_storage,
+        );
+        if let Some(error) = pollster::block_on(self.device.pop_error_scope()) {
+            return Err(anyhow!("the shader failed to compile: {}", error));
+        }
+        self.fullscreen_pipelines = pipelines;
+        Ok(())
+    }
+
+    /// Picks the precision for the current view, unless one has been chosen in the console.
+    /// Coordinates are relative to the reference orbit while rendering by perturbation.
+    /// Double-single is only kept where it is used, so the title shows what is drawn.
+    fn update_precision(&mut self, perturbation: bool) {
+        let uniform = &self.camera_uniform;
+        let centre = if perturbation {
+            uniform.offset
+        } else {
+            uniform.pos
+        };
+        let extent =
+            centre[0].abs().max(centre[1].abs()) + uniform.scale * uniform.aspect.max(1.0) as f64;
+        let pixel_size = 2.0 * uniform.scale / self.size.height as f64;
+        let precision = self
+            .precision_override
+            .unwrap_or_else(|| Precision::for_view(extent, pixel_size, self.device.features()));
+        // Perturbation only has plain deltas
+        let double_single = !perturbation && self.fractal.supports_double_single();
+        self.precision = if precision == Precision::DoubleSingle && !double_single {
+            Precision::F32
+        } else {
+            precision
+        };
+    }
+
+    fn update_title(&mut self) {
+        let title = match (self.console.input(), &self.message) {
+            (Some(input), _) => format!("fractalbox > {}_", input),
+            (None, Some(message)) => format!("fractalbox [{}] - {}", self.status(), message),
+            (None, None) => format!("fractalbox [{}]", self.status()),
+        };
+        if title != self.title {
+            self.window.set_title(&title);
+            self.title = title;
+        }
+    }
+
+    fn status(&self) -> String {
+        let status = format!("{}, {}", self.precision_status(), self.iteration_status());
+        if self.nucleus_search.is_some() {
+            format!("{}, looking for a nucleus", status)
+        } else {
+            status
+        }
+    }
+
+    fn iteration_status(&self) -> String {
+        let iterations = &self.fractal.iterations;
+        match (iterations.override_limit, iterations.adaptive) {
+            (Some(_), _) => format!("{} iterations", iterations.limit),
+            (None, true) => format!("{} iterations adaptive", iterations.limit),
+            (None, false) => format!("{} iterations auto", iterations.limit),
+        }
+    }
+
+    fn precision_status(&self) -> String {
+        match self.precision_override {
+            Some(precision) if precision != self.precision => {
+                format!("{} (no {} here)", self.precision, precision)
+            }
+            Some(_) => self.precision.to_string(),
+            None => format!("{} auto", self.precision),
+        }
+    }
+
+    /// Starts looking for the nucleus of the minibrot whose atom domain contains a position in
+    /// the window. The camera flies there once it is found.
+    fn fly_to_nucleus(&mut self, position: PhysicalPosition<f64>) -> Result<()> {
+        if !self.fractal.is_mandelbrot_set() {
+            return Err(anyhow!(
+                "minibrots can only be located in the Mandelbrot set"
+            ));
+        }
+        if self.nucleus_search.is_some() {
+            return Err(anyhow!("already looking for a nucleus"));
+        }
+        let c = self.camera.screen_to_world_big(position, self.size);
+        self.nucleus_search = Some(nucleus::Search::start(
+            c,
+            self.camera.zoom(),
+            self.fractal.iterations.limit,
+        ));
+        Ok(())
+    }
+
+    fn finish_nucleus_search(&mut self, result: Result<nucleus::Nucleus>) -> Result<()> {
+        let nucleus = result?;
+        self.message = Some(format!(
+            "nucleus: period {} at {} {}, size {}",
+            nucleus.period,
+            nucleus.position.x,
+            nucleus.position.y,
+            big::format_scientific(&nucleus.size)
+        ));
+        self.camera.fly_to(nucleus.position.clone(), nucleus.zoom());
+        Ok(())
+    }
+
+    /// Sets the trap's `position`, `scale` or `rotation` in degrees, or picks its shape and
+    /// colours by it. `image` takes an optional PPM or PGM file to replace the image with.
+    fn set_trap(&mut self, arguments: &str) -> Result<()> {
+        let arguments = arguments.trim();
+        let (setting, value) = arguments.split_once(' ').unwrap_or((arguments, ""));
+        match setting {
+            "position" => self.trap.set_position(value)?,
+            "scale" => {
+                let scale = parse_number(value, "trap scale")?;
+                if scale <= 0.0 {
+                    return Err(anyhow!("expected a positive trap scale"));
+                }
+                self.trap.scale = scale;
+            }
+            "rotation" => self.trap.rotation = parse_number(value, "rotation")?.to_radians(),
+            shape => {
+                let shape = TrapShape::parse(shape)?;
+                if shape == TrapShape::Image && !value.trim().is_empty() {
+                    self.trap_image.load(&self.queue, value.trim())?;
+                }
+                self.trap.shape = shape;
+                self.fractal.colouring = Colouring::Trap;
+            }
+        }
+        Ok(())
+    }
+
+    /// Sets the palette's `offset`, `scale`, `repeat` or `blend` interpolation, imports a
+    /// gradient with `load <path>`, or picks one from the library by name.
+    fn set_palette(&mut self, arguments: &str) -> Result<()> {
+        let arguments = arguments.trim();
+        let (setting, value) = arguments.split_once(' ').unwrap_or((arguments, ""));
+        match setting {
+            "offset" => self.palette.offset = parse_number(value, "palette offset")?,
+            "scale" => self.palette.scale = parse_number(value, "palette scale")?,
+            "repeat" => self.palette.repeat = parse_switch(value, "repeat")?,
+            "load" => self.palette.gradient = Gradient::load(value.trim())?,
+            "blend" => self.palette.interpolation = Interpolation::parse(value)?,
+            name => self.palette.select(name)?,
+        }
+        self.palette_texture.write(&self.queue, &self.palette);
+        Ok(())
+    }
+
+    /// Traces the external ray with an angle typed as a fraction or a binary expansion, and
+    /// flies the camera to where it lands. `off` removes the ray.
+    fn trace_ray(&mut self, arguments: &str) -> Result<()> {
+        if arguments.trim() == "off" {
+            self.external_ray = None;
+            return Ok(());
+        }
+        if !self.fractal.is_mandelbrot_set() {
+            return Err(anyhow!(
+                "external rays can only be traced in the Mandelbrot set"
+            ));
+        }
+        let ray = ExternalRay::trace(ExternalAngle::parse(arguments)?)?;
+        self.message = Some(format!(
+            "ray {} (preperiod {}, period {}) lands at {} {}",
+            ray.angle, ray.angle.preperiod, ray.angle.period, ray.landing.x, ray.landing.y
+        ));
+        self.camera.fly_to(ray.landing.clone(), ray.zoom);
+        self.external_ray = Some(ray);
+        Ok(())
+    }
+
+    /// Jumps the camera to a location written as `"<re> <im> [zoom]"`.
+    pub fn set_location(&mut self, location: &str) -> Result<()> {
+        self.camera.set_location(location)
+    }
+
+    /// Holding control over the Mandelbrot view previews the Julia set for the point under the
+    /// cursor.
+    fn picking_julia_constant(&self) -> bool {
+        self.modifiers.control_key() && !self.fractal.julia
+    }
+
+    /// Whether `fs_main` is counting the pixels the iteration limit cuts short this frame.
+    fn counting_iterations(&self) -> bool {
+        self.fractal.iterations.adaptive
+            && self.fractal.mode == Mode::EscapeTime
+            && self.fragment_storage
+    }
+
     pub fn update(&mut self, dt: std::time::Duration) {
-        self.camera_controller.update_camera(&mut self.camera, dt);
-        self.camera_uniform.update(&self.camera);
+        if let Some(result) = self.nucleus_search.as_ref().and_then(nucleus::Search::poll) {
+            self.nucleus_search = None;
+            if let Err(e) = self.finish_nucleus_search(result) {
+                self.message = Some(e.to_string());
+            }
+        }
+        self.update_title();
+        if self.fractal.mode.is_3d() {
+            self.camera_controller
+                .update_camera_3d(&mut self.camera_3d, dt);
+        } else {
+            self.camera_controller.update_camera(&mut self.camera, dt);
+        }
+        // Deeper pixels would collapse into blocks, since even the deltas from the reference
+        // orbit run out of exponent range
+        let min_pixel_size = Precision::min_pixel_size(self.device.features());
+        let max_zoom = -(min_pixel_size * self.size.height as f64 / 2.0).ln() as f32;
+        if self.camera.limit_zoom(max_zoom) {
+            self.message = Some("the precision can't zoom any deeper".to_string());
+        }
+        self.camera_3d_uniform.update(&self.camera_3d);
+        self.queue.write_buffer(
+            &self.camera_3d_buffer,
+            0,
+            bytemuck::cast_slice(&[self.camera_3d_uniform]),
+        );
+        self.camera_controller
+            .update_fractal(&mut self.fractal, &self.camera, dt);
+        self.fractal.update(dt);
+        if let Some((limit, counts)) = self.iteration_counter.poll(&self.device) {
+            if self.counting_iterations() {
+                self.fractal
+                    .iterations
+                    .adapt(limit, counts, self.size.width * self.size.height);
+            }
+        }
+        self.fractal.iterations.update(self.camera.zoom());
+        let preview = self.picking_julia_constant();
+        let reference = if self.camera.zoom() > PERTURBATION_ZOOM
+            && self.fractal.supports_perturbation()
+            && self.fragment_storage
+        {
+            let julia_constant =
+                (self.fractal.julia || preview).then_some(self.fractal.julia_constant);
+            if self.reference_orbit.update(
+                &self.camera,
+                julia_constant,
+                self.fractal.iterations.limit,
+            ) {
+                self.queue.write_buffer(
+                    &self.reference_orbit_buffer,
+                    0,
+                    bytemuck::cast_slice(&self.reference_orbit.points),
+                );
+            }
+            // Nothing can be rendered by perturbation until the first orbit arrives
+            self.reference_orbit
+                .centre()
+                .is_some()
+                .then_some(&self.reference_orbit)
+        } else {
+            None
+        };
+        self.camera_uniform
+            .update(&self.camera, reference.and_then(|orbit| orbit.centre()));
+        self.fractal_uniform
+            .update(&self.fractal, preview, reference);
+        self.update_precision(self.fractal_uniform.perturbation != 0);
+        self.queue.write_buffer(
+            &self.fractal_buffer,
+            0,
+            bytemuck::cast_slice(&[self.fractal_uniform]),
+        );
+        self.newton_uniform.update(&self.newton);
+        self.queue.write_buffer(
+            &self.newton_buffer,
+            0,
+            bytemuck::cast_slice(&[self.newton_uniform]),
+        );
+        if let Some(pass) = &mut self.buddhabrot_pass {
+            pass.update(&self.queue, |uniform| {
+                uniform.update(&self.buddhabrot, self.size)
+            });
+        }
+        if let Some(pass) = &mut self.flame_pass {
+            pass.update(&self.queue, |uniform| {
+                uniform.update(&self.flame, self.size)
+            });
+        }
+        self.ray_overlay.update(
+            &self.queue,
+            self.external_ray
+                .as_ref()
+                .filter(|_| self.fractal.is_mandelbrot_set()),
+            &self.camera,
+        );
+        self.raymarch_uniform
+            .update(&self.mandelbulb, &self.mandelbox);
+        self.queue.write_buffer(
+            &self.raymarch_buffer,
+            0,
+            bytemuck::cast_slice(&[self.raymarch_uniform]),
+        );
+        self.trap_uniform.update(&self.trap);
+        self.queue.write_buffer(
+            &self.trap_buffer,
+            0,
+            bytemuck::cast_slice(&[self.trap_uniform]),
+        );
+        self.palette_uniform.update(&self.palette);
+        self.queue.write_buffer(
+            &self.palette_buffer,
+            0,
+            bytemuck::cast_slice(&[self.palette_uniform]),
+        );
+        self.lyapunov_uniform.update(&self.lyapunov);
+        self.queue.write_buffer(
+            &self.lyapunov_buffer,
+            0,
+            bytemuck::cast_slice(&[self.lyapunov_uniform]),
+        );
         self.queue.write_buffer(
             &self.camera_buffer,
             0,
@@ -330,14 +1492,39 @@ impl State {
 
     pub fn render(&mut self) -> Result<(), wgpu::SurfaceError> {
         let output = self.surface.get_current_texture()?;
-        let view = output
-            .texture
-            .create_view(&wgpu::TextureViewDescriptor::default());
+        let view = output.texture.create_view(&wgpu::TextureViewDescriptor {
+            format: Some(self.view_format),
+            ..Default::default()
+        });
         let mut encoder = self
             .device
             .create_command_encoder(&wgpu::CommandEncoderDescriptor {
                 label: Some("Render Encoder"),
             });
+        let bind_groups = [&self.camera_bind_group];
+        match (
+            self.fractal.mode,
+            &mut self.buddhabrot_pass,
+            &mut self.flame_pass,
+        ) {
+            (Mode::Buddhabrot, Some(pass), _) => pass.compute(
+                &mut encoder,
+                &self.queue,
+                &self.camera_uniform,
+                &bind_groups,
+            ),
+            (Mode::Flame, _, Some(pass)) => pass.compute(
+                &mut encoder,
+                &self.queue,
+                &self.camera_uniform,
+                &bind_groups,
+            ),
+            _ => {}
+        }
+        let counting_iterations = self.counting_iterations();
+        if counting_iterations {
+            self.iteration_counter.clear(&mut encoder);
+        }
         {
             let mut fullscreen_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
                 label: Some("Fullscreen Render Pass"),
@@ -358,19 +1545,42 @@ impl State {
                 ..Default::default()
             });
 
-            fullscreen_pass.set_pipeline(&self.fullscreen_pipeline);
-            fullscreen_pass.set_bind_group(0, &self.fullscreen_bind_group, &[]);
-            fullscreen_pass.set_bind_group(1, &self.camera_bind_group, &[]);
-            fullscreen_pass.set_bind_group(2, &self.utils_bind_group, &[]);
+            if let (Mode::Flame, Some(pipeline), Some(flame_pass)) =
+                (self.fractal.mode, &self.flame_pipeline, &self.flame_pass)
+            {
+                fullscreen_pass.set_pipeline(pipeline);
+                fullscreen_pass.set_bind_group(0, &flame_pass.render_bind_group, &[]);
+            } else {
+                let (_, pipeline) = self
+                    .fullscreen_pipelines
+                    .iter()
+                    .find(|(precision, _)| *precision == self.precision)
+                    .unwrap();
+                fullscreen_pass.set_pipeline(pipeline);
+                fullscreen_pass.set_bind_group(0, &self.fullscreen_bind_group, &[]);
+                fullscreen_pass.set_bind_group(1, &self.camera_bind_group, &[]);
+                fullscreen_pass.set_bind_group(2, &self.utils_bind_group, &[]);
+                if let Some(buddhabrot_pass) = &self.buddhabrot_pass {
+                    fullscreen_pass.set_bind_group(3, &buddhabrot_pass.render_bind_group, &[]);
+                }
+            }
             fullscreen_pass.set_vertex_buffer(0, self.fullscreen_vertex_buffer.slice(..));
             fullscreen_pass.set_index_buffer(
                 self.fullscreen_index_buffer.slice(..),
                 wgpu::IndexFormat::Uint16,
             );
             fullscreen_pass.draw_indexed(0..FULLSCREEN_INDICES.len() as u32, 0, 0..1);
+            if self.fractal.mode == Mode::EscapeTime {
+                self.ray_overlay.draw(&mut fullscreen_pass);
+            }
         }
 
+        if counting_iterations {
+            self.iteration_counter
+                .copy(&mut encoder, self.fractal.iterations.limit);
+        }
         self.queue.submit(std::iter::once(encoder.finish()));
+        self.iteration_counter.submitted();
         output.present();
         self.frame_count += 1.0;
         Ok(())
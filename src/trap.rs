@@ -0,0 +1,373 @@
+use anyhow::{anyhow, Context, Result};
+use cgmath::Point2;
+
+/// The side of the square texture that images are resampled to, so that loading another one
+/// doesn't need a new bind group.
+const IMAGE_SIZE: u32 = 256;
+/// Larger images are refused rather than read into memory only to be shrunk.
+const MAX_IMAGE_SIZE: u32 = 16384;
+
+/// The shape orbits are measured against, matching `trap_distance` in fullscreen.wgsl. The
+/// shapes are defined in the trap's own frame, where its scale is 1.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TrapShape {
+    Point,
+    /// The x axis of the trap's frame.
+    Line,
+    /// Both axes of the trap's frame.
+    Cross,
+    /// The unit circle.
+    Circle,
+    /// The image loaded into [`TrapImage`], covering the square from -1 to 1. Its transparent
+    /// parts are far from the trap, so the orbit picks up the colour of the first opaque pixel
+    /// it lands on.
+    Image,
+}
+
+impl TrapShape {
+    const ALL: [TrapShape; 5] = [
+        TrapShape::Point,
+        TrapShape::Line,
+        TrapShape::Cross,
+        TrapShape::Circle,
+        TrapShape::Image,
+    ];
+
+    pub fn next(self) -> Self {
+        Self::ALL[(self as usize + 1) % Self::ALL.len()]
+    }
+
+    pub fn parse(name: &str) -> Result<Self> {
+        match name.trim() {
+            "point" => Ok(TrapShape::Point),
+            "line" => Ok(TrapShape::Line),
+            "cross" => Ok(TrapShape::Cross),
+            "circle" => Ok(TrapShape::Circle),
+            "image" => Ok(TrapShape::Image),
+            _ => Err(anyhow!(
+                "unknown trap {:?}, expected point, line, cross, circle or image",
+                name
+            )),
+        }
+    }
+}
+
+/// Where the trap sits on the complex plane, for [`crate::fractal::Colouring::Trap`].
+#[derive(Debug)]
+pub struct OrbitTrap {
+    pub shape: TrapShape,
+    pub position: Point2<f32>,
+    pub scale: f32,
+    /// Anticlockwise, in radians.
+    pub rotation: f32,
+}
+
+impl OrbitTrap {
+    pub fn new() -> Self {
+        Self {
+            shape: TrapShape::Point,
+            position: Point2::new(0.0, 0.0),
+            scale: 1.0,
+            rotation: 0.0,
+        }
+    }
+
+    pub fn set_position(&mut self, arguments: &str) -> Result<()> {
+        let coordinates = arguments
+            .split_whitespace()
+            .map(str::parse)
+            .collect::<Result<Vec<f32>, _>>()
+            .map_err(|_| anyhow!("invalid trap position {:?}", arguments))?;
+        let [x, y] = coordinates[..] else {
+            return Err(anyhow!("expected \"<re> <im>\", got {:?}", arguments));
+        };
+        self.position = Point2::new(x, y);
+        Ok(())
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct TrapUniform {
+    pub position: [f32; 2],
+    /// The cosine and sine of the rotation.
+    pub rotation: [f32; 2],
+    pub scale: f32,
+    pub shape: u32,
+}
+
+impl TrapUniform {
+    pub fn new() -> Self {
+        Self {
+            position: [0.0; 2],
+            rotation: [1.0, 0.0],
+            scale: 1.0,
+            shape: 0,
+        }
+    }
+
+    pub fn update(&mut self, trap: &OrbitTrap) {
+        self.position = trap.position.into();
+        self.rotation = [trap.rotation.cos(), trap.rotation.sin()];
+        self.scale = trap.scale;
+        self.shape = trap.shape as u32;
+    }
+}
+
+/// The texture sampled by image traps. It starts out with a built-in rosette, and can be
+/// replaced from a binary PPM or PGM file. The texture is sRGB like the files, so the shader
+/// samples it in linear light.
+#[derive(Debug)]
+pub struct TrapImage {
+    texture: wgpu::Texture,
+    pub view: wgpu::TextureView,
+    pub sampler: wgpu::Sampler,
+}
+
+impl TrapImage {
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue) -> Self {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("trap_image_texture"),
+            size: wgpu::Extent3d {
+                width: IMAGE_SIZE,
+                height: IMAGE_SIZE,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba8UnormSrgb,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("trap_image_sampler"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+        let image = Self {
+            texture,
+            view,
+            sampler,
+        };
+        image.write(queue, &rosette());
+        image
+    }
+
+    /// Replaces the image with a binary PPM or PGM file, resampled to the texture's size.
+    pub fn load(&self, queue: &wgpu::Queue, path: &str) -> Result<()> {
+        let bytes = std::fs::read(path).with_context(|| format!("reading {}", path))?;
+        let (width, height, pixels) =
+            parse_netpbm(&bytes).with_context(|| format!("parsing {}", path))?;
+        self.write(queue, &resample(width, height, &pixels));
+        Ok(())
+    }
+
+    fn write(&self, queue: &wgpu::Queue, pixels: &[[u8; 4]]) {
+        queue.write_texture(
+            wgpu::ImageCopyTexture {
+                texture: &self.texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            bytemuck::cast_slice(pixels),
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(4 * IMAGE_SIZE),
+                rows_per_image: Some(IMAGE_SIZE),
+            },
+            self.texture.size(),
+        );
+    }
+}
+
+/// A five petalled flower on a transparent background, coloured round by angle.
+fn rosette() -> Vec<[u8; 4]> {
+    (0..IMAGE_SIZE * IMAGE_SIZE)
+        .map(|i| {
+            let x = 2.0 * ((i % IMAGE_SIZE) as f32 + 0.5) / IMAGE_SIZE as f32 - 1.0;
+            let y = 1.0 - 2.0 * ((i / IMAGE_SIZE) as f32 + 0.5) / IMAGE_SIZE as f32;
+            let (radius, angle) = (x.hypot(y), y.atan2(x));
+            let edge = 0.6 + 0.3 * (5.0 * angle).cos();
+            if radius > edge {
+                return [0; 4];
+            }
+            let shade = 1.0 - 0.6 * radius / edge;
+            let channel = |offset: f32| {
+                let hue = 0.5 + 0.5 * (angle + offset).cos();
+                (255.0 * shade * hue) as u8
+            };
+            [channel(0.0), channel(2.094), channel(4.189), 255]
+        })
+        .collect()
+}
+
+/// Picks the nearest of an image's pixels for each texel. The arithmetic is done in u64, where
+/// it can't overflow for images within [`MAX_IMAGE_SIZE`].
+fn resample(width: u32, height: u32, pixels: &[[u8; 4]]) -> Vec<[u8; 4]> {
+    let (width, height, size) = (width as u64, height as u64, IMAGE_SIZE as u64);
+    (0..size * size)
+        .map(|i| {
+            let x = (i % size) * width / size;
+            let y = (i / size) * height / size;
+            pixels[(y * width + x) as usize]
+        })
+        .collect()
+}
+
+/// Parses the header and pixels of a binary PPM (P6) or PGM (P5) with up to 8 bits per
+/// channel. The pixels are opaque.
+fn parse_netpbm(bytes: &[u8]) -> Result<(u32, u32, Vec<[u8; 4]>)> {
+    let mut position = 0;
+    let mut fields = Vec::new();
+    while fields.len() < 4 {
+        match bytes.get(position) {
+            None => return Err(anyhow!("the header is cut short")),
+            Some(b'#') => {
+                while bytes.get(position).is_some_and(|&byte| byte != b'\n') {
+                    position += 1;
+                }
+            }
+            Some(byte) if byte.is_ascii_whitespace() => position += 1,
+            Some(_) => {
+                let start = position;
+                while bytes
+                    .get(position)
+                    .is_some_and(|byte| !byte.is_ascii_whitespace())
+                {
+                    position += 1;
+                }
+                fields.push(std::str::from_utf8(&bytes[start..position])?);
+            }
+        }
+    }
+    // A single whitespace character separates the header from the pixels
+    position += 1;
+
+    let channels = match fields[0] {
+        "P5" => 1,
+        "P6" => 3,
+        magic => return Err(anyhow!("expected a binary PPM or PGM, found {:?}", magic)),
+    };
+    let number = |field: &str| {
+        field
+            .parse::<u32>()
+            .map_err(|_| anyhow!("invalid number {:?} in the header", field))
+    };
+    let (width, height, max_value) = (number(fields[1])?, number(fields[2])?, number(fields[3])?);
+    if width == 0 || height == 0 || !(1..=255).contains(&max_value) {
+        return Err(anyhow!("only 8 bit images with pixels are supported"));
+    }
+    if width > MAX_IMAGE_SIZE || height > MAX_IMAGE_SIZE {
+        return Err(anyhow!(
+            "images can be at most {} pixels across, this is {}x{}",
+            MAX_IMAGE_SIZE,
+            width,
+            height
+        ));
+    }
+    let data = (width as usize)
+        .checked_mul(height as usize)
+        .and_then(|pixels| pixels.checked_mul(channels))
+        .and_then(|length| length.checked_add(position))
+        .and_then(|end| bytes.get(position..end))
+        .ok_or_else(|| anyhow!("the pixels are cut short"))?;
+    // Values above the maximum are out of spec, and taken as the maximum
+    let scale = |value: u8| ((value as u32).min(max_value) * 255 / max_value) as u8;
+    let pixels = data
+        .chunks_exact(channels)
+        .map(|pixel| match *pixel {
+            [grey] => [scale(grey), scale(grey), scale(grey), 255],
+            [r, g, b] => [scale(r), scale(g), scale(b), 255],
+            _ => unreachable!(),
+        })
+        .collect();
+    Ok((width, height, pixels))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn netpbm(header: &str, pixels: &[u8]) -> Vec<u8> {
+        let mut bytes = header.as_bytes().to_vec();
+        bytes.extend_from_slice(pixels);
+        bytes
+    }
+
+    #[test]
+    fn ppm_pixels_are_opaque() {
+        let bytes = netpbm("P6 2 1 255\n", &[255, 0, 0, 1, 2, 3]);
+        let (width, height, pixels) = parse_netpbm(&bytes).unwrap();
+        assert_eq!((width, height), (2, 1));
+        assert_eq!(pixels, [[255, 0, 0, 255], [1, 2, 3, 255]]);
+    }
+
+    #[test]
+    fn pgm_pixels_are_grey() {
+        let bytes = netpbm("P5\n1 2\n255\n", &[7, 200]);
+        let (_, _, pixels) = parse_netpbm(&bytes).unwrap();
+        assert_eq!(pixels, [[7, 7, 7, 255], [200, 200, 200, 255]]);
+    }
+
+    #[test]
+    fn comments_are_skipped() {
+        let bytes = netpbm("P5 # made by hand\n# size\n1 1\n#depth\n255\n", &[9]);
+        let (_, _, pixels) = parse_netpbm(&bytes).unwrap();
+        assert_eq!(pixels, [[9, 9, 9, 255]]);
+    }
+
+    #[test]
+    fn values_are_scaled_to_the_maximum() {
+        let bytes = netpbm("P5 4 1 15\n", &[0, 5, 15, 20]);
+        let (_, _, pixels) = parse_netpbm(&bytes).unwrap();
+        let greys: Vec<_> = pixels.iter().map(|pixel| pixel[0]).collect();
+        assert_eq!(greys, [0, 85, 255, 255]);
+    }
+
+    #[test]
+    fn truncated_files_are_rejected() {
+        assert!(parse_netpbm(b"P6 2 1").is_err());
+        assert!(parse_netpbm(&netpbm("P6 2 1 255\n", &[1, 2, 3, 4, 5])).is_err());
+        assert!(parse_netpbm(&netpbm("P5 1 1 255\n", &[])).is_err());
+    }
+
+    #[test]
+    fn unsupported_images_are_rejected() {
+        assert!(parse_netpbm(b"P3 1 1 255\n0 0 0").is_err());
+        assert!(parse_netpbm(&netpbm("P5 1 1 65535\n", &[0, 0])).is_err());
+        assert!(parse_netpbm(&netpbm("P5 0 1 255\n", &[])).is_err());
+        assert!(parse_netpbm(&netpbm("P5 x 1 255\n", &[0])).is_err());
+    }
+
+    #[test]
+    fn oversized_images_are_rejected() {
+        // Far too few bytes either way, but the size must be refused before it is multiplied
+        assert!(parse_netpbm(b"P6 4294967295 4294967295 255\n").is_err());
+        let error = parse_netpbm(b"P5 16385 1 255\n").unwrap_err();
+        assert!(error.to_string().contains("at most"), "{}", error);
+    }
+
+    #[test]
+    fn resampling_picks_the_nearest_pixel() {
+        let pixels = [
+            [1, 0, 0, 255],
+            [2, 0, 0, 255],
+            [3, 0, 0, 255],
+            [4, 0, 0, 255],
+        ];
+        let resampled = resample(2, 2, &pixels);
+        let size = IMAGE_SIZE as usize;
+        assert_eq!(resampled.len(), size * size);
+        assert_eq!(resampled[0], pixels[0]);
+        assert_eq!(resampled[size - 1], pixels[1]);
+        assert_eq!(resampled[size * (size - 1)], pixels[2]);
+        assert_eq!(resampled[size * size - 1], pixels[3]);
+        // The widest image still lands inside it
+        let wide = vec![[0; 4]; MAX_IMAGE_SIZE as usize];
+        assert_eq!(resample(MAX_IMAGE_SIZE, 1, &wide).len(), size * size);
+    }
+}
@@ -0,0 +1,562 @@
+use std::collections::HashMap;
+use std::fmt::Write;
+
+use anyhow::{anyhow, Result};
+
+use crate::precision::Precision;
+
+/// The largest exponent that real powers are expanded into multiplications for.
+const MAX_INTEGER_EXPONENT: i32 = 64;
+
+/// The functions a formula can call, all taking a single argument.
+const FUNCTIONS: [&str; 15] = [
+    "sin", "cos", "tan", "sinh", "cosh", "tanh", "exp", "log", "sqrt", "conj", "abs", "re", "im",
+    "cabs", "arg",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Type {
+    Real,
+    Complex,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(f64),
+    Identifier(String),
+    Symbol(char),
+    End,
+}
+
+/// A piece of generated WGSL along with the type of value it produces.
+#[derive(Debug, Clone)]
+struct Typed {
+    code: String,
+    ty: Type,
+}
+
+impl Typed {
+    fn real(code: String) -> Self {
+        Self {
+            code,
+            ty: Type::Real,
+        }
+    }
+
+    fn complex(code: String) -> Self {
+        Self {
+            code,
+            ty: Type::Complex,
+        }
+    }
+
+    /// The value as a `vec2<real>`, promoting reals onto the real axis.
+    fn as_complex(&self) -> String {
+        match self.ty {
+            Type::Real => format!("vec2<real>({}, real(0.0))", self.code),
+            Type::Complex => self.code.clone(),
+        }
+    }
+}
+
+/// Splits the source into tokens, each paired with the column it starts at.
+fn tokenise(source: &str) -> Result<Vec<(usize, Token)>> {
+    let chars = source.chars().collect::<Vec<_>>();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let start = i;
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+            continue;
+        }
+
+        let token = if c.is_ascii_digit() || c == '.' {
+            while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.') {
+                i += 1;
+            }
+            // Only treat an e as an exponent when digits follow
+            let exponent_digits = |j: usize| chars.get(j).is_some_and(char::is_ascii_digit);
+            if i < chars.len() && (chars[i] == 'e' || chars[i] == 'E') {
+                if exponent_digits(i + 1) {
+                    i += 1;
+                } else if matches!(chars.get(i + 1), Some('+' | '-')) && exponent_digits(i + 2) {
+                    i += 2;
+                }
+                while i < chars.len() && chars[i].is_ascii_digit() {
+                    i += 1;
+                }
+            }
+            let text = chars[start..i].iter().collect::<String>();
+            let value = text
+                .parse()
+                .map_err(|_| anyhow!("invalid number {:?} at column {}", text, start + 1))?;
+            Token::Number(value)
+        } else if c.is_alphabetic() || c == '_' {
+            while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            Token::Identifier(chars[start..i].iter().collect())
+        } else if "+-*/^()=;".contains(c) {
+            i += 1;
+            Token::Symbol(c)
+        } else {
+            return Err(anyhow!("unexpected {:?} at column {}", c, start + 1));
+        };
+        tokens.push((start + 1, token));
+    }
+    tokens.push((chars.len() + 1, Token::End));
+    Ok(tokens)
+}
+
+/// Parses, type checks and translates a formula in a single pass.
+struct Compiler {
+    tokens: Vec<(usize, Token)>,
+    position: usize,
+    variables: HashMap<String, Type>,
+    body: String,
+}
+
+impl Compiler {
+    fn peek(&self) -> &Token {
+        &self.tokens[self.position].1
+    }
+
+    fn next(&mut self) -> Token {
+        let token = self.tokens[self.position].1.clone();
+        if token != Token::End {
+            self.position += 1;
+        }
+        token
+    }
+
+    fn error(&self, message: &str) -> anyhow::Error {
+        let (column, token) = &self.tokens[self.position];
+        match token {
+            Token::End => anyhow!("{} at the end of the formula", message),
+            _ => anyhow!("{} at column {}", message, column),
+        }
+    }
+
+    fn expect(&mut self, symbol: char) -> Result<()> {
+        if *self.peek() != Token::Symbol(symbol) {
+            return Err(self.error(&format!("expected '{}'", symbol)));
+        }
+        self.next();
+        Ok(())
+    }
+
+    fn eat(&mut self, symbol: char) -> bool {
+        let found = *self.peek() == Token::Symbol(symbol);
+        if found {
+            self.next();
+        }
+        found
+    }
+
+    /// statements := statement (';' statement)*
+    fn statements(&mut self) -> Result<()> {
+        loop {
+            if *self.peek() != Token::End && *self.peek() != Token::Symbol(';') {
+                self.statement()?;
+            }
+            if *self.peek() == Token::End {
+                return Ok(());
+            }
+            self.expect(';')?;
+        }
+    }
+
+    /// statement := name '=' expression | expression, where a bare expression is assigned to z
+    fn statement(&mut self) -> Result<()> {
+        let assignment = match (self.peek(), &self.tokens[self.position + 1].1) {
+            (Token::Identifier(name), Token::Symbol('=')) => Some(name.clone()),
+            _ => None,
+        };
+        let name = match assignment {
+            Some(name) => {
+                if name == "c" || name == "i" || name == "pi" || name == "e" {
+                    return Err(self.error(&format!("{} can't be assigned to", name)));
+                }
+                if FUNCTIONS.contains(&name.as_str()) {
+                    return Err(self.error(&format!("{} is a function", name)));
+                }
+                self.next();
+                self.next();
+                name
+            }
+            None => "z".to_string(),
+        };
+
+        let value = self.expression()?;
+        match self.variables.get(&name) {
+            None => {
+                writeln!(self.body, "    var v_{} = {};", name, value.code)?;
+                self.variables.insert(name, value.ty);
+            }
+            Some(Type::Complex) => writeln!(
+                self.body,
+                "    {} = {};",
+                variable(&name),
+                value.as_complex()
+            )?,
+            Some(Type::Real) if value.ty == Type::Real => {
+                writeln!(self.body, "    {} = {};", variable(&name), value.code)?
+            }
+            Some(Type::Real) => {
+                return Err(anyhow!("{} is real but was assigned a complex value", name))
+            }
+        }
+        Ok(())
+    }
+
+    /// expression := term (('+' | '-') term)*
+    fn expression(&mut self) -> Result<Typed> {
+        let mut left = self.term()?;
+        loop {
+            let operator = match self.peek() {
+                Token::Symbol(operator @ ('+' | '-')) => *operator,
+                _ => return Ok(left),
+            };
+            self.next();
+            let right = self.term()?;
+            left = match (left.ty, right.ty) {
+                (Type::Real, Type::Real) => {
+                    Typed::real(format!("({} {} {})", left.code, operator, right.code))
+                }
+                _ => Typed::complex(format!(
+                    "({} {} {})",
+                    left.as_complex(),
+                    operator,
+                    right.as_complex()
+                )),
+            };
+        }
+    }
+
+    /// term := unary (('*' | '/') unary)*
+    fn term(&mut self) -> Result<Typed> {
+        let mut left = self.unary()?;
+        loop {
+            let operator = match self.peek() {
+                Token::Symbol(operator @ ('*' | '/')) => *operator,
+                _ => return Ok(left),
+            };
+            self.next();
+            let right = self.unary()?;
+            left = match (left.ty, right.ty, operator) {
+                (Type::Real, Type::Real, _) => {
+                    Typed::real(format!("({} {} {})", left.code, operator, right.code))
+                }
+                // Scaling by a real is plain vector arithmetic
+                (_, Type::Real, _) | (Type::Real, _, '*') => {
+                    Typed::complex(format!("({} {} {})", left.code, operator, right.code))
+                }
+                (_, _, '*') => {
+                    Typed::complex(format!("complex_mul({}, {})", left.code, right.code))
+                }
+                _ => Typed::complex(format!(
+                    "complex_div({}, {})",
+                    left.as_complex(),
+                    right.code
+                )),
+            };
+        }
+    }
+
+    /// unary := '-' unary | power
+    fn unary(&mut self) -> Result<Typed> {
+        if self.eat('-') {
+            let value = self.unary()?;
+            return Ok(Typed {
+                code: format!("(-{})", value.code),
+                ty: value.ty,
+            });
+        }
+        self.power()
+    }
+
+    /// An exponent that is a whole number, possibly negated, and not itself raised to a power.
+    /// It is consumed if found.
+    fn integer_exponent(&mut self) -> Option<i32> {
+        let negative = *self.peek() == Token::Symbol('-');
+        let start = self.position + negative as usize;
+        let Token::Number(value) = self.tokens[start].1 else {
+            return None;
+        };
+        let next = &self.tokens[(start + 1).min(self.tokens.len() - 1)].1;
+        if value.fract() != 0.0
+            || value > MAX_INTEGER_EXPONENT as f64
+            || *next == Token::Symbol('^')
+        {
+            return None;
+        }
+        self.position = start + 1;
+        Some(if negative { -value } else { value } as i32)
+    }
+
+    /// power := atom ('^' unary)?, which makes powers right associative and binds -z^2 as -(z^2)
+    fn power(&mut self) -> Result<Typed> {
+        let base = self.atom()?;
+        if !self.eat('^') {
+            return Ok(base);
+        }
+        if base.ty == Type::Real {
+            if let Some(exponent) = self.integer_exponent() {
+                return Ok(Typed::real(format!(
+                    "real_powi({}, {})",
+                    base.code, exponent
+                )));
+            }
+        }
+        let exponent = self.unary()?;
+        Ok(match (base.ty, exponent.ty) {
+            // A negative real raised to a fractional power is complex
+            (Type::Real, Type::Real) => Typed::complex(format!(
+                "complex_pow({}, narrow({}))",
+                base.as_complex(),
+                exponent.code
+            )),
+            (Type::Complex, Type::Real) => Typed::complex(format!(
+                "complex_pow({}, narrow({}))",
+                base.code, exponent.code
+            )),
+            _ => Typed::complex(format!(
+                "complex_exp(complex_mul({}, complex_log({})))",
+                exponent.as_complex(),
+                base.as_complex()
+            )),
+        })
+    }
+
+    /// atom := number | name | function '(' expression ')' | '(' expression ')'
+    fn atom(&mut self) -> Result<Typed> {
+        match self.peek().clone() {
+            Token::Number(value) => {
+                self.next();
+                Ok(Typed::real(format!("real({:?})", value)))
+            }
+            Token::Symbol('(') => {
+                self.next();
+                let value = self.expression()?;
+                self.expect(')')?;
+                Ok(value)
+            }
+            Token::Identifier(name) if FUNCTIONS.contains(&name.as_str()) => {
+                self.next();
+                self.expect('(')?;
+                let argument = self.expression()?;
+                self.expect(')')?;
+                Ok(call(&name, argument))
+            }
+            Token::Identifier(name) => {
+                let value = match name.as_str() {
+                    "i" => Typed::complex("vec2<real>(real(0.0), real(1.0))".to_string()),
+                    "pi" => Typed::real(format!("real({:?})", std::f64::consts::PI)),
+                    "e" => Typed::real(format!("real({:?})", std::f64::consts::E)),
+                    _ => match self.variables.get(&name) {
+                        Some(&ty) => Typed {
+                            code: variable(&name),
+                            ty,
+                        },
+                        None => return Err(self.error(&format!("unknown variable {}", name))),
+                    },
+                };
+                self.next();
+                Ok(value)
+            }
+            _ => Err(self.error("expected a value")),
+        }
+    }
+}
+
+/// The WGSL name of a variable. Locals are prefixed so they can't clash with WGSL keywords.
+fn variable(name: &str) -> String {
+    match name {
+        "z" | "c" => name.to_string(),
+        _ => format!("v_{}", name),
+    }
+}
+
+/// Translates a call to one of [`FUNCTIONS`].
+fn call(name: &str, argument: Typed) -> Typed {
+    match (name, argument.ty) {
+        ("abs", _) => Typed {
+            code: format!("abs({})", argument.code),
+            ty: argument.ty,
+        },
+        ("re", Type::Real) => argument,
+        ("re", Type::Complex) => Typed::real(format!("{}.x", argument.code)),
+        ("im", Type::Real) => Typed::real("real(0.0)".to_string()),
+        ("im", Type::Complex) => Typed::real(format!("{}.y", argument.code)),
+        ("cabs", Type::Real) => Typed::real(format!("abs({})", argument.code)),
+        ("cabs" | "arg", _) => Typed::real(format!("complex_{}({})", name, argument.as_complex())),
+        _ => Typed::complex(format!("complex_{}({})", name, argument.as_complex())),
+    }
+}
+
+/// An iteration written in a small formula language, such as `z = z^3 + c*sin(z)`, and its
+/// translation into the WGSL function `user_formula`.
+///
+/// A formula is a list of assignments separated by semicolons. z starts as the current value and
+/// its final value is the next one, c is the constant, and other names become local variables,
+/// which are complex or real depending on the first value they are given. A bare expression is
+/// assigned to z. The functions are those in [`FUNCTIONS`], where `cabs` is the modulus and
+/// `abs` takes the absolute value of each part. A real raised to a whole number stays real, and
+/// to any other power is complex, since the base may be negative.
+#[derive(Debug, Clone)]
+pub struct UserFormula {
+    pub source: String,
+    wgsl: String,
+}
+
+impl UserFormula {
+    pub fn new() -> Self {
+        Self::compile("z = z^2 + c").unwrap()
+    }
+
+    pub fn compile(source: &str) -> Result<Self> {
+        let mut compiler = Compiler {
+            tokens: tokenise(source)?,
+            position: 0,
+            variables: HashMap::from([
+                ("z".to_string(), Type::Complex),
+                ("c".to_string(), Type::Complex),
+            ]),
+            body: String::new(),
+        };
+        compiler.statements()?;
+        let wgsl = format!(
+            "fn user_formula(current: vec2<real>, c: vec2<real>) -> vec2<real> {{\n    var z = current;\n{}    return z;\n}}\n",
+            compiler.body
+        );
+        Ok(Self {
+            source: source.trim().to_string(),
+            wgsl,
+        })
+    }
+
+    /// The source of `fullscreen.wgsl` at the given precision, with the double-single
+    /// functions, the prelude and this formula appended.
+    pub fn shader_source(&self, precision: Precision) -> String {
+        let source = format!(
+            "{}\n{}\n{}\n{}",
+            include_str!("fullscreen.wgsl"),
+            include_str!("double_single.wgsl"),
+            include_str!("user_formula.wgsl"),
+            self.wgsl
+        );
+        precision.shader_source(&source)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn body(source: &str) -> String {
+        let formula = UserFormula::compile(source).unwrap();
+        let start = formula.wgsl.find('{').unwrap() + 1;
+        formula.wgsl[start..]
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && *line != "var z = current;" && *line != "}")
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    fn error(source: &str) -> String {
+        UserFormula::compile(source).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn tokenises_numbers_and_columns() {
+        let tokens = tokenise("z^2.5e-1 + 3e").unwrap();
+        assert_eq!(
+            tokens,
+            vec![
+                (1, Token::Identifier("z".to_string())),
+                (2, Token::Symbol('^')),
+                (3, Token::Number(0.25)),
+                (10, Token::Symbol('+')),
+                (12, Token::Number(3.0)),
+                (13, Token::Identifier("e".to_string())),
+                (14, Token::End),
+            ]
+        );
+    }
+
+    #[test]
+    fn multiplication_binds_tighter_than_addition() {
+        assert_eq!(
+            body("z = 1 + 2 * 3"),
+            "z = vec2<real>((real(1.0) + (real(2.0) * real(3.0))), real(0.0)); return z;"
+        );
+    }
+
+    #[test]
+    fn negation_applies_after_powers() {
+        assert_eq!(
+            body("-z^2"),
+            "z = (-complex_pow(z, narrow(real(2.0)))); return z;"
+        );
+    }
+
+    #[test]
+    fn powers_are_right_associative() {
+        assert_eq!(
+            body("z = z^2^3"),
+            "z = complex_pow(z, narrow(real_powi(real(2.0), 3))); return z;"
+        );
+    }
+
+    #[test]
+    fn real_powers_are_multiplied_out() {
+        assert_eq!(
+            body("x = re(z)^2 - im(z)^-2; z = z + x"),
+            "var v_x = (real_powi(z.x, 2) - real_powi(z.y, -2)); \
+             z = (z + vec2<real>(v_x, real(0.0))); return z;"
+        );
+    }
+
+    #[test]
+    fn fractional_real_powers_are_complex() {
+        assert_eq!(
+            body("x = re(z)^0.5"),
+            "var v_x = complex_pow(vec2<real>(z.x, real(0.0)), narrow(real(0.5))); return z;"
+        );
+        assert_eq!(
+            error("x = re(z); x = re(z)^0.5"),
+            "x is real but was assigned a complex value"
+        );
+    }
+
+    #[test]
+    fn variables_take_the_type_of_their_first_value() {
+        assert_eq!(
+            body("x = cabs(z); w = x * z; z = w"),
+            "var v_x = complex_cabs(z); var v_w = (v_x * z); z = v_w; return z;"
+        );
+        assert_eq!(
+            error("x = im(z); x = z"),
+            "x is real but was assigned a complex value"
+        );
+    }
+
+    #[test]
+    fn errors_give_the_column() {
+        assert_eq!(error("z = z + $"), "unexpected '$' at column 9");
+        assert_eq!(error("z = sin z"), "expected '(' at column 9");
+        assert_eq!(error("z = z + q"), "unknown variable q at column 9");
+        assert_eq!(error("c = z"), "c can't be assigned to at column 1");
+        assert_eq!(
+            error("z = (z + c"),
+            "expected ')' at the end of the formula"
+        );
+        assert_eq!(
+            error("z = z +"),
+            "expected a value at the end of the formula"
+        );
+    }
+}
@@ -0,0 +1,78 @@
+// The complex functions available to user formulas. WGSL has no f64 transcendentals, so these
+// work in f32 like the polar form of `complex_pow`.
+
+// Converts to f32 in a function so that constant formulas aren't evaluated at compile time,
+// which doesn't support narrowing f64.
+fn narrow(x: real) -> f32 {
+    return f32(x);
+}
+
+// x^power by repeated multiplication, for integer exponents known when the formula is compiled.
+// pow is undefined for negative x.
+fn real_powi(x: real, power: i32) -> real {
+    var result = real(1.0);
+    for (var i = 0; i < abs(power); i++) {
+        result *= x;
+    }
+    if power < 0 {
+        return real(1.0) / result;
+    }
+    return result;
+}
+
+fn complex_exp(z: vec2<real>) -> vec2<real> {
+    let w = vec2<f32>(z);
+    return vec2<real>(exp(w.x) * vec2<f32>(cos(w.y), sin(w.y)));
+}
+
+fn complex_log(z: vec2<real>) -> vec2<real> {
+    let w = vec2<f32>(z);
+    return vec2<real>(vec2<f32>(log(length(w)), atan2(w.y, w.x)));
+}
+
+fn complex_sqrt(z: vec2<real>) -> vec2<real> {
+    let w = vec2<f32>(z);
+    let radius = sqrt(length(w));
+    let angle = atan2(w.y, w.x) * 0.5;
+    return vec2<real>(radius * vec2<f32>(cos(angle), sin(angle)));
+}
+
+fn complex_sin(z: vec2<real>) -> vec2<real> {
+    let w = vec2<f32>(z);
+    return vec2<real>(vec2<f32>(sin(w.x) * cosh(w.y), cos(w.x) * sinh(w.y)));
+}
+
+fn complex_cos(z: vec2<real>) -> vec2<real> {
+    let w = vec2<f32>(z);
+    return vec2<real>(vec2<f32>(cos(w.x) * cosh(w.y), -sin(w.x) * sinh(w.y)));
+}
+
+fn complex_tan(z: vec2<real>) -> vec2<real> {
+    return complex_div(complex_sin(z), complex_cos(z));
+}
+
+fn complex_sinh(z: vec2<real>) -> vec2<real> {
+    let w = vec2<f32>(z);
+    return vec2<real>(vec2<f32>(sinh(w.x) * cos(w.y), cosh(w.x) * sin(w.y)));
+}
+
+fn complex_cosh(z: vec2<real>) -> vec2<real> {
+    let w = vec2<f32>(z);
+    return vec2<real>(vec2<f32>(cosh(w.x) * cos(w.y), sinh(w.x) * sin(w.y)));
+}
+
+fn complex_tanh(z: vec2<real>) -> vec2<real> {
+    return complex_div(complex_sinh(z), complex_cosh(z));
+}
+
+fn complex_conj(z: vec2<real>) -> vec2<real> {
+    return vec2<real>(z.x, -z.y);
+}
+
+fn complex_cabs(z: vec2<real>) -> real {
+    return sqrt(z.x * z.x + z.y * z.y);
+}
+
+fn complex_arg(z: vec2<real>) -> real {
+    return real(atan2(f32(z.y), f32(z.x)));
+}